@@ -0,0 +1,83 @@
+use crate::ray::Ray;
+use crate::vec::{Point3, Vec3};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn centered(center: Point3, half_extents: Vec3) -> Aabb {
+        Aabb::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn center(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.max(&other.min), self.max.min(&other.max))
+    }
+
+    pub fn expand(&self, delta: f32) -> Aabb {
+        let delta = Vec3::new(delta, delta, delta);
+        Aabb::new(self.min - delta, self.max + delta)
+    }
+
+    pub fn translate(&self, offset: &Vec3) -> Aabb {
+        Aabb::new(self.min + *offset, self.max + *offset)
+    }
+
+    /// The center and radius of the smallest sphere around the box.
+    pub fn bounding_sphere(&self) -> (Point3, f32) {
+        let center = self.center();
+        (center, (self.max - center).len())
+    }
+
+    pub fn corners(&self) -> [Point3; 8] {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            *corner = Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+        }
+        corners
+    }
+
+    /// Returns the parametric interval over which the ray is inside the box, clipped to
+    /// `[t_min, t_max]`.
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut near = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut far = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaNs from 0 * inf fall through these comparisons and leave the interval as-is
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
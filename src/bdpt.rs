@@ -0,0 +1,676 @@
+//! Bidirectional path tracing after Veach, "Robust Monte Carlo Methods for Light Transport
+//! Simulation", in the form given by pbrt. A path is traced from the camera and another from a
+//! light, and every vertex of one is joined to every vertex of the other. Each way of building
+//! a path is weighted against the others that could have built it, so that light which is hard
+//! to find from the camera, such as caustics through glass, comes from the light's side.
+
+use crate::hittable::HitRecord;
+use crate::integrator::{self, Integrator, Splats, MIN_ROULETTE_DEPTH};
+use crate::light::Light;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampling;
+use crate::scene::Scene;
+use crate::vec::{Color, Point3, Vec3};
+
+use rand::{Rng, RngCore};
+
+enum Kind<'a> {
+    Camera,
+    /// A point on the light at the given index among the scene's.
+    Light(usize, &'a dyn Light),
+    /// A point on a surface or in a volume, where `wo` points back along the subpath.
+    Surface {
+        hit: HitRecord,
+        frame: Onb,
+        wo: Vec3,
+    },
+    /// Where a camera path escapes the scene in the given direction, towards the sky and any
+    /// lights at infinity.
+    Escaped(Vec3),
+}
+
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    p: Point3,
+    /// The true normal of vertices on surfaces, which densities by area depend on.
+    normal: Option<Vec3>,
+    /// The subpath's contribution up to this vertex, with its sampling densities divided out.
+    throughput: Color,
+    /// Whether the subpath went on from here by a delta lobe, such as a mirror's.
+    delta: bool,
+    /// Densities by area of picking this vertex from the one before it on its subpath, and
+    /// from the one after it, as though the path had been traced the other way. Escaped
+    /// vertices keep their densities by solid angle.
+    pdf_fwd: f32,
+    pdf_rev: f32,
+}
+
+impl<'a> Vertex<'a> {
+    fn new(kind: Kind<'a>, p: Point3, normal: Option<Vec3>, throughput: Color) -> Vertex<'a> {
+        Vertex {
+            kind,
+            p,
+            normal,
+            throughput,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_surface(&self) -> bool {
+        matches!(self.kind, Kind::Surface { .. })
+    }
+
+    fn direction_to(&self, p: &Point3) -> Vec3 {
+        (*p - self.p).unit_vector()
+    }
+
+    /// Turns a density by solid angle of picking the direction from here towards `next` into
+    /// a density by area at `next`.
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        if let Kind::Escaped(_) = next.kind {
+            return pdf;
+        }
+        let w = next.p - self.p;
+        let distance_squared = w.square_len();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if let Some(n) = next.normal {
+            pdf *= n.dot(&w).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    /// The BSDF for light scattering between `next` and the previous vertex on the subpath,
+    /// without the cosine term. Light paths carry importance rather than radiance, for which
+    /// shading normals need correcting.
+    fn f(&self, next: &Vertex, importance: bool) -> Color {
+        match &self.kind {
+            Kind::Surface { hit, frame, wo } => {
+                let wi = self.direction_to(&next.p);
+                let f = hit
+                    .material
+                    .eval(hit, &frame.to_local(wo), &frame.to_local(&wi));
+                if importance {
+                    f * shading_correction(hit, frame, wo, &wi)
+                } else {
+                    f
+                }
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// The cosine between the shading normal and the direction to `p`, or 1 away from
+    /// surfaces.
+    fn cos_shading(&self, p: &Point3) -> f32 {
+        match (&self.kind, self.normal) {
+            (Kind::Surface { frame, .. }, Some(_)) => frame.to_local(&self.direction_to(p)).z.abs(),
+            _ => 1.0,
+        }
+    }
+
+    /// The density by area with which the subpath through `prev` and this vertex goes on to
+    /// `next`.
+    fn pdf(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        let wn = self.direction_to(&next.p);
+        let pdf = match &self.kind {
+            Kind::Camera => scene.camera.pdf_direction(&wn),
+            Kind::Light(_, light) => light.emission_pdf(&self.p, &wn).1,
+            Kind::Surface { hit, frame, .. } => match prev {
+                Some(prev) => {
+                    let wp = self.direction_to(&prev.p);
+                    hit.material
+                        .pdf(hit, &frame.to_local(&wp), &frame.to_local(&wn))
+                }
+                None => 0.0,
+            },
+            Kind::Escaped(_) => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// The light this vertex is on, and its index among the scene's.
+    fn light<'s>(&self, scene: &'s Scene) -> Option<(usize, &'s dyn Light)>
+    where
+        'a: 's,
+    {
+        match self.kind {
+            Kind::Light(index, light) => Some((index, light)),
+            Kind::Surface { .. } => scene
+                .lights
+                .iter()
+                .position(|light| light.normal(&self.p).is_some())
+                .map(|index| (index, scene.lights[index].as_ref())),
+            _ => None,
+        }
+    }
+
+    /// The densities with which light paths start here, by area and counting the chance of
+    /// picking the light, and leave towards `next`, by solid angle.
+    fn emission_pdf(&self, scene: &Scene, next: &Vertex) -> (f32, f32) {
+        match self.light(scene) {
+            Some((index, light)) => {
+                let (pdf_position, pdf_direction) =
+                    light.emission_pdf(&self.p, &self.direction_to(&next.p));
+                (
+                    scene.light_tree().emitter_pmf(index) * pdf_position,
+                    pdf_direction,
+                )
+            }
+            None => (0.0, 0.0),
+        }
+    }
+
+    /// Whether this is a light at a single point, which scattered rays can never hit.
+    fn is_delta_light(&self) -> bool {
+        match self.kind {
+            Kind::Light(_, light) => !light.is_area(),
+            _ => false,
+        }
+    }
+}
+
+/// Corrects the BSDF for light carrying importance, which loses its symmetry where the shading
+/// normal differs from the true one (Veach, section 5.3).
+pub fn shading_correction(hit: &HitRecord, frame: &Onb, wo: &Vec3, wi: &Vec3) -> f32 {
+    if hit.material.is_volumetric() {
+        return 1.0;
+    }
+    let numerator = frame.to_local(wo).z.abs() * hit.normal.dot(wi).abs();
+    let denominator = hit.normal.dot(wo).abs() * frame.to_local(wi).z.abs();
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+/// Traces paths from both the camera and the lights and joins them up. Much slower per sample
+/// than `PathTracer`, but finds caustics and light coming through small gaps that it can't.
+pub struct Bdpt {
+    max_depth: usize,
+}
+
+impl Bdpt {
+    /// Builds paths of up to `max_depth` bounces.
+    pub fn new(max_depth: u32) -> Bdpt {
+        Bdpt {
+            max_depth: max_depth as usize,
+        }
+    }
+
+    /// Extends `path` from its last vertex along `r` until it's `max_vertices` long or the
+    /// path ends. `pdf` is the density by solid angle with which `r` was picked.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'a>(
+        &self,
+        scene: &'a Scene,
+        mut r: Ray,
+        mut throughput: Color,
+        mut pdf: f32,
+        max_vertices: usize,
+        importance: bool,
+        path: &mut Vec<Vertex<'a>>,
+        rng: &mut dyn RngCore,
+    ) {
+        let mut bounces = 0;
+        while path.len() < max_vertices {
+            let hit = match integrator::intersect(scene, &r, rng) {
+                Some(hit) => hit,
+                None => {
+                    if !importance {
+                        let direction = r.direction.unit_vector();
+                        let mut escaped = Vertex::new(
+                            Kind::Escaped(direction),
+                            r.origin + direction,
+                            None,
+                            throughput,
+                        );
+                        escaped.pdf_fwd = pdf;
+                        path.push(escaped);
+                    }
+                    break;
+                }
+            };
+
+            let p = hit.p;
+            let normal = if hit.material.is_volumetric() {
+                None
+            } else {
+                Some(hit.normal)
+            };
+            let frame = hit.shading_frame();
+            let wo = -r.direction.unit_vector();
+            let mut vertex = Vertex::new(Kind::Surface { hit, frame, wo }, p, normal, throughput);
+            vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf, &vertex);
+            path.push(vertex);
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let n = path.len();
+            let (wi, delta, pdf_rev) = {
+                let hit = match &path[n - 1].kind {
+                    Kind::Surface { hit, .. } => hit,
+                    _ => unreachable!(),
+                };
+                let wo_local = frame.to_local(&wo);
+                let sample = match hit.material.sample(hit, &wo_local, rng) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+                let wi = frame.to_world(&sample.wi);
+                throughput *= if hit.material.is_volumetric() {
+                    sample.f / sample.pdf
+                } else {
+                    sample.weight()
+                };
+                if importance {
+                    throughput *= shading_correction(hit, &frame, &wo, &wi);
+                }
+                if sample.delta {
+                    (wi, true, 0.0)
+                } else {
+                    pdf = sample.pdf;
+                    (wi, false, hit.material.pdf(hit, &sample.wi, &wo_local))
+                }
+            };
+            if delta {
+                pdf = 0.0;
+            }
+            path[n - 1].delta = delta;
+            path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev, &path[n - 2]);
+            r = Ray::new(p, wi, r.time);
+
+            bounces += 1;
+            if bounces >= MIN_ROULETTE_DEPTH {
+                let survival = throughput.max_component();
+                if survival < 1.0 {
+                    if rng.gen::<f32>() >= survival {
+                        break;
+                    }
+                    throughput /= survival;
+                }
+            }
+        }
+    }
+
+    fn camera_path<'a>(&self, r: Ray, scene: &'a Scene, rng: &mut dyn RngCore) -> Vec<Vertex<'a>> {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut path = vec![Vertex::new(Kind::Camera, r.origin, None, white)];
+        let pdf = scene.camera.pdf_direction(&r.direction.unit_vector());
+        self.random_walk(
+            scene,
+            r,
+            white,
+            pdf,
+            self.max_depth + 2,
+            false,
+            &mut path,
+            rng,
+        );
+        path
+    }
+
+    fn light_path<'a>(
+        &self,
+        time: f32,
+        scene: &'a Scene,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vertex<'a>> {
+        let mut path = Vec::new();
+        // Paths from lights at infinity can't be weighed against camera paths escaping to them,
+        // so those lights are only ever found from the camera's side
+        let (index, pmf) = match scene.light_tree().sample_emitter(rng.gen()) {
+            Some(picked) => picked,
+            None => return path,
+        };
+        let light = scene.lights[index].as_ref();
+        let emission = match light.sample_emission(rng) {
+            Some(emission) if emission.pdf_position > 0.0 && emission.pdf_direction > 0.0 => {
+                emission
+            }
+            _ => return path,
+        };
+        if emission.radiance.max_component() <= 0.0 {
+            return path;
+        }
+
+        let pdf_origin = pmf * emission.pdf_position;
+        let mut vertex = Vertex::new(
+            Kind::Light(index, light),
+            emission.origin,
+            emission.normal,
+            emission.radiance / pdf_origin,
+        );
+        vertex.pdf_fwd = pdf_origin;
+        path.push(vertex);
+
+        let cos_theta = emission
+            .normal
+            .map_or(1.0, |n| n.dot(&emission.direction).abs());
+        let throughput = emission.radiance * cos_theta / (pdf_origin * emission.pdf_direction);
+        let r = Ray::new(emission.origin, emission.direction, time);
+        self.random_walk(
+            scene,
+            r,
+            throughput,
+            emission.pdf_direction,
+            self.max_depth + 1,
+            true,
+            &mut path,
+            rng,
+        );
+        path
+    }
+
+    /// The light along a camera path that escapes the scene, weighted against finding lights
+    /// at infinity by sampling them.
+    fn escaped(&self, scene: &Scene, camera: &[Vertex], time: f32) -> Color {
+        let end = &camera[camera.len() - 1];
+        let prev = &camera[camera.len() - 2];
+        let direction = match end.kind {
+            Kind::Escaped(direction) => direction,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
+        let mut radiance = scene.background(&Ray::new(prev.p, direction, time));
+        for (i, light) in scene.lights.iter().enumerate() {
+            let mut light_radiance = light.infinite_radiance(&direction);
+            if light_radiance.max_component() > 0.0 && prev.is_surface() && !prev.delta {
+                let pmf = scene.light_tree().pmf(&prev.p, prev.normal.as_ref(), i);
+                let light_pdf = pmf * light.infinite_pdf(&direction);
+                light_radiance *= sampling::power_heuristic(end.pdf_fwd, light_pdf);
+            }
+            radiance += light_radiance;
+        }
+        end.throughput * radiance
+    }
+
+    /// Joins the first `s` vertices of the light path to the first `t` of the camera path,
+    /// returning the weighted light along the path, and for `t` of 1, where on the image it
+    /// lands.
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        scene: &Scene,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        time: f32,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Option<(f32, f32)>)> {
+        let pt = &camera_path[t - 1];
+        if let Kind::Escaped(_) = pt.kind {
+            return if s == 0 {
+                Some((self.escaped(scene, &camera_path[..t], time), None))
+            } else {
+                None
+            };
+        }
+
+        let mut raster = None;
+        let mut sampled = None;
+        let contribution = if s == 0 {
+            match &pt.kind {
+                Kind::Surface { hit, .. } => pt.throughput * hit.material.emitted(hit),
+                _ => return None,
+            }
+        } else if t == 1 {
+            let qs = &light_path[s - 1];
+            if !qs.is_surface() || qs.delta {
+                return None;
+            }
+            let lens = scene.camera.sample_towards(&qs.p, rng)?;
+            let throughput = lens.importance / lens.pdf;
+            let camera = Vertex::new(
+                Kind::Camera,
+                lens.point,
+                None,
+                Color::new(throughput, throughput, throughput),
+            );
+            let contribution =
+                qs.throughput * qs.f(&camera, true) * camera.throughput * qs.cos_shading(&camera.p);
+            if contribution.max_component() <= 0.0 {
+                return None;
+            }
+            raster = Some(lens.raster);
+            sampled = Some(camera);
+            contribution
+                * integrator::shadow_transmittance(scene, &qs.p, &lens.wi, lens.distance, time, rng)
+        } else if s == 1 {
+            if !pt.is_surface() || pt.delta {
+                return None;
+            }
+            let (index, pmf) = scene
+                .light_tree()
+                .sample(&pt.p, pt.normal.as_ref(), rng.gen())?;
+            let light = scene.lights[index].as_ref();
+            let sample = light.sample(&pt.p, rng)?;
+            if sample.distance == f32::MAX {
+                return self.connect_infinite(scene, pt, light, &sample, pmf, time, rng);
+            }
+
+            let q = pt.p + sample.wi * sample.distance;
+            let mut vertex = Vertex::new(
+                Kind::Light(index, light),
+                q,
+                light.normal(&q),
+                sample.radiance / (pmf * sample.pdf),
+            );
+            vertex.pdf_fwd = vertex.emission_pdf(scene, pt).0;
+            let contribution =
+                pt.throughput * pt.f(&vertex, false) * vertex.throughput * pt.cos_shading(&q);
+            if contribution.max_component() <= 0.0 {
+                return None;
+            }
+            sampled = Some(vertex);
+            contribution
+                * integrator::shadow_transmittance(
+                    scene,
+                    &pt.p,
+                    &sample.wi,
+                    sample.distance,
+                    time,
+                    rng,
+                )
+        } else {
+            let qs = &light_path[s - 1];
+            if !qs.is_surface() || qs.delta || !pt.is_surface() || pt.delta {
+                return None;
+            }
+            let contribution = qs.throughput * qs.f(pt, true) * pt.f(qs, false) * pt.throughput;
+            if contribution.max_component() <= 0.0 {
+                return None;
+            }
+            let d = pt.p - qs.p;
+            let distance = d.len();
+            let g = qs.cos_shading(&pt.p) * pt.cos_shading(&qs.p) / (distance * distance);
+            contribution
+                * g
+                * integrator::shadow_transmittance(
+                    scene,
+                    &qs.p,
+                    &(d / distance),
+                    distance,
+                    time,
+                    rng,
+                )
+        };
+        if contribution.max_component() <= 0.0 {
+            return None;
+        }
+
+        let weight = self.mis_weight(scene, light_path, camera_path, sampled.as_ref(), s, t);
+        Some((contribution * weight, raster))
+    }
+
+    /// Joins `pt` to a light at infinity, weighted against escaping paths finding it.
+    #[allow(clippy::too_many_arguments)]
+    fn connect_infinite(
+        &self,
+        scene: &Scene,
+        pt: &Vertex,
+        light: &dyn Light,
+        sample: &crate::light::LightSample,
+        pmf: f32,
+        time: f32,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Option<(f32, f32)>)> {
+        let (hit, frame, wo) = match &pt.kind {
+            Kind::Surface { hit, frame, wo } => (hit, frame, wo),
+            _ => return None,
+        };
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(&sample.wi));
+        let mut f = hit.material.eval(hit, &wo, &wi);
+        if !hit.material.is_volumetric() {
+            f *= wi.z.abs();
+        }
+        if f.max_component() <= 0.0 {
+            return None;
+        }
+        let mut weight = sample.weight() / pmf;
+        if light.is_area() {
+            weight *= sampling::power_heuristic(pmf * sample.pdf, hit.material.pdf(hit, &wo, &wi));
+        }
+        let transmittance =
+            integrator::shadow_transmittance(scene, &pt.p, &sample.wi, sample.distance, time, rng);
+        Some((pt.throughput * f * weight * transmittance, None))
+    }
+
+    /// Weighs the path joining `s` light vertices to `t` camera vertices against every other
+    /// way of building it from the same vertices, with the power heuristic. `sampled` stands
+    /// in for the light's vertex when `s` is 1 and the camera's when `t` is 1.
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let qs = match s {
+            0 => None,
+            1 => sampled,
+            _ => Some(&light_path[s - 1]),
+        };
+        let pt = if t == 1 {
+            sampled.expect("camera vertex for t = 1")
+        } else {
+            &camera_path[t - 1]
+        };
+        let qs_minus = if s > 1 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+        let pt_minus = if t > 1 {
+            Some(&camera_path[t - 2])
+        } else {
+            None
+        };
+
+        // The densities and delta flags of both subpaths, updated for this connection
+        let densities = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta);
+        let mut light: Vec<_> = match s {
+            1 => vec![densities(qs.expect("light vertex for s = 1"))],
+            _ => light_path[..s].iter().map(densities).collect(),
+        };
+        let mut camera: Vec<_> = match t {
+            1 => vec![densities(pt)],
+            _ => camera_path[..t].iter().map(densities).collect(),
+        };
+
+        camera[t - 1].1 = match qs {
+            Some(qs) => qs.pdf(scene, qs_minus, pt),
+            None => {
+                let pdf = pt
+                    .emission_pdf(scene, pt_minus.expect("vertex before light"))
+                    .0;
+                // Emitters that aren't among the scene's lights can only be found this way
+                if pdf == 0.0 {
+                    return 1.0;
+                }
+                pdf
+            }
+        };
+        camera[t - 1].2 = false;
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(scene, Some(qs), pt_minus),
+                None => pt.convert_density(pt.emission_pdf(scene, pt_minus).1, pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].1 = pt.pdf(scene, pt_minus, qs);
+            light[s - 1].2 = false;
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].1 = qs.pdf(scene, Some(pt), qs_minus);
+            }
+        }
+
+        // Densities are zero next to delta vertices, where they cancel out
+        let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].1) / remap(camera[i].0);
+            let strategy = (s + t - i, i);
+            if !camera[i].2 && !camera[i - 1].2 && strategy != (1, 1) {
+                sum += ratio * ratio;
+            }
+        }
+        let delta_light = match s {
+            0 => false,
+            1 => qs.is_some_and(|qs| qs.is_delta_light()),
+            _ => light_path[0].is_delta_light(),
+        };
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].1) / remap(light[i].0);
+            let delta_before = if i > 0 { light[i - 1].2 } else { delta_light };
+            let strategy = (i, s + t - i);
+            if !light[i].2 && !delta_before && strategy != (1, 1) {
+                sum += ratio * ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for Bdpt {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, splats: &Splats) -> Color {
+        let time = r.time;
+        let camera_path = self.camera_path(r, scene, rng);
+        let light_path = self.light_path(time, scene, rng);
+
+        // Lights that don't start paths, such as the sun, are still found by sampling them
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len().max(1) {
+                let depth = s + t;
+                if (s == 1 && t == 1) || depth < 2 || depth - 2 > self.max_depth {
+                    continue;
+                }
+                if let Some((contribution, raster)) =
+                    self.connect(scene, &light_path, &camera_path, s, t, time, rng)
+                {
+                    match raster {
+                        Some(raster) => splats.add(raster, contribution),
+                        None => radiance += contribution,
+                    }
+                }
+            }
+        }
+        radiance
+    }
+}
@@ -80,8 +80,8 @@ pub fn decode_image(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<Image> {
 
     let color_palette = read_color_palette(bmp_data, &dib_header)?;
 
-    let width = dib_header.width.abs() as u32;
-    let height = dib_header.height.abs() as u32;
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
     let padding = width % 4;
 
     let data = match color_palette {
@@ -212,10 +212,10 @@ fn read_color_palette(
 
     bmp_data.seek(SeekFrom::Start(BMP_HEADER_SIZE + dh.header_size as u64))?;
 
-    let mut px = &mut [0; 4][0..num_bytes as usize];
+    let px = &mut [0; 4][0..num_bytes as usize];
     let mut color_palette = Vec::with_capacity(num_entries);
     for _ in 0..num_entries {
-        bmp_data.read_exact(&mut px)?;
+        bmp_data.read_exact(px)?;
         color_palette.push(px!(px[2], px[1], px[0]));
     }
 
@@ -223,7 +223,7 @@ fn read_color_palette(
 }
 
 fn read_indexes(
-    bmp_data: &mut Vec<u8>,
+    bmp_data: &mut [u8],
     palette: &[Pixel],
     width: usize,
     height: usize,
@@ -241,7 +241,7 @@ fn read_indexes(
         let start = offset + (bytes_per_row + padding) * y;
         let bytes = &bmp_data[start..start + bytes_per_row];
 
-        for i in bit_index(bytes, bpp as usize, width as usize) {
+        for i in bit_index(bytes, bpp as usize, width) {
             data.push(palette[i]);
         }
     }
@@ -283,13 +283,13 @@ struct BitIndex<'a> {
     index: usize,
 }
 
-fn bit_index(bytes: &[u8], nbits: usize, size: usize) -> BitIndex {
+fn bit_index(bytes: &[u8], nbits: usize, size: usize) -> BitIndex<'_> {
     let bits_left = BITS - nbits;
     BitIndex {
         size,
         nbits,
         bits_left,
-        mask: (!0 as u8 >> bits_left),
+        mask: (!0u8 >> bits_left),
         bytes,
         index: 0,
     }
@@ -42,6 +42,7 @@ use std::iter::Iterator;
 use std::path::Path;
 
 // Expose decoder's public types, structs, and enums
+#[allow(unused_imports)]
 pub use decoder::{BmpError, BmpErrorKind, BmpResult};
 
 /// Macro to generate a `Pixel` from `r`, `g` and `b` values.
@@ -324,7 +325,7 @@ impl Image {
     /// ```
     #[inline]
     pub fn coordinates(&self) -> ImageIndex {
-        ImageIndex::new(self.width as u32, self.height as u32)
+        ImageIndex::new(self.width, self.height)
     }
 
     /// Saves the `Image` instance to the path specified by `path`.
@@ -494,7 +495,7 @@ mod tests {
         f.seek(SeekFrom::Start(54)).unwrap();
 
         let mut px = [0; 3];
-        f.read(&mut px).unwrap();
+        f.read_exact(&mut px).unwrap();
 
         assert_eq!(
             Pixel {
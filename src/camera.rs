@@ -1,7 +1,7 @@
-use crate::ray::Ray;
+use crate::ray::{Ray, RayDifferentials};
 use crate::vec::{Point3, Vec3};
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 pub struct Camera {
     origin: Point3,
@@ -10,10 +10,17 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
+    w: Vec3,
     lens_radius: f32,
+    focus_dist: f32,
+    time0: f32,
+    time1: f32,
 }
 
 impl Camera {
+    /// `time0` and `time1` are the shutter open and close times; each ray is sent at a random
+    /// time in between.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -22,6 +29,8 @@ impl Camera {
         aspect_ratio: f32,
         aperture: f32,
         focust_dist: f32,
+        time0: f32,
+        time1: f32,
     ) -> Camera {
         let lens_radius = aperture / 2.0;
         let theta = vfov * std::f32::consts::PI / 180.0;
@@ -42,23 +51,130 @@ impl Camera {
             vertical,
             u,
             v,
+            w,
             lens_radius,
+            focus_dist: focust_dist,
+            time0,
+            time1,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    /// `ds` and `dt` are the size of a pixel, used to compute ray differentials for texture
+    /// filtering. The point on the lens and the time are picked with `rng`.
+    pub fn get_ray(&self, s: f32, t: f32, ds: f32, dt: f32, rng: &mut dyn RngCore) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(rng);
         let offset = self.u * rd.x + self.v * rd.y;
+        let time = self.shutter_time(rng);
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        )
+        let origin = self.origin + offset;
+        // A unit direction makes hit distances along the ray come out in world units
+        let direction = self.direction(s, t, &offset).into_unit_vector();
+        let mut ray = Ray::new(origin, direction, time);
+        ray.differentials = Some(RayDifferentials {
+            rx_origin: origin,
+            rx_direction: self.direction(s + ds, t, &offset),
+            ry_origin: origin,
+            ry_direction: self.direction(s, t + dt, &offset),
+        });
+        ray
     }
+
+    /// A random time while the shutter is open.
+    pub fn shutter_time(&self, rng: &mut dyn RngCore) -> f32 {
+        if self.time1 > self.time0 {
+            rng.gen_range(self.time0..self.time1)
+        } else {
+            self.time0
+        }
+    }
+
+    fn direction(&self, s: f32, t: f32, offset: &Vec3) -> Vec3 {
+        self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - *offset
+    }
+
+    /// The area of the lens, or 1 for a pinhole, so that densities over it stay finite.
+    fn lens_area(&self) -> f32 {
+        if self.lens_radius > 0.0 {
+            std::f32::consts::PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
+        }
+    }
+
+    /// The area of the image a unit distance in front of the lens.
+    fn image_area(&self) -> f32 {
+        self.horizontal.len() * self.vertical.len() / (self.focus_dist * self.focus_dist)
+    }
+
+    /// Where a ray leaving the lens at `origin` in the unit `direction` lands on the image, as
+    /// `(s, t)` in the same terms as `get_ray`, or `None` if it misses.
+    pub fn raster(&self, origin: &Point3, direction: &Vec3) -> Option<(f32, f32)> {
+        let cos_theta = -direction.dot(&self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus = *origin + *direction * (self.focus_dist / cos_theta) - self.lower_left_corner;
+        let s = focus.dot(&self.horizontal) / self.horizontal.square_len();
+        let t = focus.dot(&self.vertical) / self.vertical.square_len();
+        if (0.0..1.0).contains(&s) && (0.0..1.0).contains(&t) {
+            Some((s, t))
+        } else {
+            None
+        }
+    }
+
+    /// The solid angle density with which `get_ray` sends rays in the unit `direction`, for a
+    /// random point on the image.
+    pub fn pdf_direction(&self, direction: &Vec3) -> f32 {
+        let cos_theta = -direction.dot(&self.w);
+        if cos_theta <= 0.0 {
+            0.0
+        } else {
+            1.0 / (self.image_area() * cos_theta.powi(3))
+        }
+    }
+
+    /// Picks a point on the lens that sees `p`, for joining light paths straight to the camera.
+    /// `None` if `p` is outside the image.
+    pub fn sample_towards(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LensSample> {
+        let rd = self.lens_radius * random_in_unit_disk(rng);
+        let point = self.origin + self.u * rd.x + self.v * rd.y;
+        let to_lens = point - *p;
+        let distance = to_lens.len();
+        let wi = to_lens / distance;
+        let raster = self.raster(&point, &-wi)?;
+
+        // The importance of a pixel falls off with the fourth power of the cosine, as the
+        // image plane is further away and tilted towards the edges
+        let cos_theta = wi.dot(&self.w);
+        let importance = 1.0 / (self.image_area() * self.lens_area() * cos_theta.powi(4));
+        Some(LensSample {
+            point,
+            wi,
+            distance,
+            importance,
+            pdf: distance * distance / (cos_theta * self.lens_area()),
+            raster,
+        })
+    }
+}
+
+/// A point on the lens seen from somewhere in the scene.
+#[derive(Debug, Copy, Clone)]
+pub struct LensSample {
+    pub point: Point3,
+    /// Unit direction from the point in the scene towards the lens.
+    pub wi: Vec3,
+    pub distance: f32,
+    /// How much the camera's image responds to light arriving along `-wi`.
+    pub importance: f32,
+    /// Solid angle density of picking `wi`.
+    pub pdf: f32,
+    /// Where on the image the light lands, as `(s, t)` in the same terms as `get_ray`.
+    pub raster: (f32, f32),
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
+fn random_in_unit_disk(rng: &mut dyn RngCore) -> Vec3 {
     loop {
         let p = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
         if p.dot(&p) < 1.0 {
@@ -0,0 +1,163 @@
+//! Reading high dynamic range images in the Radiance RGBE (`.hdr`) format.
+
+use crate::vec::Color;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// An image of linear radiance values, with row 0 at the top.
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    /// Fills an image by evaluating `radiance` at each pixel's column and row.
+    pub fn from_fn<F: Fn(usize, usize) -> Color>(
+        width: usize,
+        height: usize,
+        radiance: F,
+    ) -> HdrImage {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(radiance(x, y));
+            }
+        }
+        HdrImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Reads a Radiance RGBE file, either flat or run-length encoded. Only the standard
+    /// `-Y height +X width` orientation is supported.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<HdrImage> {
+        HdrImage::from_reader(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(source: &mut R) -> io::Result<HdrImage> {
+        let mut line = String::new();
+        source.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid_data("not a Radiance HDR file"));
+        }
+        loop {
+            line.clear();
+            if source.read_line(&mut line)? == 0 {
+                return Err(invalid_data("missing resolution"));
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid_data("unsupported pixel format"));
+                }
+            }
+        }
+
+        line.clear();
+        source.read_line(&mut line)?;
+        let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (
+                height.parse().map_err(|_| invalid_data("bad height"))?,
+                width.parse().map_err(|_| invalid_data("bad width"))?,
+            ),
+            _ => return Err(invalid_data("unsupported orientation")),
+        };
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            read_scanline(source, &mut scanline)?;
+            pixels.extend(scanline.iter().map(rgbe_to_color));
+        }
+        Ok(HdrImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads one row, which is run-length encoded channel by channel when it starts with the bytes
+/// `2 2` followed by its width.
+fn read_scanline<R: Read>(source: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut first = [0u8; 4];
+    source.read_exact(&mut first)?;
+    let encoded = (8..0x8000).contains(&width)
+        && first[0] == 2
+        && first[1] == 2
+        && ((first[2] as usize) << 8 | first[3] as usize) == width;
+    if !encoded {
+        scanline[0] = first;
+        for pixel in scanline[1..].iter_mut() {
+            source.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            source.read_exact(&mut count)?;
+            let (count, run) = match count[0] {
+                c if c > 128 => ((c - 128) as usize, true),
+                c => (c as usize, false),
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad run length"));
+            }
+            if run {
+                let mut value = [0u8; 1];
+                source.read_exact(&mut value)?;
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                for pixel in scanline[x..x + count].iter_mut() {
+                    source.read_exact(&mut pixel[channel..channel + 1])?;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a pixel stored as three mantissas sharing an exponent.
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0f32.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale,
+    )
+}
@@ -1,29 +1,155 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vec::{Point3, Vec3};
+use crate::texture::{Texture, UvDerivatives};
+use crate::transform::Transform;
+use crate::vec::{Color, Point3, Vec3};
+use rand::{Rng, RngCore};
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct HitRecord {
     pub t: f32,
     pub p: Point3,
+    /// The true normal of the surface, for anything that depends on where the geometry is. Like
+    /// the shading normal it always faces back against the ray.
     pub normal: Vec3,
+    /// The normal materials shade with, which normal and bump maps tilt away from `normal`.
+    pub shading_normal: Vec3,
+    pub u: f32,
+    pub v: f32,
+    /// Partial derivatives of the hit point with respect to `u` and `v`, or zero if the surface
+    /// has no parameterisation.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub uv_derivatives: UvDerivatives,
+    /// Whether the ray hit the outside of the surface, where the outward normal faces it.
+    pub front_face: bool,
     pub material: Arc<dyn Material>,
 }
 
 impl HitRecord {
-    pub fn new(t: f32, p: Point3, normal: Vec3, material: Arc<dyn Material>) -> HitRecord {
+    /// Orients `outward_normal` to face against `r`, noting which side was hit.
+    pub fn new(
+        r: &Ray,
+        t: f32,
+        p: Point3,
+        outward_normal: Vec3,
+        (u, v): (f32, f32),
+        material: Arc<dyn Material>,
+    ) -> HitRecord {
+        let front_face = r.direction.dot(&outward_normal) < 0.0;
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
         HitRecord {
             t,
             p,
             normal,
+            shading_normal: normal,
+            u,
+            v,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            uv_derivatives: UvDerivatives::default(),
+            front_face,
             material,
         }
     }
+
+    /// Estimates how the surface coordinates change across the pixel from the differentials of
+    /// the ray that produced this hit, by intersecting the offset rays with the tangent plane.
+    pub fn compute_uv_derivatives(&mut self, r: &Ray) {
+        let d = match r.differentials {
+            Some(d) => d,
+            None => return,
+        };
+        let plane_d = self.normal.dot(&self.p);
+        let tx = (plane_d - self.normal.dot(&d.rx_origin)) / self.normal.dot(&d.rx_direction);
+        let ty = (plane_d - self.normal.dot(&d.ry_origin)) / self.normal.dot(&d.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
+        }
+        let dpdx = d.rx_origin + tx * d.rx_direction - self.p;
+        let dpdy = d.ry_origin + ty * d.ry_direction - self.p;
+
+        // Solve dp = du * dpdu + dv * dpdv in the two axes least aligned with the normal, which
+        // keeps the system well conditioned
+        let n = self.normal.abs();
+        let (a0, a1) = if n.x > n.y && n.x > n.z {
+            (1, 2)
+        } else if n.y > n.z {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let det = self.dpdu[a0] * self.dpdv[a1] - self.dpdv[a0] * self.dpdu[a1];
+        if det.abs() < 1e-12 {
+            return;
+        }
+        let solve = |dp: &Vec3| {
+            (
+                (self.dpdv[a1] * dp[a0] - self.dpdv[a0] * dp[a1]) / det,
+                (self.dpdu[a0] * dp[a1] - self.dpdu[a1] * dp[a0]) / det,
+            )
+        };
+        let (dudx, dvdx) = solve(&dpdx);
+        let (dudy, dvdy) = solve(&dpdy);
+        self.uv_derivatives = UvDerivatives {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        };
+    }
+
+    /// Looks up a texture at the hit point, filtered over the pixel footprint.
+    pub fn texture(&self, texture: &dyn Texture) -> Color {
+        texture.filtered(self.u, self.v, &self.p, &self.uv_derivatives)
+    }
+
+    /// Whether the hit is on the back of a one-sided surface, which rays should pass through.
+    pub fn is_culled(&self) -> bool {
+        !self.front_face && !self.material.is_two_sided()
+    }
+
+    /// The local frame materials work in, with the shading normal along +z and `dpdu` along +x.
+    pub fn shading_frame(&self) -> Onb {
+        Onb::from_wu(&self.shading_normal, &self.dpdu)
+    }
+}
+
+/// Maps a point on the unit sphere to `(u, v)`, with `u` running around the y axis from -x and
+/// `v` running from the south pole to the north pole.
+pub fn sphere_uv(p: &Point3) -> (f32, f32) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + std::f32::consts::PI;
+    (
+        phi / (2.0 * std::f32::consts::PI),
+        theta / std::f32::consts::PI,
+    )
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    /// The nearest intersection along the ray between `t_min` and `t_max`. Objects that scatter
+    /// or let light through at random, such as media, draw their random numbers from `rng`.
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord>;
+
+    /// The fraction of light that gets through along the ray between `t_min` and `t_max`, for
+    /// testing visibility. Surfaces block it entirely, while media let some through.
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Color {
+        if self.hit(r, t_min, t_max, rng).is_some() {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    /// A box around the object over the whole shutter interval, or `None` if it's unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
 }
 
 pub struct HittableList {
@@ -37,17 +163,34 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let mut temp_rec: Option<HitRecord> = None;
         let mut closest_so_far = t_max;
         for h in self.list.iter() {
-            if let Some(hit) = h.hit(r, t_min, closest_so_far) {
+            if let Some(hit) = h.hit(r, t_min, closest_so_far, rng) {
                 closest_so_far = hit.t;
                 temp_rec = Some(hit)
             }
         }
         temp_rec
     }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Color {
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        for h in self.list.iter() {
+            transmittance *= h.transmittance(r, t_min, t_max, rng);
+            if transmittance.max_component() <= 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.list.iter().map(|h| h.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.union(&b?)))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -68,39 +211,675 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    #[allow(clippy::many_single_char_names)]
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = r.origin - self.center;
-        let a = r.direction.dot(&r.direction);
-        let b = oc.dot(&r.direction);
-        let c = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let temp = (-b - discriminant.sqrt()) / a;
-            if t_min < temp && temp < t_max {
-                let p = r.point_at_parameter(temp);
-                Some(HitRecord::new(
-                    temp,
-                    p,
-                    (p - self.center) / self.radius,
-                    self.material.clone(),
-                ))
-            } else {
-                let temp = (-b + discriminant.sqrt()) / a;
-                if t_min < temp && temp < t_max {
-                    let p = r.point_at_parameter(temp);
-                    Some(HitRecord::new(
-                        temp,
-                        p,
-                        (p - self.center) / self.radius,
-                        self.material.clone(),
-                    ))
-                } else {
-                    None
-                }
-            }
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+        hit_sphere(&self.center, self.radius, &self.material, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::centered(self.center, radius))
+    }
+}
+
+/// A sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`.
+#[derive(Debug, Clone)]
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    fn center(&self, time: f32) -> Point3 {
+        // A zero-length interval means the sphere doesn't move
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + t * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+        hit_sphere(
+            &self.center(r.time),
+            self.radius,
+            &self.material,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::centered(self.center0, radius).union(&Aabb::centered(self.center1, radius)))
+    }
+}
+
+/// A flat parallelogram with one corner at `corner` and sides along `edge_u` and `edge_v`. Its
+/// outward normal is `edge_u × edge_v`, and `(u, v)` run from 0 to 1 along the two edges.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    corner: Point3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, edge_u: Vec3, edge_v: Vec3, material: Arc<dyn Material>) -> Quad {
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let n = self.edge_u.cross(&self.edge_v);
+        let denom = n.dot(&r.direction);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = n.dot(&(self.corner - r.origin)) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let p = r.point_at_parameter(t);
+
+        // Coordinates of the hit point along the edges
+        let w = n / n.dot(&n);
+        let q = p - self.corner;
+        let u = w.dot(&q.cross(&self.edge_v));
+        let v = w.dot(&self.edge_u.cross(&q));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(r, t, p, n.unit_vector(), (u, v), self.material.clone());
+        rec.dpdu = self.edge_u;
+        rec.dpdv = self.edge_v;
+        if rec.is_culled() {
+            None
         } else {
+            Some(rec)
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let far = self.corner + self.edge_u + self.edge_v;
+        Some(
+            Aabb::new(self.corner.min(&far), self.corner.max(&far)).union(&Aabb::new(
+                (self.corner + self.edge_u).min(&(self.corner + self.edge_v)),
+                (self.corner + self.edge_u).max(&(self.corner + self.edge_v)),
+            )),
+        )
+    }
+}
+
+/// A triangle whose outward normal follows the right-hand rule around `a`, `b`, `c`. `(u, v)`
+/// are the barycentric weights of `b` and `c`.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    a: Point3,
+    b: Point3,
+    c: Point3,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Arc<dyn Material>) -> Triangle {
+        Triangle { a, b, c, material }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+        // Möller-Trumbore
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let pvec = r.direction.cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = r.origin - self.a;
+        let u = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let v = r.direction.dot(&qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&qvec) * inv_det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(
+            r,
+            t,
+            r.point_at_parameter(t),
+            e1.cross(&e2).unit_vector(),
+            (u, v),
+            self.material.clone(),
+        );
+        rec.dpdu = e1;
+        rec.dpdv = e2;
+        if rec.is_culled() {
             None
+        } else {
+            Some(rec)
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.a.min(&self.b).min(&self.c),
+            self.a.max(&self.b).max(&self.c),
+        ))
+    }
+}
+
+#[allow(clippy::many_single_char_names)]
+fn hit_sphere(
+    center: &Point3,
+    radius: f32,
+    material: &Arc<dyn Material>,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord> {
+    let oc = r.origin - *center;
+    let a = r.direction.dot(&r.direction);
+    let b = oc.dot(&r.direction);
+    let c = oc.dot(&oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+    for t in [
+        (-b - discriminant.sqrt()) / a,
+        (-b + discriminant.sqrt()) / a,
+    ] {
+        if t_min < t && t < t_max {
+            let rec = sphere_hit_record(center, radius, material, r, t);
+            if !rec.is_culled() {
+                return Some(rec);
+            }
+        }
+    }
+    None
+}
+
+fn sphere_hit_record(
+    center: &Point3,
+    radius: f32,
+    material: &Arc<dyn Material>,
+    r: &Ray,
+    t: f32,
+) -> HitRecord {
+    let p = r.point_at_parameter(t);
+    let normal = (p - *center) / radius;
+    let mut rec = HitRecord::new(r, t, p, normal, sphere_uv(&normal), material.clone());
+    // Derivatives of the mapping in sphere_uv; dpdu vanishes at the poles
+    let pi = std::f32::consts::PI;
+    let sin_theta = normal.x.hypot(normal.z);
+    rec.dpdu = 2.0 * pi * radius * Vec3::new(normal.z, 0.0, -normal.x);
+    if sin_theta > 0.0 {
+        rec.dpdv = pi
+            * radius
+            * Vec3::new(
+                -normal.y * normal.x / sin_theta,
+                sin_theta,
+                -normal.y * normal.z / sin_theta,
+            );
+    }
+    rec
+}
+
+/// Places a shared object in the scene through a transform. The transform may be keyframed at
+/// the shutter open and close times to animate the object.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    start: Transform,
+    end: Transform,
+    time0: f32,
+    time1: f32,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Instance {
+        Instance::moving(object, transform, transform, 0.0, 1.0)
+    }
+
+    pub fn moving(
+        object: Arc<dyn Hittable>,
+        start: Transform,
+        end: Transform,
+        time0: f32,
+        time1: f32,
+    ) -> Instance {
+        Instance {
+            object,
+            start,
+            end,
+            time0,
+            time1,
+        }
+    }
+
+    fn transform(&self, time: f32) -> Transform {
+        if self.time1 == self.time0 {
+            return self.start;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.start.interpolate(&self.end, t)
+    }
+
+    /// The ray in the object's own space at the ray's time.
+    fn local_ray(transform: &Transform, r: &Ray) -> Ray {
+        // The local direction isn't renormalised so that t means the same thing in both spaces
+        Ray::new(
+            transform.inverse_point(&r.origin),
+            transform.inverse_vector(&r.direction),
+            r.time,
+        )
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let transform = self.transform(r.time);
+        let local = Instance::local_ray(&transform, r);
+        let mut hit = self.object.hit(&local, t_min, t_max, rng)?;
+        hit.p = transform.point(&hit.p);
+        hit.normal = transform.normal(&hit.normal).into_unit_vector();
+        hit.shading_normal = transform.normal(&hit.shading_normal).into_unit_vector();
+        hit.dpdu = transform.vector(&hit.dpdu);
+        hit.dpdv = transform.vector(&hit.dpdv);
+        Some(hit)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Color {
+        let transform = self.transform(r.time);
+        self.object
+            .transmittance(&Instance::local_ray(&transform, r), t_min, t_max, rng)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.object.bounding_box()?;
+        // Moving instances are bounded at a handful of times along the way, which follows
+        // rotations around their arc closely enough
+        const STEPS: u32 = 8;
+        let steps = if self.time1 == self.time0 { 0 } else { STEPS };
+        (0..=steps)
+            .map(|i| {
+                let time = self.time0 + (self.time1 - self.time0) * i as f32 / STEPS as f32;
+                let transform = self.transform(time);
+                let corners = local.corners().map(|corner| transform.point(&corner));
+                corners
+                    .iter()
+                    .skip(1)
+                    .fold(Aabb::new(corners[0], corners[0]), |acc, p| {
+                        acc.union(&Aabb::new(*p, *p))
+                    })
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+/// How a `Cutout` turns its alpha texture into holes.
+#[derive(Debug, Copy, Clone)]
+pub enum AlphaMode {
+    /// Removes the surface wherever alpha is below the threshold, for hard-edged masks.
+    Threshold(f32),
+    /// Lets each ray through with probability `1 - alpha`, so partial alpha averages out to a
+    /// translucent surface.
+    Stochastic,
+}
+
+/// Cuts holes in an object wherever its alpha texture is transparent. Rays carry on through the
+/// holes to whatever lies behind, including the far side of the object itself.
+pub struct Cutout {
+    object: Box<dyn Hittable>,
+    alpha: Arc<dyn Texture>,
+    mode: AlphaMode,
+}
+
+impl Cutout {
+    pub fn new(object: Box<dyn Hittable>, alpha: Arc<dyn Texture>, mode: AlphaMode) -> Cutout {
+        Cutout {
+            object,
+            alpha,
+            mode,
+        }
+    }
+
+    fn is_opaque(&self, hit: &HitRecord, rng: &mut dyn RngCore) -> bool {
+        let c = hit.texture(&*self.alpha);
+        let alpha = (c.x + c.y + c.z) / 3.0;
+        match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => rng.gen::<f32>() < alpha,
+        }
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let hit = self.object.hit(r, t_min, t_max, rng)?;
+            if self.is_opaque(&hit, rng) {
+                return Some(hit);
+            }
+            // Step past the hole so the same intersection isn't found again
+            t_min = hit.t + 1e-4;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::{Lambertian, OneSided};
+    use crate::sampling;
+    use crate::texture::{Gradient, SolidColor};
+    use crate::transform::Rotation;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    /// A camera whose shutter is open from time 0 to 1.
+    fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            5.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    /// How often a ray straight down through x = 0.5 hits `object` over the shutter interval.
+    fn blocked_fraction(object: &dyn Hittable, rng: &mut StdRng) -> f32 {
+        const SAMPLES: usize = 20_000;
+        let camera = camera();
+        let blocked = (0..SAMPLES)
+            .filter(|_| {
+                let time = camera.shutter_time(rng);
+                let r = Ray::new(Point3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), time);
+                object.hit(&r, 1e-3, f32::MAX, rng).is_some()
+            })
+            .count();
+        blocked as f32 / SAMPLES as f32
+    }
+
+    #[test]
+    fn moving_objects_blur_over_the_shutter_interval() {
+        // A sphere of radius 0.5 sliding from x = 0 to x = 2 covers the ray while its center is
+        // within 0.5 of it, for the first half of the interval
+        let mut rng = StdRng::seed_from_u64(27);
+        let (start, end) = (Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0));
+        let sphere = MovingSphere::new(start, end, 0.0, 1.0, 0.5, gray());
+        assert!((blocked_fraction(&sphere, &mut rng) - 0.5).abs() < 0.02);
+
+        let instance = Instance::moving(
+            Arc::new(Sphere::new(start, 0.5, gray())),
+            Transform::translate(start),
+            Transform::translate(end),
+            0.0,
+            1.0,
+        );
+        assert!((blocked_fraction(&instance, &mut rng) - 0.5).abs() < 0.02);
+
+        for object in [&sphere as &dyn Hittable, &instance] {
+            let bounds = object.bounding_box().unwrap();
+            assert!(bounds.min.x <= -0.5 && bounds.max.x >= 2.5);
+        }
+    }
+
+    #[test]
+    fn rotating_instances_sweep_along_an_arc() {
+        // A small ball a unit from the y axis, turned a quarter of the way round it, is an
+        // eighth of the way round halfway through rather than on the chord between its ends
+        let mut rng = StdRng::seed_from_u64(28);
+        let ball = Arc::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 0.1, gray()));
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let instance = Instance::moving(
+            ball,
+            Transform::identity(),
+            Transform::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                Rotation::new(axis, 90.0),
+                Vec3::new(1.0, 1.0, 1.0),
+            ),
+            0.0,
+            1.0,
+        );
+        let angle = std::f32::consts::FRAC_PI_4;
+        let on_arc = Point3::new(angle.cos(), 5.0, -angle.sin());
+        let on_chord = Point3::new(0.5, 5.0, -0.5);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+
+        let hit = instance.hit(&Ray::new(on_arc, down, 0.5), 1e-3, f32::MAX, &mut rng);
+        assert!((hit.unwrap().t - 4.9).abs() < 1e-3);
+        assert!(instance
+            .hit(&Ray::new(on_chord, down, 0.5), 1e-3, f32::MAX, &mut rng)
+            .is_none());
+        // At the ends of the interval it sits where the keyframes put it
+        let end = Point3::new(0.0, 5.0, -1.0);
+        assert!(instance
+            .hit(&Ray::new(end, down, 1.0), 1e-3, f32::MAX, &mut rng)
+            .is_some());
+        assert!(instance
+            .hit(&Ray::new(end, down, 0.0), 1e-3, f32::MAX, &mut rng)
+            .is_none());
+    }
+
+    /// A unit square in the plane z = 0 facing +z, with alpha rising from 0 to 1 along x.
+    fn fading_square(mode: AlphaMode) -> Cutout {
+        let square = Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            gray(),
+        );
+        let alpha = Gradient::new(
+            Arc::new(SolidColor::gray(0.0)),
+            Arc::new(SolidColor::gray(1.0)),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        Cutout::new(Box::new(square), Arc::new(alpha), mode)
+    }
+
+    fn down_at(x: f32) -> Ray {
+        Ray::new(Point3::new(x, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn cutouts_remove_the_surface_below_the_threshold() {
+        let mut rng = StdRng::seed_from_u64(37);
+        let square = fading_square(AlphaMode::Threshold(0.3));
+        for i in 0..100 {
+            let x = (i as f32 + 0.5) / 100.0;
+            let r = down_at(x);
+            let hit = square.hit(&r, 1e-3, f32::MAX, &mut rng);
+            assert_eq!(hit.is_some(), x >= 0.3, "at {}", x);
+            // Shadow rays see the same holes
+            let transmittance = square.transmittance(&r, 1e-3, f32::MAX, &mut rng);
+            assert_eq!(transmittance.x, if x >= 0.3 { 0.0 } else { 1.0 });
+        }
+    }
+
+    #[test]
+    fn partial_alpha_blocks_that_share_of_rays() {
+        const SAMPLES: usize = 20_000;
+        let mut rng = StdRng::seed_from_u64(38);
+        let square = fading_square(AlphaMode::Stochastic);
+        for x in [0.1, 0.5, 0.8] {
+            let r = down_at(x);
+            let hits = (0..SAMPLES)
+                .filter(|_| square.hit(&r, 1e-3, f32::MAX, &mut rng).is_some())
+                .count();
+            let passed = (0..SAMPLES)
+                .map(|_| square.transmittance(&r, 1e-3, f32::MAX, &mut rng).x)
+                .sum::<f32>();
+            assert!((hits as f32 / SAMPLES as f32 - x).abs() < 0.015);
+            assert!((passed / SAMPLES as f32 - (1.0 - x)).abs() < 0.015);
+        }
+    }
+
+    #[test]
+    fn holes_show_the_far_side_of_the_object() {
+        // A sphere cut away where z > 0 is seen from the front through its hole, on the inside
+        // of its back half
+        let mut rng = StdRng::seed_from_u64(39);
+        let alpha = Gradient::new(
+            Arc::new(SolidColor::gray(1.0)),
+            Arc::new(SolidColor::gray(0.0)),
+            Point3::new(0.0, 0.0, -1e-3),
+            Vec3::new(0.0, 0.0, 2e-3),
+        );
+        let bowl = Cutout::new(
+            Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, gray())),
+            Arc::new(alpha),
+            AlphaMode::Threshold(0.5),
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = bowl.hit(&r, 1e-3, f32::MAX, &mut rng).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-4 && !hit.front_face);
+    }
+
+    /// Each test shape built twice, from `material` and from a one-sided version of it.
+    fn shapes(material: Arc<dyn Material>) -> Vec<Box<dyn Hittable>> {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.2),
+            Vec3::new(2.0, 0.0, 0.3),
+            Vec3::new(0.0, 2.0, 0.0),
+            material.clone(),
+        );
+        let turned = Transform::new(
+            Vec3::new(0.3, 0.0, -0.2),
+            Rotation::new(Vec3::new(1.0, 1.0, 0.0), 70.0),
+            Vec3::new(0.5, 2.0, 1.0),
+        );
+        vec![
+            Box::new(quad.clone()),
+            Box::new(Triangle::new(
+                Point3::new(-1.0, -1.0, 0.0),
+                Point3::new(1.5, -0.5, 0.5),
+                Point3::new(0.0, 1.5, -0.5),
+                material.clone(),
+            )),
+            Box::new(Sphere::new(
+                Point3::new(0.2, 0.0, 0.0),
+                1.0,
+                material.clone(),
+            )),
+            Box::new(Instance::new(Arc::new(quad), turned)),
+        ]
+    }
+
+    #[test]
+    fn one_sided_surfaces_are_only_seen_from_the_front() {
+        let mut rng = StdRng::seed_from_u64(40);
+        let two_sided = shapes(gray());
+        let one_sided = shapes(Arc::new(OneSided::new(gray())));
+        for (both, front) in two_sided.iter().zip(&one_sided) {
+            let (mut fronts, mut backs) = (0, 0);
+            for _ in 0..5000 {
+                let origin = Point3::new(
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                );
+                let direction = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+                let r = Ray::new(origin, direction, 0.0);
+
+                // The nearest hit on the front, found by stepping past the back faces of the
+                // two-sided shape
+                let mut expected = None;
+                let mut t_min = 1e-3;
+                while let Some(hit) = both.hit(&r, t_min, f32::MAX, &mut rng) {
+                    assert!(hit.normal.dot(&r.direction) < 0.0);
+                    assert!(hit.shading_normal.dot(&r.direction) < 0.0);
+                    if hit.front_face {
+                        fronts += 1;
+                        expected = Some(hit.t);
+                        break;
+                    }
+                    backs += 1;
+                    t_min = hit.t + 1e-4;
+                }
+                let hit = front.hit(&r, 1e-3, f32::MAX, &mut rng);
+                match (hit, expected) {
+                    (Some(hit), Some(t)) => assert!(hit.front_face && (hit.t - t).abs() < 1e-4),
+                    (None, None) => {}
+                    (hit, t) => panic!("one-sided hit {:?} but expected {:?}", hit.map(|h| h.t), t),
+                }
+            }
+            assert!(fronts > 100 && backs > 100);
+        }
+    }
+
+    #[test]
+    fn front_faces_are_those_facing_away_from_the_ray() {
+        // Rays from inside a sphere hit its back, from outside its front
+        let mut rng = StdRng::seed_from_u64(41);
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, gray());
+        for _ in 0..1000 {
+            let direction = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+            let inside = Ray::new(Point3::new(0.1, -0.2, 0.3), direction, 0.0);
+            let hit = sphere.hit(&inside, 1e-3, f32::MAX, &mut rng).unwrap();
+            assert!(!hit.front_face && (hit.normal + hit.p).len() < 1e-4);
+            let outside = Ray::new(-3.0 * direction, direction, 0.0);
+            let hit = sphere.hit(&outside, 1e-3, f32::MAX, &mut rng).unwrap();
+            assert!(hit.front_face && (hit.normal - hit.p).len() < 1e-4);
+            assert!((hit.t - 2.0).abs() < 1e-4);
         }
     }
 }
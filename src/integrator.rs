@@ -0,0 +1,821 @@
+//! Ways of working out the light arriving along camera rays, from a full path tracer down to
+//! quick looks at a scene's geometry and materials for debugging it.
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::Light;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampling;
+use crate::scene::Scene;
+use crate::vec::{Color, Point3, Vec3};
+
+use rand::{Rng, RngCore};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// An algorithm for rendering a scene, one camera ray at a time.
+pub trait Integrator: Send + Sync {
+    /// The light arriving back along the camera ray `r`, drawing the integrator's own random
+    /// choices from `rng`. Light found for other pixels along the way goes in `splats`.
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, splats: &Splats) -> Color;
+
+    /// How many passes to make over the image, sharing the samples for each pixel between
+    /// them, for integrators that build up state such as photon maps from pass to pass.
+    fn passes(&self) -> u32 {
+        1
+    }
+
+    /// Gets ready for pass `pass` over the image, before any of its camera rays, drawing any
+    /// random choices from streams of the render's `seed`.
+    fn begin_pass(&mut self, _scene: &Scene, _pass: u32, _seed: u64) {}
+}
+
+/// Light added to pixels other than the one being rendered, such as light paths joined
+/// straight to the camera, which can be added to from many threads at once. Each pixel ends up
+/// with its total divided by the number of samples per pixel.
+pub struct Splats {
+    width: u32,
+    height: u32,
+    pixels: Vec<[AtomicU32; 3]>,
+}
+
+impl Splats {
+    pub fn new(width: u32, height: u32) -> Splats {
+        Splats {
+            width,
+            height,
+            pixels: (0..width * height).map(|_| Default::default()).collect(),
+        }
+    }
+
+    /// Adds `c` to the pixel at `(s, t)`, in the same terms as `Camera::get_ray`.
+    pub fn add(&self, (s, t): (f32, f32), c: Color) {
+        let x = ((s * self.width as f32) as u32).min(self.width - 1);
+        let y = ((t * self.height as f32) as u32).min(self.height - 1);
+        let pixel = &self.pixels[(y * self.width + x) as usize];
+        for (channel, value) in pixel.iter().zip([c.x, c.y, c.z]) {
+            // There's no atomic float addition, so swap in the new total unless another thread
+            // got there first
+            let mut current = channel.load(Ordering::Relaxed);
+            loop {
+                let total = (f32::from_bits(current) + value).to_bits();
+                match channel.compare_exchange_weak(
+                    current,
+                    total,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(actual) => current = actual,
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        let [r, g, b] = &self.pixels[(y * self.width + x) as usize];
+        let channel = |c: &AtomicU32| f32::from_bits(c.load(Ordering::Relaxed));
+        Color::new(channel(r), channel(g), channel(b))
+    }
+}
+
+/// The first thing `r` meets, either a surface or a point where it scatters off the scene's
+/// fog, with the details materials need filled in.
+pub fn intersect(scene: &Scene, r: &Ray, rng: &mut dyn RngCore) -> Option<HitRecord> {
+    let mut hit = scene.world.hit(r, 0.001, f32::MAX, rng);
+    if let Some(fog) = scene.fog() {
+        // Scattering off the fog before reaching the surface takes its place
+        let t_surface = hit.as_ref().map_or(f32::MAX, |hit| hit.t);
+        if let Some(scatter) = fog.sample(r, 0.001, t_surface, rng) {
+            hit = Some(scatter);
+        }
+    }
+    hit.map(|mut hit| {
+        hit.compute_uv_derivatives(r);
+        hit.shading_normal = hit.material.shading_normal(&hit);
+        hit
+    })
+}
+
+/// How much of the light arriving at `p` along `wi` from `distance` away gets past whatever is
+/// in the way.
+pub fn shadow_transmittance(
+    scene: &Scene,
+    p: &Point3,
+    wi: &Vec3,
+    distance: f32,
+    time: f32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let shadow = Ray::new(*p, *wi, time);
+    let t_max = distance - 0.001;
+    let mut transmittance = scene.world.transmittance(&shadow, 0.001, t_max, rng);
+    if let Some(fog) = scene.fog() {
+        transmittance *= fog.transmittance(&shadow, 0.001, t_max);
+    }
+    transmittance
+}
+
+/// The BSDF at `hit` for light arriving along `wi` and leaving along `wo`, with the cosine
+/// term for surfaces.
+fn scattering(hit: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+    let f = hit.material.eval(hit, wo, wi);
+    if hit.material.is_volumetric() {
+        f
+    } else {
+        f * wi.z.abs()
+    }
+}
+
+/// How many bounces of each kind a path may take before it's ended. Russian roulette ends most
+/// paths well before these; they're there to stop paths trapped between mirrors or inside glass.
+#[derive(Debug, Copy, Clone)]
+pub struct DepthLimits {
+    /// Bounces off rough and diffuse surfaces, and scattering in volumes.
+    pub diffuse: u32,
+    /// Bounces off mirrors and other delta reflectors.
+    pub specular: u32,
+    /// Passes into or out of surfaces such as glass.
+    pub transmission: u32,
+}
+
+/// Paths may be ended at random once they've taken this many bounces.
+pub const MIN_ROULETTE_DEPTH: u32 = 3;
+
+/// How a scattered ray was picked, for weighting light it finds on emitters against sampling
+/// them directly.
+#[derive(Debug, Copy, Clone)]
+struct Bounce {
+    /// Density with which the ray's direction was picked.
+    pdf: f32,
+    /// Normal of the surface the ray left, or `None` in a volume.
+    normal: Option<Vec3>,
+}
+
+/// Follows paths through the scene one bounce at a time, sampling a light at each with
+/// multiple importance sampling.
+pub struct PathTracer {
+    limits: DepthLimits,
+}
+
+impl PathTracer {
+    pub fn new(limits: DepthLimits) -> PathTracer {
+        PathTracer { limits }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut r = r;
+        // How the last bounce picked `r`, or `None` for the camera ray and after delta bounces,
+        // which always count light from emitters in full
+        let mut bounce: Option<Bounce> = None;
+        let mut depth = DepthLimits {
+            diffuse: 0,
+            specular: 0,
+            transmission: 0,
+        };
+
+        loop {
+            let hit = match intersect(scene, &r, rng) {
+                Some(hit) => hit,
+                None => {
+                    radiance += throughput * escaped_light(scene, &r, bounce);
+                    break;
+                }
+            };
+
+            let mut emitted = hit.material.emitted(&hit);
+            if let Some(bounce) = bounce {
+                if emitted.max_component() > 0.0 {
+                    let light_pdf = light_pdf(scene, &r.origin, &bounce, |light| {
+                        light.pdf(&r.origin, &hit.p)
+                    });
+                    emitted *= sampling::power_heuristic(bounce.pdf, light_pdf);
+                }
+            }
+            radiance += throughput * emitted;
+
+            let frame = hit.shading_frame();
+            let wo = frame.to_local(&-r.direction.unit_vector());
+            radiance += throughput * direct_light(scene, &hit, &frame, &wo, r.time, rng);
+
+            let sample = match hit.material.sample(&hit, &wo, rng) {
+                Some(sample) => sample,
+                None => break,
+            };
+            let volumetric = hit.material.is_volumetric();
+            let (count, limit) = if !volumetric && sample.wi.z * wo.z < 0.0 {
+                (&mut depth.transmission, self.limits.transmission)
+            } else if sample.delta {
+                (&mut depth.specular, self.limits.specular)
+            } else {
+                (&mut depth.diffuse, self.limits.diffuse)
+            };
+            if *count >= limit {
+                break;
+            }
+            *count += 1;
+
+            throughput *= if volumetric {
+                sample.f / sample.pdf
+            } else {
+                sample.weight()
+            };
+            bounce = if sample.delta {
+                None
+            } else {
+                Some(Bounce {
+                    pdf: sample.pdf,
+                    normal: light_normal(&hit),
+                })
+            };
+            r = Ray::new(hit.p, frame.to_world(&sample.wi), r.time);
+
+            // Paths carrying little light are ended at random, and the survivors made brighter
+            // to make up for the rest
+            if depth.diffuse + depth.specular + depth.transmission >= MIN_ROULETTE_DEPTH {
+                let survival = throughput.max_component();
+                if survival < 1.0 {
+                    if rng.gen::<f32>() >= survival {
+                        break;
+                    }
+                    throughput /= survival;
+                }
+            }
+        }
+        radiance
+    }
+}
+
+/// Light from the sky and any lights at infinity along a ray that escapes the scene, counted in
+/// full, as for rays that lights can't be sampled along.
+pub fn background_light(scene: &Scene, r: &Ray) -> Color {
+    escaped_light(scene, r, None)
+}
+
+/// Light from the sky and any lights at infinity along a ray that escapes the scene.
+fn escaped_light(scene: &Scene, r: &Ray, bounce: Option<Bounce>) -> Color {
+    let direction = r.direction.unit_vector();
+    let mut background = scene.background(r);
+    for (i, light) in scene.lights.iter().enumerate() {
+        let mut radiance = light.infinite_radiance(&direction);
+        if let Some(bounce) = bounce {
+            if radiance.max_component() > 0.0 {
+                let light_pdf = light.infinite_pdf(&direction)
+                    * scene.light_tree().pmf(&r.origin, bounce.normal.as_ref(), i);
+                radiance *= sampling::power_heuristic(bounce.pdf, light_pdf);
+            }
+        }
+        background += radiance;
+    }
+    background
+}
+
+/// The normal to pick lights by at `hit`, or `None` in a volume, which is lit from all sides.
+fn light_normal(hit: &HitRecord) -> Option<Vec3> {
+    if hit.material.is_volumetric() {
+        None
+    } else {
+        Some(hit.normal)
+    }
+}
+
+/// The density with which `direct_light` samples a direction from `p` that reaches the lights,
+/// where `pdf` gives each light's own density for it.
+fn light_pdf<F: Fn(&dyn Light) -> f32>(scene: &Scene, p: &Point3, bounce: &Bounce, pdf: F) -> f32 {
+    scene
+        .lights
+        .iter()
+        .enumerate()
+        .map(|(i, light)| {
+            let pdf = pdf(light.as_ref());
+            if pdf > 0.0 {
+                pdf * scene.light_tree().pmf(p, bounce.normal.as_ref(), i)
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+/// Light reaching `hit` straight from one of the scene's lights, picked by how much it's likely
+/// to contribute, and scattered towards `wo`, checked for occlusion with a shadow ray.
+fn direct_light(
+    scene: &Scene,
+    hit: &HitRecord,
+    frame: &Onb,
+    wo: &Vec3,
+    time: f32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let normal = light_normal(hit);
+    let (index, pmf) = match scene
+        .light_tree()
+        .sample(&hit.p, normal.as_ref(), rng.gen())
+    {
+        Some(picked) => picked,
+        None => return black,
+    };
+    let light = &scene.lights[index];
+    let sample = match light.sample(&hit.p, rng) {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let wi = frame.to_local(&sample.wi);
+    let f = scattering(hit, wo, &wi);
+    if f.max_component() <= 0.0 {
+        return black;
+    }
+
+    let transmittance = shadow_transmittance(scene, &hit.p, &sample.wi, sample.distance, time, rng);
+    let mut weight = sample.weight() / pmf;
+    if light.is_area() {
+        weight *= sampling::power_heuristic(pmf * sample.pdf, hit.material.pdf(hit, wo, &wi));
+    }
+    f * transmittance * weight
+}
+
+/// All the light reaching `hit` straight from emitters and the sky and scattered towards `wo`,
+/// for integrators that find indirect light some other way. A sampled light is weighted
+/// against a direction sampled from the BSDF, which is followed to whatever it finds.
+pub fn direct_lighting(
+    scene: &Scene,
+    hit: &HitRecord,
+    frame: &Onb,
+    wo: &Vec3,
+    time: f32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let radiance = direct_light(scene, hit, frame, wo, time, rng);
+    let sample = match hit.material.sample(hit, wo, rng) {
+        Some(sample) if !sample.delta => sample,
+        _ => return radiance,
+    };
+    let weight = if hit.material.is_volumetric() {
+        sample.f / sample.pdf
+    } else {
+        sample.weight()
+    };
+    let bounce = Bounce {
+        pdf: sample.pdf,
+        normal: light_normal(hit),
+    };
+    let r = Ray::new(hit.p, frame.to_world(&sample.wi), time);
+    let found = match intersect(scene, &r, rng) {
+        Some(next) => {
+            let mut emitted = next.material.emitted(&next);
+            if emitted.max_component() > 0.0 {
+                let light_pdf =
+                    light_pdf(scene, &hit.p, &bounce, |light| light.pdf(&hit.p, &next.p));
+                emitted *= sampling::power_heuristic(bounce.pdf, light_pdf);
+            }
+            emitted
+        }
+        None => escaped_light(scene, &r, Some(bounce)),
+    };
+    radiance + weight * found
+}
+
+/// Classic recursive ray tracing after Whitted: every light is sampled at each hit, and rays
+/// are only followed onwards off mirrors and through glass, so there's no indirect light. Fast
+/// and free of noise from diffuse bounces, for previewing lighting.
+pub struct Whitted {
+    max_depth: u32,
+}
+
+impl Whitted {
+    /// Follows up to `max_depth` mirror and glass bounces.
+    pub fn new(max_depth: u32) -> Whitted {
+        Whitted { max_depth }
+    }
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut r = r;
+        for _ in 0..=self.max_depth {
+            let hit = match intersect(scene, &r, rng) {
+                Some(hit) => hit,
+                None => {
+                    radiance += throughput * background_light(scene, &r);
+                    break;
+                }
+            };
+            radiance += throughput * hit.material.emitted(&hit);
+
+            let frame = hit.shading_frame();
+            let wo = frame.to_local(&-r.direction.unit_vector());
+            for light in scene.lights.iter() {
+                if let Some(sample) = light.sample(&hit.p, rng) {
+                    let f = scattering(&hit, &wo, &frame.to_local(&sample.wi));
+                    if f.max_component() > 0.0 {
+                        let transmittance = shadow_transmittance(
+                            scene,
+                            &hit.p,
+                            &sample.wi,
+                            sample.distance,
+                            r.time,
+                            rng,
+                        );
+                        radiance += throughput * f * transmittance * sample.weight();
+                    }
+                }
+            }
+
+            match hit.material.sample(&hit, &wo, rng) {
+                Some(sample) if sample.delta => {
+                    throughput *= sample.weight();
+                    r = Ray::new(hit.p, frame.to_world(&sample.wi), r.time);
+                }
+                _ => break,
+            }
+        }
+        radiance
+    }
+}
+
+/// Shades surfaces by how much of the hemisphere above them is open within `distance`, a quick
+/// way to see the shape of a scene without any lights.
+pub struct AmbientOcclusion {
+    distance: f32,
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f32) -> AmbientOcclusion {
+        AmbientOcclusion { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let hit = match scene.world.hit(&r, 0.001, f32::MAX, rng) {
+            Some(hit) => hit,
+            None => return Color::new(1.0, 1.0, 1.0),
+        };
+        // Cosine-weighted directions make the average come out as the cosine-weighted openness
+        let frame = Onb::from_w(&hit.normal);
+        let direction = frame.to_world(&sampling::cosine_hemisphere(rng.gen(), rng.gen()));
+        let probe = Ray::new(hit.p, direction, r.time);
+        if scene.world.hit(&probe, 0.001, self.distance, rng).is_some() {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+/// What a `DebugView` shows of the first surface each camera ray hits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugMode {
+    /// The shading normal, with each component mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// The texture coordinates in red and green.
+    Uv,
+    /// The distance to the camera, fading from white at the camera by about two thirds every
+    /// ten units.
+    Depth,
+    /// How much light the material reflects towards the camera overall, estimated by sampling
+    /// it, so it averages out over the samples of a pixel.
+    Albedo,
+    /// A colour for each material, to see which surfaces share one.
+    MaterialId,
+}
+
+/// Shows a single property of the scene's surfaces, with no lighting, for checking a scene is
+/// put together the way it's meant to be. Escaped rays are black.
+pub struct DebugView {
+    mode: DebugMode,
+}
+
+impl DebugView {
+    pub fn new(mode: DebugMode) -> DebugView {
+        DebugView { mode }
+    }
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let mut hit = match scene.world.hit(&r, 0.001, f32::MAX, rng) {
+            Some(hit) => hit,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        match self.mode {
+            DebugMode::Normals => {
+                hit.compute_uv_derivatives(&r);
+                let n = hit.material.shading_normal(&hit);
+                0.5 * (n + Vec3::new(1.0, 1.0, 1.0))
+            }
+            DebugMode::Uv => Color::new(hit.u, hit.v, 0.0),
+            DebugMode::Depth => {
+                let shade = (-hit.t / 10.0).exp();
+                Color::new(shade, shade, shade)
+            }
+            DebugMode::Albedo => {
+                hit.compute_uv_derivatives(&r);
+                hit.shading_normal = hit.material.shading_normal(&hit);
+                let frame = hit.shading_frame();
+                let wo = frame.to_local(&-r.direction.unit_vector());
+                match hit.material.sample(&hit, &wo, rng) {
+                    Some(sample) if hit.material.is_volumetric() => sample.f / sample.pdf,
+                    Some(sample) => sample.weight(),
+                    None => Color::new(0.0, 0.0, 0.0),
+                }
+            }
+            DebugMode::MaterialId => {
+                // Scramble the material's address into a colour
+                let address = Arc::as_ptr(&hit.material) as *const () as usize as u64;
+                let hash = address.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                let channel = |shift: u32| ((hash >> shift) & 0xff) as f32 / 255.0;
+                Color::new(channel(40), channel(48), channel(56))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hittable::{HittableList, Quad, Sphere};
+    use crate::light::PointLight;
+    use crate::material::{BsdfSample, Lambertian, Material, Metal};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::f32::consts::PI;
+
+    /// `inner` glowing evenly with `radiance` from both sides, so that light keeps bouncing
+    /// around inside a closed shape.
+    #[derive(Debug)]
+    struct Glowing {
+        inner: Arc<dyn Material>,
+        radiance: Color,
+    }
+
+    impl Material for Glowing {
+        fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+            self.inner.sample(rec, wo, rng)
+        }
+
+        fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+            self.inner.eval(rec, wo, wi)
+        }
+
+        fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+            self.inner.pdf(rec, wo, wi)
+        }
+
+        fn emitted(&self, _rec: &HitRecord) -> Color {
+            self.radiance
+        }
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    /// The inside of a unit sphere that glows with unit radiance and reflects like `inner`.
+    /// Every bounce lands on the sphere again, so light that's been reflected k times arrives
+    /// scaled by the k-th power of the albedo.
+    fn furnace(inner: Arc<dyn Material>) -> Scene {
+        let glowing = Glowing {
+            inner,
+            radiance: Color::new(1.0, 1.0, 1.0),
+        };
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(glowing));
+        Scene::new(HittableList::new(vec![Box::new(sphere)]), camera())
+    }
+
+    fn limits(diffuse: u32, specular: u32, transmission: u32) -> DepthLimits {
+        DepthLimits {
+            diffuse,
+            specular,
+            transmission,
+        }
+    }
+
+    /// The mean radiance `integrator` finds along `r` over `samples` paths.
+    fn mean_radiance(integrator: &dyn Integrator, scene: &Scene, r: Ray, samples: usize) -> f32 {
+        let mut rng = StdRng::seed_from_u64(48);
+        let splats = Splats::new(1, 1);
+        (0..samples)
+            .map(|_| integrator.radiance(r, scene, &mut rng, &splats).x)
+            .sum::<f32>()
+            / samples as f32
+    }
+
+    #[test]
+    fn russian_roulette_leaves_the_furnace_unbiased() {
+        // All the bounces together come to the geometric series 1 / (1 - albedo)
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 0.4, -0.8), 0.0);
+        for albedo in [0.5, 0.8] {
+            let scene = furnace(Arc::new(Lambertian::new(Color::new(
+                albedo, albedo, albedo,
+            ))));
+            let tracer = PathTracer::new(limits(1000, 1000, 1000));
+            let radiance = mean_radiance(&tracer, &scene, r, 40_000);
+            let expected = 1.0 / (1.0 - albedo);
+            assert!(
+                (radiance / expected - 1.0).abs() < 0.02,
+                "{} {}",
+                radiance,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn depth_limits_end_each_kind_of_bounce() {
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 0.4, -0.8), 0.0);
+        let albedo: f32 = 0.8;
+        let series = |bounces: i32| (0..=bounces).map(|k| albedo.powi(k)).sum::<f32>();
+        let color = Color::new(albedo, albedo, albedo);
+        let diffuse = furnace(Arc::new(Lambertian::new(color)));
+        let mirror = furnace(Arc::new(Metal::new(color)));
+
+        // Before roulette starts the paths are all alike
+        let radiance = mean_radiance(&PathTracer::new(limits(2, 0, 0)), &diffuse, r, 100);
+        assert!(
+            (radiance - series(2)).abs() < 1e-4,
+            "{} {}",
+            radiance,
+            series(2)
+        );
+        let radiance = mean_radiance(&PathTracer::new(limits(0, 2, 0)), &mirror, r, 100);
+        assert!(
+            (radiance - series(2)).abs() < 1e-4,
+            "{} {}",
+            radiance,
+            series(2)
+        );
+
+        // Each limit only counts its own kind of bounce
+        let radiance = mean_radiance(&PathTracer::new(limits(1000, 2, 1000)), &mirror, r, 100);
+        assert!(
+            (radiance - series(2)).abs() < 1e-4,
+            "{} {}",
+            radiance,
+            series(2)
+        );
+        let radiance = mean_radiance(&PathTracer::new(limits(2, 1000, 1000)), &diffuse, r, 100);
+        assert!(
+            (radiance - series(2)).abs() < 1e-4,
+            "{} {}",
+            radiance,
+            series(2)
+        );
+
+        // Paths that survive roulette are followed until they reach the limit
+        let radiance = mean_radiance(&PathTracer::new(limits(0, 6, 0)), &mirror, r, 40_000);
+        assert!(
+            (radiance / series(6) - 1.0).abs() < 0.01,
+            "{} {}",
+            radiance,
+            series(6)
+        );
+    }
+
+    /// A big horizontal square at `height`, which may as well go on forever.
+    fn plane(height: f32, material: Arc<dyn Material>) -> Box<dyn Hittable> {
+        Box::new(Quad::new(
+            Point3::new(-500.0, height, -500.0),
+            Vec3::new(1000.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1000.0),
+            material,
+        ))
+    }
+
+    #[test]
+    fn whitted_follows_mirrors_to_directly_lit_surfaces() {
+        // A diffuse floor lit by a point light of intensity I a height h above it has radiance
+        // ρ / π · I h / d³ at a distance d from the light
+        let floor = plane(0.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let mirror = plane(3.0, Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9))));
+        let light = PointLight::new(Point3::new(0.0, 2.0, 0.0), Color::new(4.0, 4.0, 4.0));
+        let scene = Scene::new(HittableList::new(vec![floor, mirror]), camera())
+            .with_lights(vec![Box::new(light)])
+            .with_background(None);
+        let d = 5.0f32.sqrt();
+        let lit = 0.5 / PI * 4.0 * 2.0 / (d * d * d);
+
+        let down = Ray::new(Point3::new(1.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let up = Ray::new(Point3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        for (depth, expected) in [(0, 0.0), (1, 0.9 * lit), (5, 0.9 * lit)] {
+            let radiance = mean_radiance(&Whitted::new(depth), &scene, up, 10);
+            assert!(
+                (radiance - expected).abs() < 1e-5,
+                "{} {}",
+                radiance,
+                expected
+            );
+        }
+        let radiance = mean_radiance(&Whitted::new(5), &scene, down, 10);
+        assert!((radiance - lit).abs() < 1e-5, "{} {}", radiance, lit);
+
+        // Without the mirror, nothing else reaches the floor for the path tracer to find
+        let floor = plane(0.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let light = PointLight::new(Point3::new(0.0, 2.0, 0.0), Color::new(4.0, 4.0, 4.0));
+        let scene = Scene::new(HittableList::new(vec![floor]), camera())
+            .with_lights(vec![Box::new(light)])
+            .with_background(None);
+        let radiance = mean_radiance(&PathTracer::new(limits(5, 5, 5)), &scene, down, 10);
+        assert!((radiance - lit).abs() < 1e-5, "{} {}", radiance, lit);
+    }
+
+    #[test]
+    fn ambient_occlusion_sees_the_open_sky() {
+        // Cosine-weighted probes from a floor reach a ceiling h above within a distance d when
+        // their cosine exceeds h / d, which leaves (h / d)² of them open
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let r = Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let open = Scene::new(
+            HittableList::new(vec![plane(0.0, material.clone())]),
+            camera(),
+        );
+        let radiance = mean_radiance(&AmbientOcclusion::new(2.0), &open, r, 100);
+        assert_eq!(radiance, 1.0);
+
+        let world = HittableList::new(vec![plane(0.0, material.clone()), plane(1.0, material)]);
+        let covered = Scene::new(world, camera());
+        for (distance, expected) in [(0.9, 1.0), (2.0, 0.25), (4.0, 0.0625)] {
+            let integrator = AmbientOcclusion::new(distance);
+            let radiance = mean_radiance(&integrator, &covered, r, 40_000);
+            assert!(
+                (radiance - expected).abs() < 0.01,
+                "{} {}",
+                radiance,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn debug_views_show_the_first_surface() {
+        let albedo = Color::new(0.2, 0.4, 0.6);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(albedo));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material.clone());
+        let twin = Sphere::new(Point3::new(0.0, 3.0, 0.0), 1.0, material);
+        let other = Sphere::new(
+            Point3::new(0.0, -3.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(albedo)),
+        );
+        let world = HittableList::new(vec![Box::new(sphere), Box::new(twin), Box::new(other)]);
+        let scene = Scene::new(world, camera());
+        let mut rng = StdRng::seed_from_u64(48);
+        let splats = Splats::new(1, 1);
+        let mut view = |mode: DebugMode, origin: Point3, direction: Vec3| {
+            let r = Ray::new(origin, direction, 0.0);
+            DebugView::new(mode).radiance(r, &scene, &mut rng, &splats)
+        };
+        let close = |a: Color, b: Color| (a - b).abs().max_component() < 1e-5;
+
+        // The unit sphere's normal is the point hit, at a distance of 5 - z along the ray
+        let z = 0.87f32.sqrt();
+        let (origin, forward) = (Point3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let normal = view(DebugMode::Normals, origin, forward);
+        assert!(
+            close(normal, Color::new(0.65, 0.6, 0.5 * (z + 1.0))),
+            "{:?}",
+            normal
+        );
+        let shade = (-(5.0 - z) / 10.0).exp();
+        let depth = view(DebugMode::Depth, origin, forward);
+        assert!(close(depth, Color::new(shade, shade, shade)), "{:?}", depth);
+        assert!(close(view(DebugMode::Albedo, origin, forward), albedo));
+
+        // Longitude runs from the back of the sphere, latitude up from the bottom
+        let front = view(DebugMode::Uv, Point3::new(0.0, 0.0, 5.0), forward);
+        let side = view(
+            DebugMode::Uv,
+            Point3::new(5.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+        assert!(close(front, Color::new(0.25, 0.5, 0.0)), "{:?}", front);
+        assert!(close(side, Color::new(0.5, 0.5, 0.0)), "{:?}", side);
+
+        // Spheres sharing a material share a colour, and misses are black
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let first = view(DebugMode::MaterialId, Point3::new(0.0, 1.5, 0.0), down);
+        let twin = view(DebugMode::MaterialId, Point3::new(0.0, 5.0, 0.0), down);
+        let other = view(DebugMode::MaterialId, Point3::new(0.0, -1.5, 0.0), down);
+        assert!(close(first, twin) && !close(first, other));
+        let miss = view(DebugMode::Normals, origin, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(miss.max_component(), 0.0);
+    }
+}
@@ -0,0 +1,1273 @@
+use crate::aabb::Aabb;
+use crate::hdr::HdrImage;
+use crate::hittable::{Hittable, Quad, Sphere, Triangle};
+use crate::light_tree::LightBounds;
+use crate::material::DiffuseLight;
+use crate::onb::Onb;
+use crate::sampling::{self, Distribution2D};
+use crate::vec::{Color, Point3, Vec3};
+
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Light arriving at a point from a sampled direction on a light.
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    /// Unit direction from the point towards the light.
+    pub wi: Vec3,
+    /// Distance to the light along `wi`, or `f32::MAX` for lights at infinity.
+    pub distance: f32,
+    /// Radiance arriving along `wi`, before any occlusion.
+    pub radiance: Color,
+    /// Solid angle density of picking `wi`, or 1 for lights that only shine from a single
+    /// direction.
+    pub pdf: f32,
+}
+
+impl LightSample {
+    /// The light's contribution with the sampling density divided out.
+    pub fn weight(&self) -> Color {
+        self.radiance / self.pdf
+    }
+}
+
+/// A ray of light leaving a light, for tracing light out into the scene.
+#[derive(Debug, Copy, Clone)]
+pub struct EmissionSample {
+    pub origin: Point3,
+    /// Unit direction the light leaves in.
+    pub direction: Vec3,
+    /// Normal of the light's surface at `origin`, or `None` for lights at a single point or at
+    /// infinity.
+    pub normal: Option<Vec3>,
+    /// Radiance leaving along `direction`, or intensity for lights at a single point.
+    pub radiance: Color,
+    /// Density of picking `origin` over the light's area, or 1 for lights at a single point.
+    pub pdf_position: f32,
+    /// Solid angle density of picking `direction`, or 1 for lights that only shine in a single
+    /// direction.
+    pub pdf_direction: f32,
+}
+
+/// A light source that isn't part of the scene geometry, reached only by sampling it from the
+/// points being shaded.
+pub trait Light: Send + Sync {
+    /// Samples a direction from `p` towards the light with random numbers from `rng`, or `None`
+    /// if it doesn't shine on `p`.
+    fn sample(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample>;
+
+    /// The solid angle density with which `sample` picks the direction from `p` to the point
+    /// `q`, or zero if `q` isn't on the light. Only area lights have a density.
+    fn pdf(&self, _p: &Point3, _q: &Point3) -> f32 {
+        0.0
+    }
+
+    /// Whether the light is an emitting surface in the scene that scattered rays can also hit,
+    /// in which case both ways of finding it have to be weighted against each other. Lights
+    /// surrounding the scene at infinity count, since rays that escape reach them.
+    fn is_area(&self) -> bool {
+        false
+    }
+
+    /// Radiance from a light at infinity arriving along a ray that escapes the scene in
+    /// `direction`.
+    fn infinite_radiance(&self, _direction: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// The solid angle density with which `sample` picks `direction` for lights at infinity.
+    fn infinite_pdf(&self, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    /// Whether the light surrounds the scene in every direction, taking the place of the
+    /// scene's background.
+    fn is_environment(&self) -> bool {
+        false
+    }
+
+    /// Bounds on where the light is and how it shines, for picking among many lights, or
+    /// `None` for lights at infinity.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    /// Picks a point on the light and a direction for light to leave it in, or `None` for
+    /// lights that can't start paths. Lights at infinity send light in from a disk facing them
+    /// across the scene, once `preprocess` has told them how big it is.
+    fn sample_emission(&self, _rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        None
+    }
+
+    /// The densities with which `sample_emission` picks the point `q` on the light, by area,
+    /// and `direction` leaving it, by solid angle. Both are zero if `q` isn't on the light.
+    /// Lights at infinity give the density over their disk, wherever `q` is.
+    fn emission_pdf(&self, _q: &Point3, _direction: &Vec3) -> (f32, f32) {
+        (0.0, 0.0)
+    }
+
+    /// Gets the light ready for a scene that fits inside `scene_bounds`.
+    fn preprocess(&mut self, _scene_bounds: &Aabb) {}
+
+    /// The normal of the light's surface at the point `q` on it, or `None` for lights without a
+    /// surface.
+    fn normal(&self, _q: &Point3) -> Option<Vec3> {
+        None
+    }
+}
+
+/// Shines equally in all directions from a single point, falling off with the inverse square
+/// of distance.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    /// `intensity` is the radiant intensity, so a surface one unit away facing the light
+    /// receives that much irradiance.
+    pub fn new(position: Point3, intensity: Color) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+
+    fn is_at(&self, q: &Point3) -> bool {
+        (*q - self.position).square_len() <= 1e-8
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3, _rng: &mut dyn RngCore) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.square_len();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            wi: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+            pdf: 1.0,
+        })
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        Some(EmissionSample {
+            origin: self.position,
+            direction: sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>()),
+            normal: None,
+            radiance: self.intensity,
+            pdf_position: 1.0,
+            pdf_direction: sampling::uniform_sphere_pdf(),
+        })
+    }
+
+    fn emission_pdf(&self, q: &Point3, _direction: &Vec3) -> (f32, f32) {
+        if self.is_at(q) {
+            (1.0, sampling::uniform_sphere_pdf())
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            Aabb::new(self.position, self.position),
+            4.0 * PI * self.intensity.max_component(),
+            Vec3::new(0.0, 1.0, 0.0),
+            -1.0,
+            0.0,
+            false,
+        ))
+    }
+}
+
+/// A point light restricted to a cone, which fades smoothly from full intensity at
+/// `falloff_start` degrees off its axis to nothing at `total_width` degrees.
+pub struct SpotLight {
+    light: PointLight,
+    axis: Vec3,
+    cos_total_width: f32,
+    cos_falloff_start: f32,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        target: Point3,
+        intensity: Color,
+        total_width: f32,
+        falloff_start: f32,
+    ) -> SpotLight {
+        let cos_total_width = total_width.to_radians().cos();
+        SpotLight {
+            light: PointLight::new(position, intensity),
+            axis: (target - position).unit_vector(),
+            cos_total_width,
+            cos_falloff_start: falloff_start.to_radians().cos().max(cos_total_width),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta <= self.cos_total_width {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            let t = (cos_theta - self.cos_total_width)
+                / (self.cos_falloff_start - self.cos_total_width);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        let mut sample = self.light.sample(p, rng)?;
+        let falloff = self.falloff(-sample.wi.dot(&self.axis));
+        if falloff <= 0.0 {
+            return None;
+        }
+        sample.radiance *= falloff;
+        Some(sample)
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let local =
+            sampling::uniform_cone(rng.gen::<f32>(), rng.gen::<f32>(), self.cos_total_width);
+        let direction = Onb::from_w(&self.axis).to_world(&local);
+        Some(EmissionSample {
+            origin: self.light.position,
+            direction,
+            normal: None,
+            radiance: self.light.intensity * self.falloff(direction.dot(&self.axis)),
+            pdf_position: 1.0,
+            pdf_direction: sampling::uniform_cone_pdf(self.cos_total_width),
+        })
+    }
+
+    fn emission_pdf(&self, q: &Point3, direction: &Vec3) -> (f32, f32) {
+        if !self.light.is_at(q) {
+            (0.0, 0.0)
+        } else if direction.dot(&self.axis) < self.cos_total_width {
+            (1.0, 0.0)
+        } else {
+            (1.0, sampling::uniform_cone_pdf(self.cos_total_width))
+        }
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let position = self.light.position;
+        let spread = self.cos_total_width.acos() - self.cos_falloff_start.acos();
+        Some(LightBounds::new(
+            Aabb::new(position, position),
+            4.0 * PI * self.light.intensity.max_component(),
+            self.axis,
+            self.cos_falloff_start,
+            spread.cos(),
+            false,
+        ))
+    }
+}
+
+/// Light from a distant source such as the sun, arriving from the same direction everywhere.
+/// Giving it an angular diameter spreads it over a small disk in the sky, which softens the
+/// edges of shadows and can be seen in reflections.
+pub struct DirectionalLight {
+    /// Unit direction towards the light.
+    direction: Vec3,
+    irradiance: Color,
+    cos_max: f32,
+    scene_sphere: Option<(Point3, f32)>,
+}
+
+impl DirectionalLight {
+    /// `direction` points towards the light, and `irradiance` is what a surface facing it
+    /// receives.
+    pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.unit_vector(),
+            irradiance,
+            cos_max: 1.0,
+            scene_sphere: None,
+        }
+    }
+
+    /// Spreads the light over a disk `degrees` across, like the sun's half a degree.
+    pub fn angular_diameter(mut self, degrees: f32) -> DirectionalLight {
+        self.cos_max = (0.5 * degrees).to_radians().cos();
+        self
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        if self.cos_max >= 1.0 {
+            return Some(LightSample {
+                wi: self.direction,
+                distance: f32::MAX,
+                radiance: self.irradiance,
+                pdf: 1.0,
+            });
+        }
+
+        // Uniform radiance over the disk, scaled so the total irradiance is unchanged
+        let local = sampling::uniform_cone(rng.gen::<f32>(), rng.gen::<f32>(), self.cos_max);
+        let pdf = sampling::uniform_cone_pdf(self.cos_max);
+        Some(LightSample {
+            wi: Onb::from_w(&self.direction).to_world(&local),
+            distance: f32::MAX,
+            radiance: self.irradiance * pdf,
+            pdf,
+        })
+    }
+
+    fn is_area(&self) -> bool {
+        self.cos_max < 1.0
+    }
+
+    fn infinite_radiance(&self, direction: &Vec3) -> Color {
+        if self.is_area() && direction.dot(&self.direction) >= self.cos_max {
+            self.irradiance * sampling::uniform_cone_pdf(self.cos_max)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn infinite_pdf(&self, direction: &Vec3) -> f32 {
+        if self.is_area() && direction.dot(&self.direction) >= self.cos_max {
+            sampling::uniform_cone_pdf(self.cos_max)
+        } else {
+            0.0
+        }
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let (center, _) = self.scene_sphere?;
+        let sample = self.sample(&center, rng)?;
+        infinite_emission(self.scene_sphere, &sample, rng)
+    }
+
+    fn emission_pdf(&self, _q: &Point3, direction: &Vec3) -> (f32, f32) {
+        infinite_emission_pdf(self.scene_sphere, self.infinite_pdf(&-*direction))
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.scene_sphere = Some(scene_bounds.bounding_sphere());
+    }
+}
+
+/// Light from a light at infinity, arriving as `sample`, sent into the scene from a point
+/// picked uniformly on a disk that faces it across the scene's bounding sphere
+/// `(center, radius)`.
+pub fn infinite_emission(
+    scene_sphere: Option<(Point3, f32)>,
+    sample: &LightSample,
+    rng: &mut dyn RngCore,
+) -> Option<EmissionSample> {
+    let (center, radius) = scene_sphere?;
+    if radius <= 0.0 || sample.pdf <= 0.0 {
+        return None;
+    }
+    let (x, y) = sampling::concentric_disk(rng.gen::<f32>(), rng.gen::<f32>());
+    let offset = Onb::from_w(&sample.wi).to_world(&Vec3::new(x, y, 1.0));
+    Some(EmissionSample {
+        origin: center + radius * offset,
+        direction: -sample.wi,
+        normal: None,
+        radiance: sample.radiance,
+        pdf_position: 1.0 / (PI * radius * radius),
+        pdf_direction: sample.pdf,
+    })
+}
+
+/// The densities with which `infinite_emission` picks its point and a direction whose
+/// opposite the light samples with density `pdf_direction`.
+pub fn infinite_emission_pdf(
+    scene_sphere: Option<(Point3, f32)>,
+    pdf_direction: f32,
+) -> (f32, f32) {
+    match scene_sphere {
+        Some((_, radius)) if radius > 0.0 => (1.0 / (PI * radius * radius), pdf_direction),
+        _ => (0.0, 0.0),
+    }
+}
+
+/// The sample for light from the point `q` with normal `normal` on an emitter of the given
+/// `area`, picked uniformly by area, converted to a density over solid angle at `p`. Only the
+/// front of the emitter shines.
+fn area_sample(
+    p: &Point3,
+    q: Point3,
+    normal: &Vec3,
+    area: f32,
+    radiance: Color,
+) -> Option<LightSample> {
+    let pdf = area_pdf(p, &q, normal, area);
+    if pdf <= 0.0 {
+        return None;
+    }
+    let to_light = q - *p;
+    let distance = to_light.len();
+    Some(LightSample {
+        wi: to_light / distance,
+        distance,
+        radiance,
+        pdf,
+    })
+}
+
+/// The solid angle density at `p` of picking `q` uniformly by area, or zero from behind.
+fn area_pdf(p: &Point3, q: &Point3, normal: &Vec3, area: f32) -> f32 {
+    let to_light = *q - *p;
+    let distance_squared = to_light.square_len();
+    let cos_light = -normal.dot(&to_light) / distance_squared.sqrt();
+    if cos_light <= 0.0 || distance_squared <= 0.0 {
+        0.0
+    } else {
+        distance_squared / (cos_light * area)
+    }
+}
+
+/// Light leaving the point `origin` on an emitter's front face, in a direction picked in
+/// proportion to its cosine with `normal`, as suits diffuse emitters.
+fn surface_emission(
+    origin: Point3,
+    normal: Vec3,
+    radiance: Color,
+    pdf_position: f32,
+    rng: &mut dyn RngCore,
+) -> EmissionSample {
+    let local = sampling::cosine_hemisphere(rng.gen::<f32>(), rng.gen::<f32>());
+    EmissionSample {
+        origin,
+        direction: Onb::from_w(&normal).to_world(&local),
+        normal: Some(normal),
+        radiance,
+        pdf_position,
+        pdf_direction: sampling::cosine_hemisphere_pdf(local.z),
+    }
+}
+
+/// A glowing sphere, sampled uniformly over the cone of directions it covers as seen from the
+/// shaded point. Add `object` to the scene so that it can be seen and hit.
+pub struct SphereLight {
+    center: Point3,
+    radius: f32,
+    radiance: Color,
+}
+
+impl SphereLight {
+    pub fn new(center: Point3, radius: f32, radiance: Color) -> SphereLight {
+        SphereLight {
+            center,
+            radius,
+            radiance,
+        }
+    }
+
+    /// The sphere itself, which glows from outside.
+    pub fn object(&self) -> Box<dyn Hittable> {
+        Box::new(Sphere::new(
+            self.center,
+            self.radius,
+            Arc::new(DiffuseLight::new(self.radiance)),
+        ))
+    }
+
+    /// The cosine of the half-angle of the cone the sphere covers from `p`, or `None` from
+    /// inside, where the sphere shows no light.
+    fn cos_max(&self, p: &Point3) -> Option<f32> {
+        let distance_squared = (self.center - *p).square_len();
+        let sin_squared = self.radius * self.radius / distance_squared;
+        if sin_squared >= 1.0 {
+            None
+        } else {
+            Some((1.0 - sin_squared).sqrt())
+        }
+    }
+
+    fn on_surface(&self, q: &Point3) -> bool {
+        ((*q - self.center).len() - self.radius).abs() <= 1e-3 * self.radius
+    }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        let cos_max = self.cos_max(p)?;
+        let to_center = self.center - *p;
+        let local = sampling::uniform_cone(rng.gen::<f32>(), rng.gen::<f32>(), cos_max);
+        let wi = Onb::from_w(&to_center.unit_vector()).to_world(&local);
+
+        // The near intersection with the sphere, which the sampled direction can only just
+        // miss through rounding
+        let b = wi.dot(&to_center);
+        let discriminant = (self.radius * self.radius - (to_center.square_len() - b * b)).max(0.0);
+        Some(LightSample {
+            wi,
+            distance: b - discriminant.sqrt(),
+            radiance: self.radiance,
+            pdf: sampling::uniform_cone_pdf(cos_max),
+        })
+    }
+
+    fn pdf(&self, p: &Point3, q: &Point3) -> f32 {
+        if !self.on_surface(q) {
+            return 0.0;
+        }
+        match self.cos_max(p) {
+            Some(cos_max) => sampling::uniform_cone_pdf(cos_max),
+            None => 0.0,
+        }
+    }
+
+    fn is_area(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(LightBounds::new(
+            Aabb::centered(self.center, radius),
+            PI * self.area() * self.radiance.max_component(),
+            Vec3::new(0.0, 1.0, 0.0),
+            -1.0,
+            0.0,
+            false,
+        ))
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let normal = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+        Some(surface_emission(
+            self.center + self.radius * normal,
+            normal,
+            self.radiance,
+            1.0 / self.area(),
+            rng,
+        ))
+    }
+
+    fn emission_pdf(&self, q: &Point3, direction: &Vec3) -> (f32, f32) {
+        match self.normal(q) {
+            Some(normal) => (
+                1.0 / self.area(),
+                sampling::cosine_hemisphere_pdf(normal.dot(direction)),
+            ),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn normal(&self, q: &Point3) -> Option<Vec3> {
+        if self.on_surface(q) {
+            Some((*q - self.center) / self.radius)
+        } else {
+            None
+        }
+    }
+}
+
+/// How a `QuadLight` picks points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuadSampling {
+    /// Uniformly over the quad's area, which is cheap but noisy close to large lights.
+    Area,
+    /// Uniformly over the solid angle a rectangle covers, as described by Ureña et al. in "An
+    /// Area-Preserving Parametrization for Spherical Rectangles". Quads that aren't rectangles
+    /// fall back to `Area`.
+    SolidAngle,
+}
+
+/// A glowing parallelogram, such as a softbox or window, which shines from the side its
+/// `Quad` faces. Add `object` to the scene so that it can be seen and hit.
+pub struct QuadLight {
+    corner: Point3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    normal: Vec3,
+    area: f32,
+    radiance: Color,
+    sampling: QuadSampling,
+}
+
+impl QuadLight {
+    pub fn new(corner: Point3, edge_u: Vec3, edge_v: Vec3, radiance: Color) -> QuadLight {
+        let n = edge_u.cross(&edge_v);
+        let is_rectangle = edge_u.dot(&edge_v).abs() <= 1e-4 * edge_u.len() * edge_v.len();
+        QuadLight {
+            corner,
+            edge_u,
+            edge_v,
+            normal: n.unit_vector(),
+            area: n.len(),
+            radiance,
+            sampling: if is_rectangle {
+                QuadSampling::SolidAngle
+            } else {
+                QuadSampling::Area
+            },
+        }
+    }
+
+    pub fn sampling(mut self, sampling: QuadSampling) -> QuadLight {
+        if self.sampling == QuadSampling::SolidAngle {
+            self.sampling = sampling;
+        }
+        self
+    }
+
+    /// The quad itself, which glows from the front.
+    pub fn object(&self) -> Box<dyn Hittable> {
+        Box::new(Quad::new(
+            self.corner,
+            self.edge_u,
+            self.edge_v,
+            Arc::new(DiffuseLight::new(self.radiance)),
+        ))
+    }
+
+    /// The rectangle as seen from `p`, if it's worth sampling by solid angle there.
+    fn spherical_rect(&self, p: &Point3) -> Option<SphericalRect> {
+        if self.sampling != QuadSampling::SolidAngle {
+            return None;
+        }
+        SphericalRect::new(p, &self.corner, &self.edge_u, &self.edge_v)
+    }
+
+    fn contains(&self, q: &Point3) -> bool {
+        let d = *q - self.corner;
+        let tolerance = 1e-3 * self.area.sqrt();
+        if self.normal.dot(&d).abs() > tolerance {
+            return false;
+        }
+        // Coordinates along the edges, which needn't be perpendicular
+        let w = self.normal / self.area;
+        let u = w.dot(&d.cross(&self.edge_v));
+        let v = w.dot(&self.edge_u.cross(&d));
+        (-1e-3..=1.001).contains(&u) && (-1e-3..=1.001).contains(&v)
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        // Nothing shines on points behind the light
+        if self.normal.dot(&(*p - self.corner)) <= 0.0 {
+            return None;
+        }
+        let (u0, u1) = (rng.gen::<f32>(), rng.gen::<f32>());
+        match self.spherical_rect(p) {
+            Some(rect) => {
+                let to_light = rect.sample(u0, u1) - *p;
+                let distance = to_light.len();
+                Some(LightSample {
+                    wi: to_light / distance,
+                    distance,
+                    radiance: self.radiance,
+                    pdf: 1.0 / rect.solid_angle,
+                })
+            }
+            None => area_sample(
+                p,
+                self.corner + u0 * self.edge_u + u1 * self.edge_v,
+                &self.normal,
+                self.area,
+                self.radiance,
+            ),
+        }
+    }
+
+    fn pdf(&self, p: &Point3, q: &Point3) -> f32 {
+        if !self.contains(q) || self.normal.dot(&(*p - self.corner)) <= 0.0 {
+            return 0.0;
+        }
+        match self.spherical_rect(p) {
+            Some(rect) => 1.0 / rect.solid_angle,
+            None => area_pdf(p, q, &self.normal, self.area),
+        }
+    }
+
+    fn is_area(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let far = self.corner + self.edge_u + self.edge_v;
+        let bounds = Aabb::new(self.corner.min(&far), self.corner.max(&far)).union(&Aabb::new(
+            (self.corner + self.edge_u).min(&(self.corner + self.edge_v)),
+            (self.corner + self.edge_u).max(&(self.corner + self.edge_v)),
+        ));
+        Some(LightBounds::new(
+            bounds,
+            PI * self.area * self.radiance.max_component(),
+            self.normal,
+            1.0,
+            0.0,
+            false,
+        ))
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let origin = self.corner + rng.gen::<f32>() * self.edge_u + rng.gen::<f32>() * self.edge_v;
+        Some(surface_emission(
+            origin,
+            self.normal,
+            self.radiance,
+            1.0 / self.area,
+            rng,
+        ))
+    }
+
+    fn emission_pdf(&self, q: &Point3, direction: &Vec3) -> (f32, f32) {
+        if self.contains(q) {
+            (
+                1.0 / self.area,
+                sampling::cosine_hemisphere_pdf(self.normal.dot(direction)),
+            )
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn normal(&self, q: &Point3) -> Option<Vec3> {
+        if self.contains(q) {
+            Some(self.normal)
+        } else {
+            None
+        }
+    }
+}
+
+/// A rectangle projected onto the unit sphere around a point, set up for sampling uniformly by
+/// solid angle.
+struct SphericalRect {
+    origin: Point3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+    z0: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    solid_angle: f32,
+}
+
+impl SphericalRect {
+    /// Returns `None` when the rectangle covers too small a solid angle to sample accurately,
+    /// where sampling by area does just as well.
+    #[allow(clippy::many_single_char_names)]
+    fn new(p: &Point3, corner: &Point3, edge_u: &Vec3, edge_v: &Vec3) -> Option<SphericalRect> {
+        let x = edge_u.unit_vector();
+        let y = edge_v.unit_vector();
+        let mut z = x.cross(&y);
+        let d = *corner - *p;
+        let x0 = d.dot(&x);
+        let y0 = d.dot(&y);
+        let mut z0 = d.dot(&z);
+        if z0 > 0.0 {
+            z = -z;
+            z0 = -z0;
+        }
+        let x1 = x0 + edge_u.len();
+        let y1 = y0 + edge_v.len();
+
+        let v00 = Vec3::new(x0, y0, z0);
+        let v01 = Vec3::new(x0, y1, z0);
+        let v10 = Vec3::new(x1, y0, z0);
+        let v11 = Vec3::new(x1, y1, z0);
+        let n0 = v00.cross(&v10).unit_vector();
+        let n1 = v10.cross(&v11).unit_vector();
+        let n2 = v11.cross(&v01).unit_vector();
+        let n3 = v01.cross(&v00).unit_vector();
+        let angle = |a: &Vec3, b: &Vec3| (-a.dot(b)).clamp(-1.0, 1.0).acos();
+        let g0 = angle(&n0, &n1);
+        let g1 = angle(&n1, &n2);
+        let g2 = angle(&n2, &n3);
+        let g3 = angle(&n3, &n0);
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle.is_nan() || solid_angle <= 1e-4 {
+            return None;
+        }
+
+        Some(SphericalRect {
+            origin: *p,
+            x,
+            y,
+            z,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            b0: n0.z,
+            b1: n2.z,
+            k,
+            solid_angle,
+        })
+    }
+
+    fn sample(&self, u0: f32, u1: f32) -> Point3 {
+        // Pick the x coordinate so the strip to its left holds a fraction u0 of the solid angle
+        let au = u0 * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = (1.0f32.copysign(fu) / (fu * fu + self.b0 * self.b0).sqrt()).clamp(-1.0, 1.0);
+        let xu = (-(cu * self.z0) / (1.0 - cu * cu).max(1e-12).sqrt()).clamp(self.x0, self.x1);
+
+        // Then y uniformly in the projected height of that strip
+        let d = xu.hypot(self.z0);
+        let h0 = self.y0 / d.hypot(self.y0);
+        let h1 = self.y1 / d.hypot(self.y1);
+        let hv = h0 + u1 * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-6 {
+            (hv * d) / (1.0 - hv * hv).sqrt()
+        } else {
+            self.y1
+        };
+        self.origin + xu * self.x + yv * self.y + self.z0 * self.z
+    }
+}
+
+/// A glowing triangle, sampled uniformly by area, which shines from the side its `Triangle`
+/// faces. Add `object` to the scene so that it can be seen and hit.
+pub struct TriangleLight {
+    a: Point3,
+    b: Point3,
+    c: Point3,
+    normal: Vec3,
+    area: f32,
+    radiance: Color,
+}
+
+impl TriangleLight {
+    pub fn new(a: Point3, b: Point3, c: Point3, radiance: Color) -> TriangleLight {
+        let n = (b - a).cross(&(c - a));
+        TriangleLight {
+            a,
+            b,
+            c,
+            normal: n.unit_vector(),
+            area: 0.5 * n.len(),
+            radiance,
+        }
+    }
+
+    /// The triangle itself, which glows from the front.
+    pub fn object(&self) -> Box<dyn Hittable> {
+        Box::new(Triangle::new(
+            self.a,
+            self.b,
+            self.c,
+            Arc::new(DiffuseLight::new(self.radiance)),
+        ))
+    }
+
+    /// A point picked uniformly over the triangle.
+    fn sample_point(&self, rng: &mut dyn RngCore) -> Point3 {
+        let su0 = rng.gen::<f32>().sqrt();
+        let b0 = 1.0 - su0;
+        let b1 = rng.gen::<f32>() * su0;
+        b0 * self.a + b1 * self.b + (1.0 - b0 - b1) * self.c
+    }
+
+    fn contains(&self, q: &Point3) -> bool {
+        if self.normal.dot(&(*q - self.a)).abs() > 1e-3 * self.area.sqrt() {
+            return false;
+        }
+        // Each edge must have q on its inner side
+        let tolerance = -1e-4 * self.area;
+        [(self.a, self.b), (self.b, self.c), (self.c, self.a)]
+            .iter()
+            .all(|(from, to)| {
+                0.5 * (*to - *from).cross(&(*q - *from)).dot(&self.normal) >= tolerance
+            })
+    }
+}
+
+impl Light for TriangleLight {
+    fn sample(&self, p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        area_sample(
+            p,
+            self.sample_point(rng),
+            &self.normal,
+            self.area,
+            self.radiance,
+        )
+    }
+
+    fn pdf(&self, p: &Point3, q: &Point3) -> f32 {
+        if self.contains(q) {
+            area_pdf(p, q, &self.normal, self.area)
+        } else {
+            0.0
+        }
+    }
+
+    fn is_area(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            Aabb::new(
+                self.a.min(&self.b).min(&self.c),
+                self.a.max(&self.b).max(&self.c),
+            ),
+            PI * self.area * self.radiance.max_component(),
+            self.normal,
+            1.0,
+            0.0,
+            false,
+        ))
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        Some(surface_emission(
+            self.sample_point(rng),
+            self.normal,
+            self.radiance,
+            1.0 / self.area,
+            rng,
+        ))
+    }
+
+    fn emission_pdf(&self, q: &Point3, direction: &Vec3) -> (f32, f32) {
+        if self.contains(q) {
+            (
+                1.0 / self.area,
+                sampling::cosine_hemisphere_pdf(self.normal.dot(direction)),
+            )
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn normal(&self, q: &Point3) -> Option<Vec3> {
+        if self.contains(q) {
+            Some(self.normal)
+        } else {
+            None
+        }
+    }
+}
+
+/// Light from an equirectangular HDR image wrapped around the scene at infinity, with `+y` up
+/// and the middle of the image looking along `-z`. Directions are importance sampled in
+/// proportion to the image's luminance, so that a small bright sun in the map is found by
+/// shadow rays rather than left to scattered rays to stumble on.
+pub struct EnvironmentLight {
+    image: HdrImage,
+    scale: f32,
+    distribution: Distribution2D,
+    scene_sphere: Option<(Point3, f32)>,
+}
+
+impl EnvironmentLight {
+    /// Scales the image's values by `scale`.
+    pub fn new(image: HdrImage, scale: f32) -> EnvironmentLight {
+        let (width, height) = (image.width(), image.height());
+        // Rows near the poles cover less of the sphere, so weight them down by sin(theta)
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            for x in 0..width {
+                func.push(image.get_pixel(x, y).luminance().max(0.0) * sin_theta);
+            }
+        }
+        EnvironmentLight {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            scale,
+            scene_sphere: None,
+        }
+    }
+
+    /// The image coordinates of a unit direction, with `v` running down from the top.
+    fn direction_to_uv(direction: &Vec3) -> (f32, f32) {
+        let phi = direction.x.atan2(-direction.z);
+        (
+            (0.5 + phi / (2.0 * PI)).rem_euclid(1.0),
+            direction.y.clamp(-1.0, 1.0).acos() / PI,
+        )
+    }
+
+    fn lookup(&self, u: f32, v: f32) -> Color {
+        let x = ((u * self.image.width() as f32) as usize).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f32) as usize).min(self.image.height() - 1);
+        self.scale * self.image.get_pixel(x, y)
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn is_environment(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, _p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        let ((u, v), uv_pdf) = self.distribution.sample(rng.gen::<f32>(), rng.gen::<f32>());
+        let pdf = sampling::equirectangular_pdf(uv_pdf, v);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let theta = PI * v;
+        let phi = 2.0 * PI * (u - 0.5);
+        Some(LightSample {
+            wi: Vec3::new(
+                theta.sin() * phi.sin(),
+                theta.cos(),
+                -theta.sin() * phi.cos(),
+            ),
+            distance: f32::MAX,
+            radiance: self.lookup(u, v),
+            pdf,
+        })
+    }
+
+    fn is_area(&self) -> bool {
+        true
+    }
+
+    fn infinite_radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = EnvironmentLight::direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    fn infinite_pdf(&self, direction: &Vec3) -> f32 {
+        let (u, v) = EnvironmentLight::direction_to_uv(direction);
+        sampling::equirectangular_pdf(self.distribution.pdf(u, v), v)
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let (center, _) = self.scene_sphere?;
+        let sample = self.sample(&center, rng)?;
+        infinite_emission(self.scene_sphere, &sample, rng)
+    }
+
+    fn emission_pdf(&self, _q: &Point3, direction: &Vec3) -> (f32, f32) {
+        infinite_emission_pdf(self.scene_sphere, self.infinite_pdf(&-*direction))
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.scene_sphere = Some(scene_bounds.bounding_sphere());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// The share of a spot light's intensity at an angle with cosine `cos_theta` off its axis,
+    /// with full intensity within 20 degrees easing to nothing at 40.
+    fn spot_falloff(cos_theta: f32) -> f32 {
+        let (cos_inner, cos_outer) = (20.0f32.to_radians().cos(), 40.0f32.to_radians().cos());
+        let t = ((cos_theta - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    #[test]
+    fn point_and_spot_lights_follow_their_falloff() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let intensity = Color::new(2.0, 3.0, 4.0);
+        let position = Point3::new(0.0, 3.0, 0.0);
+        let point = PointLight::new(position, intensity);
+        let sample = point
+            .sample(&Point3::new(0.0, -1.0, 0.0), &mut rng)
+            .unwrap();
+        assert!((sample.radiance - intensity / 16.0).abs().max_component() < 1e-6);
+        assert!((sample.wi - Vec3::new(0.0, 1.0, 0.0)).len() < 1e-6 && sample.distance == 4.0);
+
+        let spot = SpotLight::new(position, Point3::new(0.0, 0.0, 0.0), intensity, 40.0, 20.0);
+        for degrees in [0.0f32, 10.0, 25.0, 30.0, 35.0, 39.0, 41.0, 60.0, 120.0] {
+            let angle = degrees.to_radians();
+            let p = position + 2.0 * Vec3::new(angle.sin(), -angle.cos(), 0.0);
+            let expected = spot_falloff(angle.cos());
+            match spot.sample(&p, &mut rng) {
+                Some(sample) => {
+                    let falloff = sample.radiance.x / (intensity.x / 4.0);
+                    assert!((falloff - expected).abs() < 1e-4, "at {} degrees", degrees);
+                }
+                None => assert_eq!(expected, 0.0, "at {} degrees", degrees),
+            }
+        }
+
+        // Light sent out from the spot carries its intensity integrated over the cone
+        const STEPS: usize = 10_000;
+        let power = (0..STEPS)
+            .map(|i| {
+                let cos_theta = -1.0 + (i as f32 + 0.5) * 2.0 / STEPS as f32;
+                intensity.x * spot_falloff(cos_theta)
+            })
+            .sum::<f32>()
+            * 2.0
+            * PI
+            * (2.0 / STEPS as f32);
+        const SAMPLES: usize = 100_000;
+        let mut emitted = 0.0;
+        for _ in 0..SAMPLES {
+            let sample = spot.sample_emission(&mut rng).unwrap();
+            let (pdf_position, pdf_direction) =
+                spot.emission_pdf(&sample.origin, &sample.direction);
+            assert!(pdf_position == sample.pdf_position && pdf_direction == sample.pdf_direction);
+            emitted += sample.radiance.x / (pdf_position * pdf_direction) / SAMPLES as f32;
+        }
+        assert!(
+            (emitted / power - 1.0).abs() < 0.01,
+            "{} {}",
+            emitted,
+            power
+        );
+    }
+
+    #[test]
+    fn directional_lights_deliver_their_irradiance() {
+        // Spread evenly over a disk of half-angle θ, the light falling on a surface facing it
+        // is scaled by the mean cosine over the disk, (1 + cos θ) / 2
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(43);
+        let direction = Vec3::new(0.3, 1.0, -0.2).into_unit_vector();
+        let irradiance = Color::new(1.0, 2.0, 3.0);
+        let sharp = DirectionalLight::new(direction, irradiance);
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let sample = sharp.sample(&p, &mut rng).unwrap();
+        assert!((sample.wi - direction).len() < 1e-6 && sample.pdf == 1.0);
+        assert!((sample.weight() - irradiance).abs().max_component() < 1e-6);
+        assert!(!sharp.is_area());
+
+        let soft = DirectionalLight::new(direction, irradiance).angular_diameter(60.0);
+        let cos_max = 30.0f32.to_radians().cos();
+        let mut received = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..SAMPLES {
+            let sample = soft.sample(&p, &mut rng).unwrap();
+            assert!(sample.wi.dot(&direction) >= cos_max - 1e-5);
+            assert!((soft.infinite_pdf(&sample.wi) - sample.pdf).abs() < 1e-4 * sample.pdf);
+            let radiance = soft.infinite_radiance(&sample.wi);
+            assert!((radiance - sample.radiance).abs().max_component() < 1e-4);
+            received += sample.weight() * sample.wi.dot(&direction) / SAMPLES as f32;
+        }
+        let expected = irradiance * (0.5 * (1.0 + cos_max));
+        assert!((received - expected).abs().max_component() < 2e-3);
+        assert_eq!(soft.infinite_pdf(&-direction), 0.0);
+    }
+
+    type AreaLight = (&'static str, Box<dyn Hittable>, Box<dyn Light>);
+
+    /// Area lights overhead facing down, each after the object that shows it.
+    fn area_lights() -> Vec<AreaLight> {
+        let radiance = Color::new(1.0, 1.0, 1.0);
+        let sphere = SphereLight::new(Point3::new(0.5, 2.0, 0.3), 0.6, radiance);
+        let rect = || {
+            QuadLight::new(
+                Point3::new(-0.5, 1.5, -0.5),
+                Vec3::new(1.2, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                radiance,
+            )
+        };
+        let by_area = rect().sampling(QuadSampling::Area);
+        let slanted = QuadLight::new(
+            Point3::new(-0.5, 1.5, -0.5),
+            Vec3::new(1.2, 0.0, 0.0),
+            Vec3::new(0.4, 0.0, 1.0),
+            radiance,
+        );
+        let triangle = TriangleLight::new(
+            Point3::new(-0.5, 1.2, -0.5),
+            Point3::new(0.5, 1.2, -0.5),
+            Point3::new(-0.5, 1.2, 0.5),
+            radiance,
+        );
+        vec![
+            ("sphere", sphere.object(), Box::new(sphere)),
+            ("rectangle", rect().object(), Box::new(rect())),
+            ("rectangle by area", by_area.object(), Box::new(by_area)),
+            ("parallelogram", slanted.object(), Box::new(slanted)),
+            ("triangle", triangle.object(), Box::new(triangle)),
+        ]
+    }
+
+    #[test]
+    fn area_light_pdfs_match_their_sampling() {
+        // Sampled directions must land on the light with the density `pdf` gives, which must
+        // cover the directions the light fills exactly once. The light gathered must match
+        // looking for it in every direction around the point.
+        const SAMPLES: usize = 20_000;
+        const STEPS: usize = 600;
+        let mut rng = StdRng::seed_from_u64(44);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        for (name, object, light) in area_lights() {
+            for p in [Point3::new(0.2, -0.1, 0.0), Point3::new(0.1, 1.0, 0.05)] {
+                let mut sampled = 0.0;
+                for _ in 0..SAMPLES {
+                    let sample = light.sample(&p, &mut rng).unwrap();
+                    let q = p + sample.distance * sample.wi;
+                    assert!(
+                        light.normal(&q).is_some(),
+                        "{}: {:?} is off the light",
+                        name,
+                        q
+                    );
+                    let pdf = light.pdf(&p, &q);
+                    assert!(
+                        (pdf - sample.pdf).abs() < 2e-3 * pdf,
+                        "{}: {} {}",
+                        name,
+                        pdf,
+                        sample.pdf
+                    );
+                    sampled += sample.weight().x * sample.wi.dot(&normal).max(0.0) / SAMPLES as f32;
+                }
+
+                // Midpoint quadrature over the sphere of directions, in bands of equal area
+                let d_omega = (2.0 / STEPS as f32) * (PI / STEPS as f32);
+                let (mut covered, mut gathered) = (0.0, 0.0);
+                for i in 0..STEPS {
+                    let z = -1.0 + (i as f32 + 0.5) * 2.0 / STEPS as f32;
+                    let r = (1.0 - z * z).sqrt();
+                    for j in 0..2 * STEPS {
+                        let phi = (j as f32 + 0.5) * PI / STEPS as f32;
+                        let wi = Vec3::new(r * phi.cos(), z, r * phi.sin());
+                        let ray = Ray::new(p, wi, 0.0);
+                        if let Some(hit) = object.hit(&ray, 1e-4, f32::MAX, &mut rng) {
+                            covered += light.pdf(&p, &hit.p) * d_omega;
+                            gathered += hit.material.emitted(&hit).x * wi.y.max(0.0) * d_omega;
+                        }
+                    }
+                }
+                assert!((covered - 1.0).abs() < 0.01, "{} covers {}", name, covered);
+                assert!(
+                    (sampled / gathered - 1.0).abs() < 0.02,
+                    "{}: {} {}",
+                    name,
+                    sampled,
+                    gathered
+                );
+            }
+        }
+
+        // From straight below, a sphere of radius r a distance d away delivers π L (r / d)²
+        let sphere = SphereLight::new(Point3::new(0.0, 2.0, 0.0), 0.5, Color::new(1.0, 1.0, 1.0));
+        let p = Point3::new(0.0, 0.0, 0.0);
+        let gathered = (0..SAMPLES)
+            .map(|_| {
+                let sample = sphere.sample(&p, &mut rng).unwrap();
+                sample.weight().x * sample.wi.y
+            })
+            .sum::<f32>()
+            / SAMPLES as f32;
+        assert!((gathered - PI * 0.0625).abs() < 1e-3);
+    }
+}
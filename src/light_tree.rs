@@ -0,0 +1,543 @@
+//! Choosing which light to sample at a point, for scenes with too many lights to sample them
+//! all. Lights with finite extent are gathered into a bounding volume hierarchy whose nodes
+//! bound the lights' positions, power and the directions they shine in, after Conty Estevez and
+//! Kulla, "Importance Sampling of Many Lights with Adaptive Tree Splitting", as refined in pbrt
+//! v4.
+
+use crate::aabb::Aabb;
+use crate::light::Light;
+use crate::vec::{Point3, Vec3};
+
+use std::f32::consts::PI;
+
+/// A cone of directions around `w` with half-angle `acos(cos_theta)`.
+#[derive(Debug, Copy, Clone)]
+struct DirectionCone {
+    w: Vec3,
+    cos_theta: f32,
+}
+
+impl DirectionCone {
+    /// The smallest cone holding both `a` and `b`.
+    fn union(a: &DirectionCone, b: &DirectionCone) -> DirectionCone {
+        let theta_a = a.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_b = b.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_d = a.w.dot(&b.w).clamp(-1.0, 1.0).acos();
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return *a;
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return *b;
+        }
+
+        let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+        let axis = a.w.cross(&b.w);
+        if theta_o >= PI || axis.square_len() == 0.0 {
+            return DirectionCone {
+                w: a.w,
+                cos_theta: -1.0,
+            };
+        }
+        // Rotate a's axis towards b's until the cone reaches just far enough
+        let k = axis.unit_vector();
+        let theta_r = theta_o - theta_a;
+        let w = a.w * theta_r.cos()
+            + k.cross(&a.w) * theta_r.sin()
+            + k * (k.dot(&a.w) * (1.0 - theta_r.cos()));
+        DirectionCone {
+            w,
+            cos_theta: theta_o.cos(),
+        }
+    }
+}
+
+/// A conservative summary of one or more lights: where they are, how much power they give off
+/// and in which directions.
+#[derive(Debug, Copy, Clone)]
+pub struct LightBounds {
+    bounds: Aabb,
+    phi: f32,
+    /// Bounds the surface normals, or the axes of emission, of the lights.
+    normals: DirectionCone,
+    /// Cosine of how far past `normals` light can leave, such as 90 degrees for diffuse
+    /// emitters.
+    cos_theta_e: f32,
+    two_sided: bool,
+}
+
+impl LightBounds {
+    /// Lights inside `bounds` giving off a total power of `phi`, emitted within
+    /// `acos(cos_theta_e)` of normals that lie within `acos(cos_theta_o)` of `w`.
+    pub fn new(
+        bounds: Aabb,
+        phi: f32,
+        w: Vec3,
+        cos_theta_o: f32,
+        cos_theta_e: f32,
+        two_sided: bool,
+    ) -> LightBounds {
+        LightBounds {
+            bounds,
+            phi,
+            normals: DirectionCone {
+                w: w.unit_vector(),
+                cos_theta: cos_theta_o,
+            },
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    fn union(a: &LightBounds, b: &LightBounds) -> LightBounds {
+        if a.phi == 0.0 {
+            return *b;
+        }
+        if b.phi == 0.0 {
+            return *a;
+        }
+        LightBounds {
+            bounds: a.bounds.union(&b.bounds),
+            phi: a.phi + b.phi,
+            normals: DirectionCone::union(&a.normals, &b.normals),
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+
+    /// An estimate of how much light could reach `p`, on a surface with normal `normal` if
+    /// it's not in a volume. Only zero if none can.
+    fn importance(&self, p: &Point3, normal: Option<&Vec3>) -> f32 {
+        if self.phi == 0.0 {
+            return 0.0;
+        }
+        // Distance to the center, kept from getting too small for points near or inside
+        let center = self.bounds.center();
+        let diagonal = self.bounds.max - self.bounds.min;
+        let to_point = *p - center;
+        let distance_squared = to_point.square_len().max(0.5 * diagonal.len());
+        let wi = to_point.unit_vector();
+
+        // Cosine and sine of the smallest angle between an emission direction and wi, and of
+        // wi with the normal at p, allowing for the directions the bounds span from p
+        let cos_sub_clamped = |sin_a: f32, cos_a: f32, sin_b: f32, cos_b: f32| {
+            if cos_a > cos_b {
+                1.0
+            } else {
+                cos_a * cos_b + sin_a * sin_b
+            }
+        };
+        let sin_sub_clamped = |sin_a: f32, cos_a: f32, sin_b: f32, cos_b: f32| {
+            if cos_a > cos_b {
+                0.0
+            } else {
+                sin_a * cos_b - cos_a * sin_b
+            }
+        };
+        let sin_of = |cos: f32| (1.0 - cos * cos).max(0.0).sqrt();
+
+        let mut cos_theta_w = self.normals.w.dot(&wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = sin_of(cos_theta_w);
+        let cos_theta_o = self.normals.cos_theta;
+        let sin_theta_o = sin_of(cos_theta_o);
+        let cos_theta_b = self.subtended_cos(p);
+        let sin_theta_b = sin_of(cos_theta_b);
+
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.phi * cos_theta_p / distance_squared;
+        if let Some(n) = normal {
+            let cos_theta_i = wi.dot(n).abs();
+            let sin_theta_i = sin_of(cos_theta_i);
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+        importance.max(0.0)
+    }
+
+    /// Cosine of the half-angle of a cone from `p` holding the whole of the bounds, or -1 from
+    /// inside them.
+    fn subtended_cos(&self, p: &Point3) -> f32 {
+        let center = self.bounds.center();
+        let radius_squared = (self.bounds.max - center).square_len();
+        let distance_squared = (*p - center).square_len();
+        if distance_squared < radius_squared {
+            -1.0
+        } else {
+            (1.0 - radius_squared / distance_squared).max(0.0).sqrt()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf(usize),
+    /// The first child directly follows its parent; this is the index of the second.
+    Interior(usize),
+}
+
+/// Picks lights with probability in proportion to an estimate of how much each contributes at
+/// a point. Lights at infinity can't be bounded, so they're picked uniformly alongside the
+/// tree.
+#[derive(Debug, Clone)]
+pub struct LightTree {
+    nodes: Vec<(LightBounds, Node)>,
+    infinite: Vec<usize>,
+    /// For each light in the tree, the choices of child on the way down to it, as bits from
+    /// the least significant up. `None` for lights at infinity.
+    trails: Vec<Option<u64>>,
+}
+
+impl LightTree {
+    pub fn new(lights: &[Box<dyn Light>]) -> LightTree {
+        let mut infinite = Vec::new();
+        let mut bounded = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.phi > 0.0 => bounded.push((i, bounds)),
+                Some(_) => {}
+                None => infinite.push(i),
+            }
+        }
+        let mut tree = LightTree {
+            nodes: Vec::with_capacity(2 * bounded.len()),
+            infinite,
+            trails: vec![None; lights.len()],
+        };
+        if !bounded.is_empty() {
+            tree.build(&mut bounded, 0, 0);
+        }
+        tree
+    }
+
+    /// Splits the lights at the median of their centres along the axis they're most spread
+    /// out on.
+    fn build(&mut self, lights: &mut [(usize, LightBounds)], trail: u64, depth: u32) -> usize {
+        let index = self.nodes.len();
+        if lights.len() == 1 || depth == 64 {
+            let bounds = lights
+                .iter()
+                .skip(1)
+                .fold(lights[0].1, |acc, (_, b)| LightBounds::union(&acc, b));
+            self.nodes.push((bounds, Node::Leaf(lights[0].0)));
+            self.trails[lights[0].0] = Some(trail);
+            return index;
+        }
+
+        let centers = lights.iter().skip(1).fold(
+            Aabb::new(lights[0].1.bounds.center(), lights[0].1.bounds.center()),
+            |acc, (_, b)| acc.union(&Aabb::new(b.bounds.center(), b.bounds.center())),
+        );
+        let extent = centers.max - centers.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        lights.sort_by(|(_, a), (_, b)| {
+            a.bounds.center()[axis]
+                .partial_cmp(&b.bounds.center()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let (left, right) = lights.split_at_mut(lights.len() / 2);
+        // The node is filled in once both children are built
+        self.nodes.push((left[0].1, Node::Interior(0)));
+        self.build(left, trail, depth + 1);
+        let second = self.build(right, trail | (1 << depth), depth + 1);
+        let bounds = LightBounds::union(&self.nodes[index + 1].0, &self.nodes[second].0);
+        self.nodes[index] = (bounds, Node::Interior(second));
+        index
+    }
+
+    fn infinite_probability(&self) -> f32 {
+        let trees = if self.nodes.is_empty() { 0 } else { 1 };
+        let choices = self.infinite.len() + trees;
+        if choices == 0 {
+            0.0
+        } else {
+            self.infinite.len() as f32 / choices as f32
+        }
+    }
+
+    /// The probabilities of going to each child of the interior node at `index`, in proportion
+    /// to `weigh` of their bounds.
+    fn child_probabilities<W: Fn(&LightBounds) -> f32>(
+        &self,
+        index: usize,
+        second: usize,
+        weigh: &W,
+    ) -> Option<[f32; 2]> {
+        let first = weigh(&self.nodes[index + 1].0);
+        let second = weigh(&self.nodes[second].0);
+        let total = first + second;
+        if total > 0.0 {
+            Some([first / total, second / total])
+        } else {
+            None
+        }
+    }
+
+    /// Picks a light for the point `p` with the random number `u`, returning its index and the
+    /// probability of picking it, or `None` if no light can reach `p`.
+    pub fn sample(&self, p: &Point3, normal: Option<&Vec3>, u: f32) -> Option<(usize, f32)> {
+        let p_infinite = self.infinite_probability();
+        self.sample_by(u, p_infinite, &|bounds: &LightBounds| {
+            bounds.importance(p, normal)
+        })
+    }
+
+    /// The probability that `sample` picks the light at `light` for the point `p`.
+    pub fn pmf(&self, p: &Point3, normal: Option<&Vec3>, light: usize) -> f32 {
+        let p_infinite = self.infinite_probability();
+        self.pmf_by(light, p_infinite, &|bounds: &LightBounds| {
+            bounds.importance(p, normal)
+        })
+    }
+
+    /// Picks one of the lights in the tree to start a path from with the random number `u`, in
+    /// proportion to its power as there's no point to judge by, returning its index and the
+    /// probability of picking it. Lights at infinity are never picked.
+    pub fn sample_emitter(&self, u: f32) -> Option<(usize, f32)> {
+        self.sample_by(u, 0.0, &|bounds: &LightBounds| bounds.phi)
+    }
+
+    /// The probability that `sample_emitter` picks the light at `light`.
+    pub fn emitter_pmf(&self, light: usize) -> f32 {
+        self.pmf_by(light, 0.0, &|bounds: &LightBounds| bounds.phi)
+    }
+
+    /// Picks a light at infinity with probability `p_infinite`, or otherwise walks down the tree
+    /// choosing children in proportion to `weigh`.
+    fn sample_by<W: Fn(&LightBounds) -> f32>(
+        &self,
+        u: f32,
+        p_infinite: f32,
+        weigh: &W,
+    ) -> Option<(usize, f32)> {
+        if u < p_infinite {
+            let count = self.infinite.len();
+            let i = ((u / p_infinite * count as f32) as usize).min(count - 1);
+            return Some((self.infinite[i], p_infinite / count as f32));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f32::EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut index = 0;
+        loop {
+            match self.nodes[index].1 {
+                Node::Leaf(light) => {
+                    return if index > 0 || weigh(&self.nodes[0].0) > 0.0 {
+                        Some((light, pmf))
+                    } else {
+                        None
+                    };
+                }
+                Node::Interior(second) => {
+                    let [p_first, p_second] = self.child_probabilities(index, second, weigh)?;
+                    if u < p_first {
+                        u = (u / p_first).min(1.0 - f32::EPSILON);
+                        pmf *= p_first;
+                        index += 1;
+                    } else {
+                        u = ((u - p_first) / p_second).min(1.0 - f32::EPSILON);
+                        pmf *= p_second;
+                        index = second;
+                    }
+                }
+            }
+        }
+    }
+
+    fn pmf_by<W: Fn(&LightBounds) -> f32>(&self, light: usize, p_infinite: f32, weigh: &W) -> f32 {
+        let trail = match self.trails.get(light) {
+            Some(Some(trail)) => *trail,
+            Some(None) if self.infinite.contains(&light) => {
+                return p_infinite / self.infinite.len() as f32;
+            }
+            _ => return 0.0,
+        };
+
+        let mut pmf = 1.0 - p_infinite;
+        let mut index = 0;
+        let mut depth = 0;
+        while let Node::Interior(second) = self.nodes[index].1 {
+            let probabilities = match self.child_probabilities(index, second, weigh) {
+                Some(probabilities) => probabilities,
+                None => return 0.0,
+            };
+            if trail & (1 << depth) == 0 {
+                pmf *= probabilities[0];
+                index += 1;
+            } else {
+                pmf *= probabilities[1];
+                index = second;
+            }
+            depth += 1;
+        }
+        pmf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{DirectionalLight, PointLight, QuadLight, SphereLight, TriangleLight};
+    use crate::vec::Color;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// A spread of lights of different kinds and strengths, one of them dark and two at
+    /// infinity.
+    fn lights() -> Vec<Box<dyn Light>> {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut lights: Vec<Box<dyn Light>> = Vec::new();
+        for i in 0..12 {
+            let position = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(0.0..4.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            let power = rng.gen_range(0.5..10.0);
+            let color = Color::new(power, power, power);
+            lights.push(match i % 4 {
+                0 => Box::new(PointLight::new(position, color)),
+                1 => Box::new(SphereLight::new(position, 0.3, color)),
+                2 => Box::new(QuadLight::new(
+                    position,
+                    Vec3::new(1.0, 0.0, 0.0),
+                    Vec3::new(0.0, 0.0, 1.0),
+                    color,
+                )),
+                _ => Box::new(TriangleLight::new(
+                    position,
+                    position + Vec3::new(0.0, 1.0, 0.0),
+                    position + Vec3::new(1.0, 0.0, 0.0),
+                    color,
+                )),
+            });
+        }
+        lights.push(Box::new(PointLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 0.0),
+        )));
+        lights.push(Box::new(DirectionalLight::new(
+            Vec3::new(0.3, 1.0, 0.2),
+            Color::new(1.0, 1.0, 1.0),
+        )));
+        lights.push(Box::new(DirectionalLight::new(
+            Vec3::new(-0.5, 1.0, 0.0),
+            Color::new(2.0, 2.0, 2.0),
+        )));
+        lights
+    }
+
+    fn shading_points() -> Vec<(Point3, Option<Vec3>)> {
+        vec![
+            (Point3::new(0.0, 0.0, 0.0), Some(Vec3::new(0.0, 1.0, 0.0))),
+            (Point3::new(3.0, 1.0, -2.0), Some(Vec3::new(-1.0, 0.0, 0.0))),
+            (Point3::new(-4.0, 2.0, 4.0), None),
+        ]
+    }
+
+    #[test]
+    fn sampling_frequencies_match_pmf() {
+        const SAMPLES: usize = 200_000;
+        let lights = lights();
+        let tree = LightTree::new(&lights);
+        for (p, normal) in shading_points() {
+            let mut counts = vec![0usize; lights.len()];
+            for i in 0..SAMPLES {
+                let u = (i as f32 + 0.5) / SAMPLES as f32;
+                if let Some((light, pmf)) = tree.sample(&p, normal.as_ref(), u) {
+                    let expected = tree.pmf(&p, normal.as_ref(), light);
+                    assert!((pmf - expected).abs() <= 1e-5 * expected.max(1.0));
+                    counts[light] += 1;
+                }
+            }
+
+            let mut total = 0.0;
+            for (light, count) in counts.iter().enumerate() {
+                let pmf = tree.pmf(&p, normal.as_ref(), light);
+                total += pmf;
+                let observed = *count as f32 / SAMPLES as f32;
+                assert!(
+                    (observed - pmf).abs() < 2e-3,
+                    "light {} at {:?}: picked {} of the time, pmf {}",
+                    light,
+                    p,
+                    observed,
+                    pmf
+                );
+            }
+            assert!((total - 1.0).abs() < 1e-4, "pmf sums to {}", total);
+        }
+    }
+
+    #[test]
+    fn emitters_are_picked_by_power() {
+        const SAMPLES: usize = 200_000;
+        let lights = lights();
+        let tree = LightTree::new(&lights);
+        let phi: Vec<f32> = lights
+            .iter()
+            .map(|light| light.bounds().map_or(0.0, |bounds| bounds.phi))
+            .collect();
+        let total: f32 = phi.iter().sum();
+
+        let mut counts = vec![0usize; lights.len()];
+        for i in 0..SAMPLES {
+            let u = (i as f32 + 0.5) / SAMPLES as f32;
+            let (light, pmf) = tree.sample_emitter(u).unwrap();
+            assert!((pmf - tree.emitter_pmf(light)).abs() <= 1e-6);
+            counts[light] += 1;
+        }
+        for (light, count) in counts.iter().enumerate() {
+            let expected = phi[light] / total;
+            let pmf = tree.emitter_pmf(light);
+            assert!(
+                (pmf - expected).abs() < 1e-5,
+                "light {}: pmf {}",
+                light,
+                pmf
+            );
+            assert!((*count as f32 / SAMPLES as f32 - pmf).abs() < 2e-3);
+        }
+    }
+
+    #[test]
+    fn dark_and_missing_lights_are_never_picked() {
+        let lights = lights();
+        let tree = LightTree::new(&lights);
+        let dark = 12;
+        for (p, normal) in shading_points() {
+            assert_eq!(tree.pmf(&p, normal.as_ref(), dark), 0.0);
+            assert_eq!(tree.pmf(&p, normal.as_ref(), lights.len()), 0.0);
+        }
+        assert!(LightTree::new(&[])
+            .sample(&Point3::new(0.0, 0.0, 0.0), None, 0.5)
+            .is_none());
+    }
+
+    #[test]
+    fn lights_at_infinity_share_their_probability() {
+        let lights = lights();
+        let tree = LightTree::new(&lights);
+        let p = Point3::new(0.0, 0.0, 0.0);
+        // Two lights at infinity and the tree are picked between evenly
+        for light in [13, 14] {
+            assert!((tree.pmf(&p, None, light) - 1.0 / 3.0).abs() < 1e-6);
+        }
+    }
+}
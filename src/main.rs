@@ -4,23 +4,41 @@ extern crate rand;
 extern crate rayon;
 extern crate time;
 
+mod aabb;
+mod bdpt;
 mod bmp;
 mod camera;
 mod format;
+mod hdr;
 mod hittable;
+mod integrator;
+mod light;
+mod light_tree;
 mod material;
+mod medium;
+mod microfacet;
+mod onb;
+mod photon;
 mod ray;
+mod sampling;
+mod scene;
+mod scenes;
+mod sdf;
+mod sky;
+mod texture;
+mod transform;
 mod vec;
 
-use crate::camera::Camera;
+use crate::bdpt::Bdpt;
 use crate::format::{Bmp, Format};
-use crate::hittable::{Hittable, HittableList, Sphere};
-use crate::material::{Dialectric, Lambertian, Metal};
+use crate::integrator::{
+    AmbientOcclusion, DebugMode, DebugView, DepthLimits, Integrator, PathTracer, Splats, Whitted,
+};
+use crate::photon::{PhotonMapper, ProgressivePhotonMapper};
 use crate::rand::Rng;
-use crate::ray::Ray;
-use crate::vec::{Color, Point3, Vec3};
+use crate::sampling::seeded_rng;
+use crate::vec::Color;
 use rayon::prelude::*;
-use std::sync::Arc;
 use time::OffsetDateTime;
 
 fn main() {
@@ -28,164 +46,164 @@ fn main() {
     const IMAGE_HEIGHT: u32 = 360;
     const IMAGE_WIDTH: u32 = (IMAGE_HEIGHT as f32 * ASPECT_RATIO) as u32;
     const SAMPLES_PER_PIXEL: u32 = 500;
-    const MAX_DEPTH: u32 = 50;
+    // Progressive photon mapping traces a fresh photon map for each pass over the image
+    const PHOTON_PASSES: u32 = 20;
+    const MAX_DEPTH: DepthLimits = DepthLimits {
+        diffuse: 16,
+        specular: 32,
+        transmission: 32,
+    };
     let mut image = Bmp::new(IMAGE_WIDTH, IMAGE_HEIGHT);
 
-    let world = random_scene();
+    // Options such as `--integrator=whitted` can go anywhere among the scene's arguments
+    let (options, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let mut integrator_name = "path".to_string();
+    // Every random choice, from the scene's layout to each pixel's samples, follows from the
+    // seed, so the same seed renders the same image
+    let mut seed = 0u64;
+    for option in options {
+        if let Some(name) = option.strip_prefix("--integrator=") {
+            integrator_name = name.to_string();
+        } else if let Some(value) = option.strip_prefix("--seed=") {
+            seed = match value.parse() {
+                Ok(value) => value,
+                Err(_) => {
+                    eprintln!("Invalid seed '{}', expected a whole number", value);
+                    std::process::exit(1);
+                }
+            };
+        } else {
+            eprintln!(
+                "Unknown option '{}', expected --integrator=<name> or --seed=<n>",
+                option
+            );
+            std::process::exit(1);
+        }
+    }
+    let mut integrator: Box<dyn Integrator> = match integrator_name.as_str() {
+        "path" => Box::new(PathTracer::new(MAX_DEPTH)),
+        "bdpt" => Box::new(Bdpt::new(MAX_DEPTH.diffuse)),
+        "photons" => Box::new(PhotonMapper::new(200_000, 1_000_000, 4, MAX_DEPTH.specular)),
+        "sppm" => Box::new(ProgressivePhotonMapper::new(
+            PHOTON_PASSES,
+            250_000,
+            0.25,
+            MAX_DEPTH.specular,
+        )),
+        "whitted" => Box::new(Whitted::new(MAX_DEPTH.specular)),
+        "ao" => Box::new(AmbientOcclusion::new(2.0)),
+        "normals" => Box::new(DebugView::new(DebugMode::Normals)),
+        "uv" => Box::new(DebugView::new(DebugMode::Uv)),
+        "depth" => Box::new(DebugView::new(DebugMode::Depth)),
+        "albedo" => Box::new(DebugView::new(DebugMode::Albedo)),
+        "material" => Box::new(DebugView::new(DebugMode::MaterialId)),
+        _ => {
+            eprintln!(
+                "Unknown integrator '{}', expected one of: path, bdpt, photons, sppm, whitted, ao, \
+                normals, uv, depth, albedo, material",
+                integrator_name
+            );
+            std::process::exit(1);
+        }
+    };
 
-    let camera = Camera::new(
-        Point3::new(13.0, 2.0, 3.0),
-        Point3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        ASPECT_RATIO,
-        0.1,
-        10.0,
-    );
+    let scene_name = args
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "random".to_string());
+    let scene = match scenes::build(&scene_name, ASPECT_RATIO, seed, args.get(1).cloned()) {
+        Some(scene) => scene,
+        None => {
+            eprintln!(
+                "Unknown scene '{}', expected one of: {}",
+                scene_name,
+                scenes::names()
+            );
+            std::process::exit(1);
+        }
+    };
 
+    // Light paths joined straight to the camera land on pixels other than the one being traced
+    let splats = Splats::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+    // Progressive integrators refine what they know of the scene between passes over the image,
+    // each of which takes a share of every pixel's samples, the first ones any left over
+    let passes = integrator.passes().clamp(1, SAMPLES_PER_PIXEL);
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); (IMAGE_WIDTH * IMAGE_HEIGHT) as usize];
+    let total_rows = IMAGE_HEIGHT * passes;
     let start_time = OffsetDateTime::now_local().unwrap();
     let mut row_count = 0u32;
-    for j in image.iter_rows() {
-        let it_start_time = OffsetDateTime::now_local().unwrap();
-
-        let scanline: Vec<Color> = (0..image.get_width())
-            .into_par_iter()
-            .map(|i| {
-                let mut rng = rand::thread_rng();
-
-                let mut c = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..SAMPLES_PER_PIXEL {
-                    let u = (i as f32 + rng.gen::<f32>()) / IMAGE_WIDTH as f32;
-                    let v = (j as f32 + rng.gen::<f32>()) / IMAGE_HEIGHT as f32;
-                    let r = camera.get_ray(u, v);
-                    // let p = r.point_at_parameter(2.0);
-                    c += color(r, &world, MAX_DEPTH);
-                }
-                c /= SAMPLES_PER_PIXEL as f32;
-                Color::new(c.x.sqrt(), c.y.sqrt(), c.z.sqrt())
-            })
-            .collect();
-        for (i, pixel) in scanline.into_iter().enumerate() {
-            image.set_pixel(i as u32, j, pixel);
-        }
-
-        row_count += 1;
-        let rows_remaining = image.get_height() - row_count;
-        let curr_time = OffsetDateTime::now_local().unwrap();
-        let last_it_elapsed = curr_time - it_start_time;
-        let elapsed = curr_time - start_time;
-        let time_per_iteration = elapsed / row_count;
-        let est_time_remaining = time_per_iteration * rows_remaining;
-        let est_time_of_completion = curr_time + time_per_iteration * rows_remaining;
-        eprintln!(
-            "Rendered scanline {} of {}\n\
-            \tlast line: {}.{:0>3}s\n\
-            \ttime/line: {}.{:0>3}s\n\
-            \telapsed:   {}:{:0>2}:{:0>2}\n\
-            \tremaining: {}:{:0>2}:{:0>2}\n\
-            \tETA:       {}",
-            row_count,
-            IMAGE_HEIGHT,
-            last_it_elapsed.whole_seconds(),
-            last_it_elapsed.whole_milliseconds() % 1000,
-            time_per_iteration.whole_seconds(),
-            time_per_iteration.whole_milliseconds() % 1000,
-            elapsed.whole_hours(),
-            elapsed.whole_minutes() % 60,
-            elapsed.whole_seconds() % 60,
-            est_time_remaining.whole_hours(),
-            est_time_remaining.whole_minutes() % 60,
-            est_time_remaining.whole_seconds() % 60,
-            est_time_of_completion,
-        );
-    }
+    for pass in 0..passes {
+        integrator.begin_pass(&scene, pass, seed);
+        let samples_per_pass =
+            SAMPLES_PER_PIXEL / passes + u32::from(pass < SAMPLES_PER_PIXEL % passes);
+        for j in image.iter_rows() {
+            let it_start_time = OffsetDateTime::now_local().unwrap();
 
-    image.save("image").expect("Unable to save image");
-}
+            let scanline: Vec<Color> = (0..image.get_width())
+                .into_par_iter()
+                .map(|i| {
+                    let mut rng = seeded_rng(seed, &[pass, j, i]);
 
-fn color(r: Ray, world: &dyn Hittable, depth: u32) -> Color {
-    if let Some(hit) = world.hit(&r, 0.001, f32::MAX) {
-        if depth > 0 {
-            if let Some((scattered, attenuation)) = hit.material.scatter(&r, &hit) {
-                attenuation * color(scattered, world, depth - 1)
-            } else {
-                Color::new(0.0, 0.0, 0.0)
+                    let mut c = Color::new(0.0, 0.0, 0.0);
+                    for _ in 0..samples_per_pass {
+                        let u = (i as f32 + rng.gen::<f32>()) / IMAGE_WIDTH as f32;
+                        let v = (j as f32 + rng.gen::<f32>()) / IMAGE_HEIGHT as f32;
+                        let r = scene.camera.get_ray(
+                            u,
+                            v,
+                            1.0 / IMAGE_WIDTH as f32,
+                            1.0 / IMAGE_HEIGHT as f32,
+                            &mut rng,
+                        );
+                        c += integrator.radiance(r, &scene, &mut rng, &splats);
+                    }
+                    c
+                })
+                .collect();
+            for (i, c) in scanline.into_iter().enumerate() {
+                pixels[(j * IMAGE_WIDTH) as usize + i] += c;
             }
-        } else {
-            Color::new(0.0, 0.0, 0.0)
-        }
-    } else {
-        let unit_direction = r.direction.unit_vector();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
-    }
-}
 
-fn random_scene() -> HittableList {
-    let mut rng = rand::thread_rng();
-
-    let mut list: Vec<Box<dyn Hittable>> = vec![
-        Box::new(Sphere::new(
-            Point3::new(0.0, -1000.0, 0.0),
-            1000.0,
-            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
-        )),
-        Box::new(Sphere::new(
-            Point3::new(0.0, 1.0, 0.0),
-            1.0,
-            Arc::new(Dialectric::new(1.5)),
-        )),
-        Box::new(Sphere::new(
-            Point3::new(-4.0, 1.0, 0.0),
-            1.0,
-            Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
-        )),
-        Box::new(Sphere::new(
-            Point3::new(4.0, 1.0, 0.0),
-            1.0,
-            Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5))),
-        )),
-    ];
-    let cmp = Vec3::new(4.0, 0.2, 0.0);
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f32>();
-            let center = Point3::new(
-                a as f32 + 0.9f32 * rng.gen::<f32>(),
-                0.2,
-                b as f32 + 0.9f32 * rng.gen::<f32>(),
+            row_count += 1;
+            let rows_remaining = total_rows - row_count;
+            let curr_time = OffsetDateTime::now_local().unwrap();
+            let last_it_elapsed = curr_time - it_start_time;
+            let elapsed = curr_time - start_time;
+            let time_per_iteration = elapsed / row_count;
+            let est_time_remaining = time_per_iteration * rows_remaining;
+            let est_time_of_completion = curr_time + time_per_iteration * rows_remaining;
+            eprintln!(
+                "Rendered scanline {} of {}\n\
+                \tlast line: {}.{:0>3}s\n\
+                \ttime/line: {}.{:0>3}s\n\
+                \telapsed:   {}:{:0>2}:{:0>2}\n\
+                \tremaining: {}:{:0>2}:{:0>2}\n\
+                \tETA:       {}",
+                row_count,
+                total_rows,
+                last_it_elapsed.whole_seconds(),
+                last_it_elapsed.whole_milliseconds() % 1000,
+                time_per_iteration.whole_seconds(),
+                time_per_iteration.whole_milliseconds() % 1000,
+                elapsed.whole_hours(),
+                elapsed.whole_minutes() % 60,
+                elapsed.whole_seconds() % 60,
+                est_time_remaining.whole_hours(),
+                est_time_remaining.whole_minutes() % 60,
+                est_time_remaining.whole_seconds() % 60,
+                est_time_of_completion,
             );
-            if (center - cmp).len() > 0.9 {
-                if choose_mat < 0.8 {
-                    list.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Lambertian::new(Color::new(
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                        ))),
-                    )));
-                } else if choose_mat < 0.95 {
-                    list.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Metal::new(Color::new(
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                            0.5 * (1.0 + rng.gen::<f32>()),
-                        ))),
-                    )));
-                } else {
-                    list.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Dialectric::new(1.5)),
-                    )))
-                }
-            }
         }
     }
 
-    HittableList::new(list)
+    let samples = SAMPLES_PER_PIXEL as f32;
+    for j in 0..IMAGE_HEIGHT {
+        for i in 0..IMAGE_WIDTH {
+            let c = (pixels[(j * IMAGE_WIDTH + i) as usize] + splats.get(i, j)) / samples;
+            image.set_pixel(i, j, Color::new(c.x.sqrt(), c.y.sqrt(), c.z.sqrt()));
+        }
+    }
+    image.save("image").expect("Unable to save image");
 }
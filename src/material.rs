@@ -1,129 +1,1611 @@
 use crate::hittable::HitRecord;
-use crate::ray::Ray;
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, ComplexIor, Ggx};
+use crate::onb::Onb;
+use crate::sampling;
+use crate::texture::{SolidColor, Texture};
 use crate::vec::{Color, Vec3};
 
-use rand::Rng;
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+use std::sync::Arc;
 
+/// A direction chosen by `Material::sample`, in the local shading frame.
+#[derive(Debug, Copy, Clone)]
+pub struct BsdfSample {
+    pub wi: Vec3,
+    /// The BSDF value, without the cosine term.
+    pub f: Color,
+    pub pdf: f32,
+    /// Whether `wi` came from a delta lobe such as a mirror, which `eval` and `pdf` never
+    /// include. Its `f` and `pdf` are then only meaningful as a ratio.
+    pub delta: bool,
+}
+
+impl BsdfSample {
+    pub fn new(wi: Vec3, f: Color, pdf: f32) -> BsdfSample {
+        BsdfSample {
+            wi,
+            f,
+            pdf,
+            delta: false,
+        }
+    }
+
+    /// A sample from a delta lobe that scatters `reflectance` of the light into `wi`, chosen
+    /// with probability `pdf` among the material's lobes.
+    pub fn delta(wi: Vec3, reflectance: Color, pdf: f32) -> BsdfSample {
+        BsdfSample {
+            wi,
+            f: reflectance / wi.z.abs(),
+            pdf,
+            delta: true,
+        }
+    }
+
+    /// The factor by which the light arriving along `wi` is scaled on its way to `wo` when
+    /// scattering off a surface.
+    pub fn weight(&self) -> Color {
+        self.f * (self.wi.z.abs() / self.pdf)
+    }
+}
+
+/// How a surface scatters light. Directions are unit vectors in the local shading frame of
+/// `HitRecord::shading_frame`, with the normal along +z, and both point away from the surface:
+/// `wo` towards the viewer and `wi` towards the light. The normal faces the viewer, so `wo` is
+/// above the surface unless a shading normal has tilted too far, and `rec.front_face` tells
+/// whether the outside was hit. `rec.t` is the distance the ray travelled, since rays are traced
+/// with unit directions.
 pub trait Material: core::fmt::Debug + Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    /// Chooses an incoming direction for light leaving along `wo`, or `None` if the light is
+    /// absorbed. Random choices are drawn from `rng`.
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample>;
+
+    /// The BSDF value for the non-delta lobes, without the cosine term.
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color;
+
+    /// The density with which `sample` picks `wi` from the non-delta lobes.
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32;
+
+    /// The normal to shade `rec` with, for materials that add detail to the surface.
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.shading_normal
+    }
+
+    /// Whether this scatters light inside a volume rather than off a surface. Volumes have no
+    /// cosine term, so `f` is the whole of the phase function.
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+
+    /// Whether the back of the surface can be seen. Rays pass straight through the back of
+    /// one-sided surfaces.
+    fn is_two_sided(&self) -> bool {
+        true
+    }
+
+    /// Radiance the surface gives off towards the viewer.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Makes `inner` one-sided, so that it can only be seen from outside. Useful for open geometry
+/// seen from both sides, or to look into a room through its walls.
+#[derive(Debug, Clone)]
+pub struct OneSided {
+    inner: Arc<dyn Material>,
+}
+
+impl OneSided {
+    pub fn new(inner: Arc<dyn Material>) -> OneSided {
+        OneSided { inner }
+    }
+}
+
+impl Material for OneSided {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        self.inner.sample(rec, wo, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.inner.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.inner.pdf(rec, wo, wi)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.inner.shading_normal(rec)
+    }
+
+    fn is_two_sided(&self) -> bool {
+        false
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.inner.emitted(rec)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let target = rec.p + rec.normal + random_in_unit_sphere();
-        let scattered = Ray::new(rec.p, target - rec.p);
-        let attenuation = self.albedo;
-        Some((scattered, attenuation))
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        sample_diffuse(self, rec, wo, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        if same_hemisphere(wo, wi) {
+            rec.texture(&*self.albedo) / PI
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
     }
+
+    fn pdf(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        diffuse_pdf(wo, wi)
+    }
+}
+
+/// A surface that glows with `radiance` from its front face and reflects nothing, for area
+/// lights. Pair it with a `Light` over the same shape so that it's also sampled directly.
+#[derive(Debug, Clone)]
+pub struct DiffuseLight {
+    radiance: Arc<dyn Texture>,
 }
 
-fn random_in_unit_sphere() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
-            - Vec3::new(1.0, 1.0, 1.0);
-        if p.square_len() < 1.0 {
-            return p;
+impl DiffuseLight {
+    pub fn new(radiance: Color) -> DiffuseLight {
+        DiffuseLight::textured(Arc::new(SolidColor::new(radiance)))
+    }
+
+    pub fn textured(radiance: Arc<dyn Texture>) -> DiffuseLight {
+        DiffuseLight { radiance }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _rec: &HitRecord, _wo: &Vec3, _rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        None
+    }
+
+    fn eval(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> f32 {
+        0.0
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            rec.texture(&*self.radiance)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Rough diffuse reflection from the Oren-Nayar model of V-shaped microfacets, which stays
+/// brighter towards silhouettes than Lambertian, as seen on clay, plaster or the moon. `sigma` is
+/// the standard deviation of the facet slopes in degrees, with zero giving Lambertian.
+#[derive(Debug, Clone)]
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f32,
+    b: f32,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, sigma: f32) -> OrenNayar {
+        OrenNayar::textured(Arc::new(SolidColor::new(albedo)), sigma)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, sigma: f32) -> OrenNayar {
+        let sigma2 = sigma.to_radians().powi(2);
+        OrenNayar {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        sample_diffuse(self, rec, wo, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        if !same_hemisphere(wo, wi) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let max_cos = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs())
+        };
+        rec.texture(&*self.albedo) / PI * (self.a + self.b * max_cos * sin_alpha * tan_beta)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        diffuse_pdf(wo, wi)
+    }
+}
+
+/// Cosine-weighted sampling on the side of `wo`, shared by the diffuse materials.
+fn sample_diffuse(
+    material: &dyn Material,
+    rec: &HitRecord,
+    wo: &Vec3,
+    rng: &mut dyn RngCore,
+) -> Option<BsdfSample> {
+    let wi = upper(
+        &sampling::cosine_hemisphere(rng.gen::<f32>(), rng.gen::<f32>()),
+        wo.z < 0.0,
+    );
+    let pdf = material.pdf(rec, wo, &wi);
+    if pdf <= 0.0 {
+        return None;
+    }
+    Some(BsdfSample::new(wi, material.eval(rec, wo, &wi), pdf))
+}
+
+fn diffuse_pdf(wo: &Vec3, wi: &Vec3) -> f32 {
+    if same_hemisphere(wo, wi) {
+        sampling::cosine_hemisphere_pdf(wi.z.abs())
+    } else {
+        0.0
+    }
+}
+
+/// A metal tinted by `albedo`. With zero roughness it is a perfect mirror, otherwise it reflects
+/// off a GGX microsurface.
+#[derive(Debug, Clone)]
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
 }
 
 impl Metal {
     pub fn new(albedo: Color) -> Metal {
-        Metal { albedo }
+        Metal::rough(albedo, 0.0)
+    }
+
+    pub fn rough(albedo: Color, roughness: f32) -> Metal {
+        Metal::textured(
+            Arc::new(SolidColor::new(albedo)),
+            Arc::new(SolidColor::gray(roughness)),
+        )
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, roughness: Arc<dyn Texture>) -> Metal {
+        Metal { albedo, roughness }
+    }
+
+    fn ggx(&self, rec: &HitRecord) -> Ggx {
+        let roughness = scalar_texture(rec, &*self.roughness);
+        Ggx::from_roughness(roughness, roughness)
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let reflected = reflect(&r_in.direction.unit_vector(), &rec.normal);
-        let scattered = Ray::new(rec.p, reflected);
-        let attenuation = self.albedo;
-        if scattered.direction.dot(&rec.normal) > 0.0 {
-            Some((scattered, attenuation))
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = self.ggx(rec);
+        if ggx.is_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            return Some(BsdfSample::delta(wi, rec.texture(&*self.albedo), 1.0));
+        }
+        let wi = sample_ggx_reflection(&ggx, wo, rng)?;
+        let pdf = self.pdf(rec, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(wi, self.eval(rec, wo, &wi), pdf))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        let ggx = self.ggx(rec);
+        match reflection_half_vector(wo, wi) {
+            Some(m) if !ggx.is_smooth() => {
+                ggx_reflection(&ggx, wo, wi, &m).0 * rec.texture(&*self.albedo)
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let ggx = self.ggx(rec);
+        match reflection_half_vector(wo, wi) {
+            Some(m) if !ggx.is_smooth() => ggx_reflection(&ggx, wo, wi, &m).1,
+            _ => 0.0,
+        }
+    }
+}
+
+/// A physically based metal whose colour comes from its complex index of refraction, reflecting
+/// off a GGX microsurface. Different roughness along the surface tangent and bitangent gives a
+/// brushed look.
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    ior: ComplexIor,
+    roughness_u: Arc<dyn Texture>,
+    roughness_v: Arc<dyn Texture>,
+}
+
+impl Conductor {
+    pub fn new(ior: ComplexIor, roughness: f32) -> Conductor {
+        let roughness = Arc::new(SolidColor::gray(roughness));
+        Conductor::anisotropic(ior, roughness.clone(), roughness)
+    }
+
+    /// `roughness_u` applies along `dpdu` of the surface and `roughness_v` across it.
+    pub fn anisotropic(
+        ior: ComplexIor,
+        roughness_u: Arc<dyn Texture>,
+        roughness_v: Arc<dyn Texture>,
+    ) -> Conductor {
+        Conductor {
+            ior,
+            roughness_u,
+            roughness_v,
+        }
+    }
+
+    fn ggx(&self, rec: &HitRecord) -> Ggx {
+        Ggx::from_roughness(
+            scalar_texture(rec, &*self.roughness_u),
+            scalar_texture(rec, &*self.roughness_v),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = self.ggx(rec);
+        if ggx.is_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            return Some(BsdfSample::delta(
+                wi,
+                fresnel_conductor(wo.z, &self.ior),
+                1.0,
+            ));
+        }
+        let wi = sample_ggx_reflection(&ggx, wo, rng)?;
+        let pdf = self.pdf(rec, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(wi, self.eval(rec, wo, &wi), pdf))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        let ggx = self.ggx(rec);
+        match reflection_half_vector(wo, wi) {
+            Some(m) if !ggx.is_smooth() => {
+                ggx_reflection(&ggx, wo, wi, &m).0 * fresnel_conductor(wo.dot(&m), &self.ior)
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        let ggx = self.ggx(rec);
+        match reflection_half_vector(wo, wi) {
+            Some(m) if !ggx.is_smooth() => ggx_reflection(&ggx, wo, wi, &m).1,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Scatters light equally in all directions inside a volume, tinted by `albedo`.
+#[derive(Debug, Clone)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn sample(&self, rec: &HitRecord, _wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        let wi = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+        Some(BsdfSample::new(
+            wi,
+            rec.texture(&*self.albedo) * sampling::uniform_sphere_pdf(),
+            sampling::uniform_sphere_pdf(),
+        ))
+    }
+
+    fn eval(&self, rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Color {
+        rec.texture(&*self.albedo) * sampling::uniform_sphere_pdf()
+    }
+
+    fn pdf(&self, _rec: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> f32 {
+        sampling::uniform_sphere_pdf()
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+/// The Henyey-Greenstein phase function, which favours scattering forwards when `g` is positive
+/// and backwards when it's negative, as in clouds and dusty air. `g` of zero is `Isotropic`.
+#[derive(Debug, Clone)]
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f32) -> HenyeyGreenstein {
+        HenyeyGreenstein::textured(Arc::new(SolidColor::new(albedo)), g)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, g: f32) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// The phase function for scattering by an angle whose cosine is `cos_theta`, measured from
+    /// the direction the light was travelling.
+    fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.max(1e-8).sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        let (u0, u1) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u0
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u0);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u1;
+
+        // Light arrives travelling along -wo
+        let forward = -wo.unit_vector();
+        let (tangent, bitangent) = forward.orthonormal_basis();
+        let wi = sin_theta * phi.cos() * tangent
+            + sin_theta * phi.sin() * bitangent
+            + cos_theta * forward;
+        let p = self.phase(cos_theta);
+        Some(BsdfSample::new(wi, rec.texture(&*self.albedo) * p, p))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        rec.texture(&*self.albedo) * self.pdf(rec, wo, wi)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.phase(-wo.unit_vector().dot(&wi.unit_vector()))
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+/// Tilts the shading normal of `inner` by a tangent-space normal map, whose channels hold the
+/// components of the normal along `dpdu`, `dpdv` and the surface normal, mapped from `[-1, 1]`
+/// to `[0, 1]`. The map must hold linear values rather than sRGB colours. `strength` scales the
+/// tilt.
+#[derive(Debug, Clone)]
+pub struct NormalMapped {
+    inner: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+    strength: f32,
+}
+
+impl NormalMapped {
+    pub fn new(inner: Arc<dyn Material>, map: Arc<dyn Texture>, strength: f32) -> NormalMapped {
+        NormalMapped {
+            inner,
+            map,
+            strength,
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        self.inner.sample(rec, wo, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.inner.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.inner.pdf(rec, wo, wi)
+    }
+
+    fn is_two_sided(&self) -> bool {
+        self.inner.is_two_sided()
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let c = 2.0 * rec.texture(&*self.map) - Vec3::new(1.0, 1.0, 1.0);
+        let local_normal = Vec3::new(self.strength * c.x, self.strength * c.y, c.z);
+
+        // Follow dpdv for the bitangent, whichever way round the parameterisation is
+        let mut frame = Onb::from_wu(&rec.normal, &rec.dpdu);
+        if frame.v.dot(&rec.dpdv) < 0.0 {
+            frame.v = -frame.v;
+        }
+        let n = frame.to_world(&local_normal);
+        if n.square_len() > 0.0 {
+            n.into_unit_vector()
+        } else {
+            rec.normal
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.inner.emitted(rec)
+    }
+}
+
+/// Tilts the shading normal of `inner` as if the surface were displaced along its normal by
+/// `scale` times the `height` texture, without moving the geometry.
+#[derive(Debug, Clone)]
+pub struct BumpMapped {
+    inner: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    scale: f32,
+}
+
+impl BumpMapped {
+    pub fn new(inner: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f32) -> BumpMapped {
+        BumpMapped {
+            inner,
+            height,
+            scale,
+        }
+    }
+}
+
+impl Material for BumpMapped {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        self.inner.sample(rec, wo, rng)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.inner.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.inner.pdf(rec, wo, wi)
+    }
+
+    fn is_two_sided(&self) -> bool {
+        self.inner.is_two_sided()
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let orientation = rec.dpdu.cross(&rec.dpdv).dot(&rec.normal);
+        if orientation == 0.0 {
+            return rec.normal;
+        }
+
+        // Take finite differences over about half a pixel, so the bumps are filtered like the
+        // texture is
+        let d = &rec.uv_derivatives;
+        let mut du = 0.5 * (d.dudx.abs() + d.dudy.abs());
+        if du == 0.0 {
+            du = 5e-4;
+        }
+        let mut dv = 0.5 * (d.dvdx.abs() + d.dvdy.abs());
+        if dv == 0.0 {
+            dv = 5e-4;
+        }
+        let height = |u: f32, v: f32, p: &Vec3| {
+            let c = self.height.filtered(u, v, p, d);
+            self.scale * (c.x + c.y + c.z) / 3.0
+        };
+        let h = height(rec.u, rec.v, &rec.p);
+        let dhdu = (height(rec.u + du, rec.v, &(rec.p + du * rec.dpdu)) - h) / du;
+        let dhdv = (height(rec.u, rec.v + dv, &(rec.p + dv * rec.dpdv)) - h) / dv;
+
+        let n = (rec.dpdu + dhdu * rec.normal).cross(&(rec.dpdv + dhdv * rec.normal));
+        let n = n.into_unit_vector();
+        if orientation < 0.0 {
+            -n
         } else {
-            None
+            n
         }
     }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.inner.emitted(rec)
+    }
+}
+
+/// Reflects `wo` off a microfacet normal sampled from the visible normals.
+fn sample_ggx_reflection(ggx: &Ggx, wo: &Vec3, rng: &mut dyn RngCore) -> Option<Vec3> {
+    let m = ggx.sample_visible(wo, rng.gen::<f32>(), rng.gen::<f32>());
+    let wi = reflect(&-*wo, &m);
+    if wi.z <= 0.0 {
+        None
+    } else {
+        Some(wi)
+    }
+}
+
+/// The microfacet normal that reflects `wo` into `wi`, if both are above the surface.
+fn reflection_half_vector(wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return None;
+    }
+    Some((*wo + *wi).into_unit_vector())
+}
+
+/// The microfacet normal that refracts `wo` above the surface into `wi` below it, where `eta` is
+/// the index below relative to above.
+fn refraction_half_vector(wo: &Vec3, wi: &Vec3, eta: f32) -> Option<Vec3> {
+    let m = (*wo + eta * *wi).into_unit_vector();
+    let m = if m.z < 0.0 { -m } else { m };
+    if wo.dot(&m) <= 0.0 || wi.dot(&m) >= 0.0 || !m.z.is_finite() {
+        None
+    } else {
+        Some(m)
+    }
+}
+
+/// The GGX reflection BRDF through microfacet `m` without Fresnel, and the density of sampling
+/// `wi` from the visible normals.
+fn ggx_reflection(ggx: &Ggx, wo: &Vec3, wi: &Vec3, m: &Vec3) -> (f32, f32) {
+    let value = ggx.d(m) * ggx.g2(wo, wi) / (4.0 * wo.z * wi.z);
+    let pdf = ggx.pdf_visible(wo, m) / (4.0 * wo.dot(m));
+    (value, pdf)
+}
+
+/// The GGX transmission BTDF through microfacet `m` without Fresnel, and the density of sampling
+/// `wi` from the visible normals.
+fn ggx_transmission(ggx: &Ggx, wo: &Vec3, wi: &Vec3, m: &Vec3, eta: f32) -> (f32, f32) {
+    let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+    let dm_dwi = cos_i.abs() / (cos_i + cos_o / eta).powi(2);
+    let value = ggx.d(m) * ggx.g2(wo, wi) * cos_o.abs() * dm_dwi / (wo.z * wi.z).abs();
+    (value, ggx.pdf_visible(wo, m) * dm_dwi)
+}
+
+fn same_hemisphere(a: &Vec3, b: &Vec3) -> bool {
+    a.z * b.z > 0.0
+}
+
+/// Mirrors `w` through the surface when `flip` is set, so that materials which treat both sides
+/// alike can work with `wo` above it.
+fn upper(w: &Vec3, flip: bool) -> Vec3 {
+    if flip {
+        Vec3::new(w.x, w.y, -w.z)
+    } else {
+        *w
+    }
+}
+
+/// Reads a greyscale texture as a single value.
+fn scalar_texture(rec: &HitRecord, texture: &dyn Texture) -> f32 {
+    average(&rec.texture(texture))
 }
 
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     *v - 2.0 * v.dot(n) * n
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Glass-like material that reflects and refracts by the exact Fresnel equations. Rough glass
+/// scatters through a GGX microsurface, and light travelling inside is absorbed following the
+/// Beer-Lambert law. Thin-walled glass models a sheet too thin to bend light, such as a window
+/// pane or a soap bubble, so rays pass straight through instead of entering a solid.
+#[derive(Debug, Clone)]
 pub struct Dialectric {
     ref_idx: f32,
+    roughness: Arc<dyn Texture>,
+    absorption: Color,
+    thin: bool,
 }
 
 impl Dialectric {
     pub fn new(ref_idx: f32) -> Dialectric {
-        Dialectric { ref_idx }
+        Dialectric::rough(ref_idx, 0.0)
+    }
+
+    pub fn rough(ref_idx: f32, roughness: f32) -> Dialectric {
+        Dialectric::textured(ref_idx, Arc::new(SolidColor::gray(roughness)))
+    }
+
+    pub fn textured(ref_idx: f32, roughness: Arc<dyn Texture>) -> Dialectric {
+        Dialectric {
+            ref_idx,
+            roughness,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            thin: false,
+        }
+    }
+
+    /// Tints the glass so that light which has travelled `distance` through it is left with
+    /// the colour `transmittance`.
+    pub fn tinted(self, transmittance: Color, distance: f32) -> Dialectric {
+        let absorption = |t: f32| -t.max(1e-6).ln() / distance;
+        Dialectric {
+            absorption: Color::new(
+                absorption(transmittance.x),
+                absorption(transmittance.y),
+                absorption(transmittance.z),
+            ),
+            ..self
+        }
+    }
+
+    pub fn thin(ref_idx: f32, roughness: f32) -> Dialectric {
+        Dialectric {
+            thin: true,
+            ..Dialectric::rough(ref_idx, roughness)
+        }
+    }
+
+    fn ggx(&self, rec: &HitRecord) -> Ggx {
+        let roughness = scalar_texture(rec, &*self.roughness);
+        Ggx::from_roughness(roughness, roughness)
+    }
+
+    /// The index beyond the surface relative to the side the ray arrived from, and the light
+    /// left after travelling to the surface from that side.
+    fn side(&self, rec: &HitRecord) -> (f32, Color) {
+        if self.thin || rec.front_face {
+            (self.ref_idx, Color::new(1.0, 1.0, 1.0))
+        } else {
+            // The ray has just crossed the inside of the solid
+            let transmittance = Color::new(
+                (-self.absorption.x * rec.t).exp(),
+                (-self.absorption.y * rec.t).exp(),
+                (-self.absorption.z * rec.t).exp(),
+            );
+            (1.0 / self.ref_idx, transmittance)
+        }
+    }
+
+    /// Reflectance at `cos_theta` to a microfacet. For a thin sheet this sums the light bounced
+    /// back and forth between its two faces.
+    fn reflectance(&self, cos_theta: f32, eta: f32) -> f32 {
+        let r = fresnel_dielectric(cos_theta, eta);
+        if self.thin && r < 1.0 {
+            r + (1.0 - r) * (1.0 - r) * r / (1.0 - r * r)
+        } else {
+            r
+        }
+    }
+
+    /// Both `eval` and `pdf` for the rough surface, which share most of their work.
+    fn evaluate(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> (Color, f32) {
+        let ggx = self.ggx(rec);
+        if ggx.is_smooth() || wo.z <= 0.0 {
+            return (Color::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let (eta, transmittance) = self.side(rec);
+
+        let (fraction, (value, pdf)) = if wi.z > 0.0 || self.thin {
+            // A thin sheet transmits into the mirror image of the reflected direction
+            let transmitted = wi.z < 0.0;
+            let wi = upper(wi, transmitted);
+            let m = match reflection_half_vector(wo, &wi) {
+                Some(m) => m,
+                None => return (Color::new(0.0, 0.0, 0.0), 0.0),
+            };
+            let r = self.reflectance(wo.dot(&m), eta);
+            let fraction = if transmitted { 1.0 - r } else { r };
+            (fraction, ggx_reflection(&ggx, wo, &wi, &m))
+        } else {
+            let m = match refraction_half_vector(wo, wi, eta) {
+                Some(m) => m,
+                None => return (Color::new(0.0, 0.0, 0.0), 0.0),
+            };
+            let fraction = 1.0 - fresnel_dielectric(wo.dot(&m), eta);
+            (fraction, ggx_transmission(&ggx, wo, wi, &m, eta))
+        };
+        (fraction * value * transmittance, fraction * pdf)
     }
 }
 
 impl Material for Dialectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut rng = rand::thread_rng();
-
-        let reflected = reflect(&r_in.direction, &rec.normal);
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let (outward_normal, ni_over_nt, cosine) = if r_in.direction.dot(&rec.normal) > 0.0 {
-            (
-                -1.0 * rec.normal,
-                self.ref_idx,
-                self.ref_idx * r_in.direction.dot(&rec.normal) / r_in.direction.len(),
-            )
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let (eta, transmittance) = self.side(rec);
+
+        let ggx = self.ggx(rec);
+        if ggx.is_smooth() {
+            let r = self.reflectance(wo.z, eta);
+            let (wi, fraction) = if rng.gen::<f32>() < r {
+                (Vec3::new(-wo.x, -wo.y, wo.z), r)
+            } else if self.thin {
+                (-*wo, 1.0 - r)
+            } else {
+                (refract(wo, &Vec3::new(0.0, 0.0, 1.0), eta)?, 1.0 - r)
+            };
+            return Some(BsdfSample::delta(wi, fraction * transmittance, fraction));
+        }
+
+        let m = ggx.sample_visible(wo, rng.gen::<f32>(), rng.gen::<f32>());
+        let wi = if rng.gen::<f32>() < self.reflectance(wo.dot(&m), eta) {
+            reflect(&-*wo, &m)
+        } else if self.thin {
+            upper(&reflect(&-*wo, &m), true)
         } else {
-            (
-                rec.normal,
-                1.0 / self.ref_idx,
-                -1.0 * r_in.direction.dot(&rec.normal) / r_in.direction.len(),
-            )
+            refract(wo, &m, eta)?
         };
-        let refracted = refract(&r_in.direction, &outward_normal, ni_over_nt);
-        let reflect_prob = if refracted.is_some() {
-            schlick(cosine, self.ref_idx)
+        let (f, pdf) = self.evaluate(rec, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(wi, f, pdf))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        self.evaluate(rec, wo, wi).0
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        self.evaluate(rec, wo, wi).1
+    }
+}
+
+/// Roughness is kept above this so that every lobe of `Principled` has a finite density, which
+/// lets its sampling densities be mixed
+const MIN_PRINCIPLED_ROUGHNESS: f32 = 0.05;
+
+/// An artist-friendly material in the style of the Disney principled BSDF, blending a diffuse
+/// base with sheen, a GGX specular layer that can be metallic, anisotropic or transmissive, and a
+/// clearcoat on top. All parameters are in `[0, 1]`.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    anisotropic: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    sheen_tint: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_roughness: Arc<dyn Texture>,
+    transmission: Arc<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled::textured(Arc::new(SolidColor::new(base_color)))
+    }
+
+    /// A rough dielectric with the default specular of 0.5, which corresponds to an index of
+    /// refraction of 1.5.
+    pub fn textured(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: Arc::new(SolidColor::gray(0.0)),
+            roughness: Arc::new(SolidColor::gray(0.5)),
+            anisotropic: Arc::new(SolidColor::gray(0.0)),
+            specular: Arc::new(SolidColor::gray(0.5)),
+            sheen: Arc::new(SolidColor::gray(0.0)),
+            sheen_tint: Arc::new(SolidColor::gray(0.5)),
+            clearcoat: Arc::new(SolidColor::gray(0.0)),
+            clearcoat_roughness: Arc::new(SolidColor::gray(0.1)),
+            transmission: Arc::new(SolidColor::gray(0.0)),
+        }
+    }
+
+    pub fn metallic(self, metallic: f32) -> Principled {
+        self.metallic_map(Arc::new(SolidColor::gray(metallic)))
+    }
+
+    pub fn metallic_map(self, metallic: Arc<dyn Texture>) -> Principled {
+        Principled { metallic, ..self }
+    }
+
+    pub fn roughness(self, roughness: f32) -> Principled {
+        self.roughness_map(Arc::new(SolidColor::gray(roughness)))
+    }
+
+    pub fn roughness_map(self, roughness: Arc<dyn Texture>) -> Principled {
+        Principled { roughness, ..self }
+    }
+
+    /// Stretches highlights along `dpdu` of the surface.
+    pub fn anisotropic(self, anisotropic: f32) -> Principled {
+        self.anisotropic_map(Arc::new(SolidColor::gray(anisotropic)))
+    }
+
+    pub fn anisotropic_map(self, anisotropic: Arc<dyn Texture>) -> Principled {
+        Principled {
+            anisotropic,
+            ..self
+        }
+    }
+
+    /// Scales the reflectance of the dielectric at normal incidence from 0 to 8%.
+    pub fn specular(self, specular: f32) -> Principled {
+        self.specular_map(Arc::new(SolidColor::gray(specular)))
+    }
+
+    pub fn specular_map(self, specular: Arc<dyn Texture>) -> Principled {
+        Principled { specular, ..self }
+    }
+
+    /// A soft rim of light at grazing angles as seen on cloth, `tint` blending it from white
+    /// towards the base colour.
+    pub fn sheen(self, sheen: f32, tint: f32) -> Principled {
+        self.sheen_map(
+            Arc::new(SolidColor::gray(sheen)),
+            Arc::new(SolidColor::gray(tint)),
+        )
+    }
+
+    pub fn sheen_map(self, sheen: Arc<dyn Texture>, tint: Arc<dyn Texture>) -> Principled {
+        Principled {
+            sheen,
+            sheen_tint: tint,
+            ..self
+        }
+    }
+
+    /// A clear varnish layer with its own roughness.
+    pub fn clearcoat(self, clearcoat: f32, roughness: f32) -> Principled {
+        self.clearcoat_map(
+            Arc::new(SolidColor::gray(clearcoat)),
+            Arc::new(SolidColor::gray(roughness)),
+        )
+    }
+
+    pub fn clearcoat_map(
+        self,
+        clearcoat: Arc<dyn Texture>,
+        roughness: Arc<dyn Texture>,
+    ) -> Principled {
+        Principled {
+            clearcoat,
+            clearcoat_roughness: roughness,
+            ..self
+        }
+    }
+
+    /// How much of the dielectric base refracts light instead of scattering it diffusely.
+    pub fn transmission(self, transmission: f32) -> Principled {
+        self.transmission_map(Arc::new(SolidColor::gray(transmission)))
+    }
+
+    pub fn transmission_map(self, transmission: Arc<dyn Texture>) -> Principled {
+        Principled {
+            transmission,
+            ..self
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let entering = rec.front_face;
+        let roughness = scalar_texture(rec, &*self.roughness).max(MIN_PRINCIPLED_ROUGHNESS);
+        let anisotropic = scalar_texture(rec, &*self.anisotropic).clamp(0.0, 1.0);
+        let aspect = (1.0 - 0.9 * anisotropic).sqrt();
+        let alpha = roughness * roughness;
+        let clearcoat_roughness =
+            scalar_texture(rec, &*self.clearcoat_roughness).max(MIN_PRINCIPLED_ROUGHNESS);
+
+        // Specular maps linearly to reflectance at normal incidence, from which the index of
+        // refraction follows
+        let f0 = (0.08 * scalar_texture(rec, &*self.specular)).clamp(1e-4, 0.99);
+        let ior = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+
+        PrincipledLobes {
+            base_color: rec.texture(&*self.base_color),
+            metallic: scalar_texture(rec, &*self.metallic),
+            roughness,
+            specular: Ggx::new(alpha / aspect, alpha * aspect),
+            eta: if entering { ior } else { 1.0 / ior },
+            // Only the refracting part of the material exists on the inside of a solid
+            outside: entering,
+            sheen: scalar_texture(rec, &*self.sheen),
+            sheen_tint: scalar_texture(rec, &*self.sheen_tint),
+            clearcoat: if entering {
+                scalar_texture(rec, &*self.clearcoat)
+            } else {
+                0.0
+            },
+            clearcoat_ggx: Ggx::from_roughness(clearcoat_roughness, clearcoat_roughness),
+            transmission: scalar_texture(rec, &*self.transmission).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn sample(&self, rec: &HitRecord, wo: &Vec3, rng: &mut dyn RngCore) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(rec);
+        let wi = lobes.sample(wo, rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())?;
+        let pdf = lobes.pdf(wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(wi, lobes.eval(wo, &wi), pdf))
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.lobes(rec).eval(wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.lobes(rec).pdf(wo, wi)
+    }
+}
+
+/// The parameters of a `Principled` material resolved at one point, with directions in the local
+/// shading frame and `wo` above the surface.
+struct PrincipledLobes {
+    base_color: Color,
+    metallic: f32,
+    roughness: f32,
+    specular: Ggx,
+    eta: f32,
+    outside: bool,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_ggx: Ggx,
+    transmission: f32,
+}
+
+impl PrincipledLobes {
+    fn diffuse_weight(&self) -> f32 {
+        if self.outside {
+            (1.0 - self.metallic) * (1.0 - self.transmission)
         } else {
-            1.0
-        };
-        if rng.gen::<f32>() < reflect_prob {
-            Some((Ray::new(rec.p, reflected), attenuation))
+            0.0
+        }
+    }
+
+    fn transmission_weight(&self) -> f32 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    /// Probabilities of sampling the diffuse, specular and clearcoat lobes, roughly following
+    /// how much each reflects.
+    fn lobe_probabilities(&self, wo: &Vec3) -> (f32, f32, f32) {
+        let fresnel = fresnel_dielectric(wo.z, self.eta);
+        let diffuse = self.diffuse_weight() * (1.0 - fresnel) * average(&self.base_color);
+        let specular = self.metallic
+            + (1.0 - self.metallic) * fresnel
+            + self.transmission_weight() * (1.0 - fresnel);
+        let clearcoat = 0.25 * self.clearcoat * schlick(wo.z, 0.04);
+        let total = diffuse + specular + clearcoat;
+        (diffuse / total, specular / total, clearcoat / total)
+    }
+
+    /// Reflectance of the specular layer off a microfacet at `cos_theta` to `wo`, blending the
+    /// dielectric towards a metal tinted by the base colour.
+    fn specular_fresnel(&self, cos_theta: f32) -> Color {
+        let dielectric = (1.0 - self.metallic) * fresnel_dielectric(cos_theta, self.eta);
+        let metal = self.metallic * schlick_color(cos_theta, &self.base_color);
+        Color::new(dielectric, dielectric, dielectric) + metal
+    }
+
+    /// Of the light sampled from the specular lobe through microfacet `m`, the fraction that is
+    /// reflected rather than refracted.
+    fn reflect_probability(&self, wo: &Vec3, m: &Vec3) -> f32 {
+        let transmission = self.transmission_weight();
+        if transmission <= 0.0 {
+            return 1.0;
+        }
+        let cos_theta = wo.dot(m);
+        let reflected = average(&self.specular_fresnel(cos_theta));
+        let refracted = transmission * (1.0 - fresnel_dielectric(cos_theta, self.eta));
+        reflected / (reflected + refracted)
+    }
+
+    /// The BSDF value, without the cosine term.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wi.z == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        if wi.z < 0.0 {
+            let transmission = self.transmission_weight();
+            let m = match refraction_half_vector(wo, wi, self.eta) {
+                Some(m) if transmission > 0.0 => m,
+                _ => return Color::new(0.0, 0.0, 0.0),
+            };
+            let value = transmission
+                * (1.0 - fresnel_dielectric(wo.dot(&m), self.eta))
+                * ggx_transmission(&self.specular, wo, wi, &m, self.eta).0;
+            return value * self.base_color;
+        }
+
+        let m = (*wo + *wi).into_unit_vector();
+        let cos_d = wi.dot(&m);
+
+        let mut value =
+            ggx_reflection(&self.specular, wo, wi, &m).0 * self.specular_fresnel(wo.dot(&m));
+
+        let diffuse = self.diffuse_weight();
+        if diffuse > 0.0 {
+            // Retro-reflection at grazing angles grows with roughness
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * (1.0 - wi.z).powi(5))
+                * (1.0 + (fd90 - 1.0) * (1.0 - wo.z).powi(5));
+            value += diffuse * retro / PI * self.base_color;
+
+            if self.sheen > 0.0 {
+                let luminance = average(&self.base_color);
+                let tint = if luminance > 0.0 {
+                    self.base_color / luminance
+                } else {
+                    Color::new(1.0, 1.0, 1.0)
+                };
+                let color =
+                    (1.0 - self.sheen_tint) * Color::new(1.0, 1.0, 1.0) + self.sheen_tint * tint;
+                value += diffuse * self.sheen * (1.0 - cos_d).powi(5) * color;
+            }
+        }
+
+        if self.clearcoat > 0.0 {
+            let coat = 0.25
+                * self.clearcoat
+                * ggx_reflection(&self.clearcoat_ggx, wo, wi, &m).0
+                * schlick(wo.dot(&m), 0.04);
+            value += Color::new(coat, coat, coat);
+        }
+        value
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let (p_diffuse, p_specular, p_clearcoat) = self.lobe_probabilities(wo);
+        if wi.z < 0.0 {
+            return match refraction_half_vector(wo, wi, self.eta) {
+                Some(m) => {
+                    p_specular
+                        * (1.0 - self.reflect_probability(wo, &m))
+                        * ggx_transmission(&self.specular, wo, wi, &m, self.eta).1
+                }
+                None => 0.0,
+            };
+        }
+
+        match reflection_half_vector(wo, wi) {
+            Some(m) => {
+                p_diffuse * sampling::cosine_hemisphere_pdf(wi.z)
+                    + p_specular
+                        * self.reflect_probability(wo, &m)
+                        * ggx_reflection(&self.specular, wo, wi, &m).1
+                    + p_clearcoat * ggx_reflection(&self.clearcoat_ggx, wo, wi, &m).1
+            }
+            None => 0.0,
+        }
+    }
+
+    fn sample(&self, wo: &Vec3, u0: f32, u1: f32, u2: f32) -> Option<Vec3> {
+        let (p_diffuse, p_specular, _) = self.lobe_probabilities(wo);
+        let wi = if u0 < p_diffuse {
+            sampling::cosine_hemisphere(u1, u2)
+        } else if u0 < p_diffuse + p_specular {
+            let m = self.specular.sample_visible(wo, u1, u2);
+            // Reuse the part of u0 that falls within this lobe to pick reflection or refraction
+            if (u0 - p_diffuse) / p_specular < self.reflect_probability(wo, &m) {
+                reflect(&-*wo, &m)
+            } else {
+                refract(wo, &m, self.eta)?
+            }
         } else {
-            Some((Ray::new(rec.p, refracted.unwrap()), attenuation))
+            let m = self.clearcoat_ggx.sample_visible(wo, u1, u2);
+            reflect(&-*wo, &m)
+        };
+        if wi.z < 0.0 && self.transmission_weight() <= 0.0 {
+            return None;
         }
+        Some(wi)
     }
 }
 
-fn refract(v: &Vec3, n: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
-    let uv = v.unit_vector();
-    let dt = uv.dot(n);
-    let discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
-    if discriminant > 0.0 {
-        Some(ni_over_nt * (uv - n * dt) - n * discriminant.sqrt())
-    } else {
-        None
+fn average(c: &Color) -> f32 {
+    (c.x + c.y + c.z) / 3.0
+}
+
+fn schlick(cos_theta: f32, f0: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick_color(cos_theta: f32, f0: &Color) -> Color {
+    let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    *f0 + weight * (Color::new(1.0, 1.0, 1.0) - *f0)
+}
+
+/// Refracts `wo`, which points away from the surface on the side of `n`, into the medium whose
+/// index relative to the current one is `eta`. Returns `None` on total internal reflection.
+fn refract(wo: &Vec3, n: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = wo.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
     }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + (cos_i / eta - cos_t) * n)
 }
 
-fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    let r0_2 = r0 * r0;
-    r0_2 + (1.0 - r0_2) * (1.0 - cosine).powi(5)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::vec::Point3;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn materials() -> Vec<Arc<dyn Material>> {
+        let albedo = Color::new(0.8, 0.6, 0.4);
+        vec![
+            Arc::new(Lambertian::new(albedo)),
+            Arc::new(OrenNayar::new(albedo, 20.0)),
+            Arc::new(Metal::rough(albedo, 0.6)),
+            Arc::new(Conductor::new(ComplexIor::COPPER, 0.5)),
+            Arc::new(Conductor::anisotropic(
+                ComplexIor::GOLD,
+                Arc::new(SolidColor::gray(0.7)),
+                Arc::new(SolidColor::gray(0.4)),
+            )),
+            Arc::new(Dialectric::rough(1.5, 0.6)),
+            Arc::new(Dialectric::thin(1.5, 0.6)),
+            Arc::new(
+                Principled::new(albedo)
+                    .metallic(0.3)
+                    .roughness(0.6)
+                    .sheen(0.5, 0.5)
+                    .clearcoat(0.5, 0.5),
+            ),
+            Arc::new(Principled::new(albedo).roughness(0.7).transmission(0.6)),
+            Arc::new(Isotropic::new(albedo)),
+            Arc::new(HenyeyGreenstein::new(albedo, 0.5)),
+        ]
+    }
+
+    /// A hit on the plane z = 0 from above, or from below when `front_face` is false.
+    fn hit(material: &Arc<dyn Material>, front_face: bool) -> HitRecord {
+        let z = if front_face { 1.0 } else { -1.0 };
+        let r = Ray::new(Point3::new(0.0, 0.0, z), Vec3::new(0.0, 0.0, -z), 0.0);
+        let mut rec = HitRecord::new(
+            &r,
+            1.0,
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            (0.5, 0.5),
+            material.clone(),
+        );
+        rec.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        rec
+    }
+
+    fn wo() -> Vec3 {
+        Vec3::new(0.3, -0.2, 0.9).into_unit_vector()
+    }
+
+    #[test]
+    fn samples_agree_with_eval_and_pdf() {
+        let mut rng = StdRng::seed_from_u64(17);
+        for material in materials() {
+            for front_face in [true, false] {
+                let rec = hit(&material, front_face);
+                for _ in 0..2000 {
+                    let sample = match material.sample(&rec, &wo(), &mut rng) {
+                        Some(sample) if !sample.delta => sample,
+                        _ => continue,
+                    };
+                    assert!((sample.wi.len() - 1.0).abs() < 1e-3);
+                    let pdf = material.pdf(&rec, &wo(), &sample.wi);
+                    assert!(
+                        (pdf - sample.pdf).abs() <= 1e-3 * pdf.max(1.0),
+                        "{:?}: sampled pdf {} but pdf gives {}",
+                        material,
+                        sample.pdf,
+                        pdf
+                    );
+                    let f = material.eval(&rec, &wo(), &sample.wi);
+                    assert!(
+                        (f - sample.f).abs().max_component() <= 1e-3 * f.max_component().max(1.0),
+                        "{:?}: sampled f {:?} but eval gives {:?}",
+                        material,
+                        sample.f,
+                        f
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_directions_follow_pdf() {
+        // Bin the sphere into cells of equal solid angle, uniform in z and in the angle around
+        // z, and compare how often samples land in each against the integral of the pdf
+        const Z_BINS: usize = 8;
+        const PHI_BINS: usize = 8;
+        const SUBDIVISIONS: usize = 32;
+        const SAMPLES: usize = 100_000;
+        let cell = |wi: &Vec3| {
+            let z = ((wi.z + 1.0) * 0.5 * Z_BINS as f32) as usize;
+            let phi = (wi.y.atan2(wi.x) + PI) / (2.0 * PI) * PHI_BINS as f32;
+            z.min(Z_BINS - 1) * PHI_BINS + (phi as usize).min(PHI_BINS - 1)
+        };
+
+        let mut rng = StdRng::seed_from_u64(23);
+        for material in materials() {
+            for front_face in [true, false] {
+                let rec = hit(&material, front_face);
+                let mut observed = [0.0f32; Z_BINS * PHI_BINS];
+                for _ in 0..SAMPLES {
+                    if let Some(sample) = material.sample(&rec, &wo(), &mut rng) {
+                        if !sample.delta {
+                            observed[cell(&sample.wi)] += 1.0 / SAMPLES as f32;
+                        }
+                    }
+                }
+
+                let steps_z = Z_BINS * SUBDIVISIONS;
+                let steps_phi = PHI_BINS * SUBDIVISIONS;
+                let d_omega = (2.0 / steps_z as f32) * (2.0 * PI / steps_phi as f32);
+                let mut expected = [0.0f32; Z_BINS * PHI_BINS];
+                for i in 0..steps_z {
+                    let z = -1.0 + (i as f32 + 0.5) * 2.0 / steps_z as f32;
+                    let r = (1.0 - z * z).sqrt();
+                    for j in 0..steps_phi {
+                        let phi = -PI + (j as f32 + 0.5) * 2.0 * PI / steps_phi as f32;
+                        let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                        expected[cell(&wi)] += material.pdf(&rec, &wo(), &wi) * d_omega;
+                    }
+                }
+
+                for (observed, expected) in observed.iter().zip(expected.iter()) {
+                    assert!(
+                        (observed - expected).abs() < 0.01,
+                        "{:?} (front face {}): {} sampled, {} expected",
+                        material,
+                        front_face,
+                        observed,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    /// Light left after crossing `distance` of glass tinted to `color` over `tint_distance`.
+    fn beer_lambert(color: Color, tint_distance: f32, distance: f32) -> Color {
+        let fade = |c: f32| c.powf(distance / tint_distance);
+        Color::new(fade(color.x), fade(color.y), fade(color.z))
+    }
+
+    #[test]
+    fn smooth_glass_splits_light_by_fresnel_and_snell() {
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(32);
+        let tint = Color::new(0.5, 0.8, 1.0);
+        let glass: Arc<dyn Material> = Arc::new(Dialectric::new(1.5).tinted(tint, 2.0));
+        for front_face in [true, false] {
+            let mut rec = hit(&glass, front_face);
+            rec.t = 3.0;
+            let (eta, transmittance) = if front_face {
+                (1.5, Color::new(1.0, 1.0, 1.0))
+            } else {
+                (1.0 / 1.5, beer_lambert(tint, 2.0, 3.0))
+            };
+            let wo = wo();
+            let mut reflected = 0;
+            for _ in 0..SAMPLES {
+                let sample = glass.sample(&rec, &wo, &mut rng).unwrap();
+                assert!(sample.delta);
+                if sample.wi.z > 0.0 {
+                    reflected += 1;
+                    assert!((sample.wi - Vec3::new(-wo.x, -wo.y, wo.z)).len() < 1e-5);
+                    assert!((sample.weight() - transmittance).abs().max_component() < 1e-5);
+                } else {
+                    // The tangential part of the direction shrinks by the ratio of the indices
+                    let tangent = Vec3::new(sample.wi.x, sample.wi.y, 0.0);
+                    let expected = Vec3::new(-wo.x, -wo.y, 0.0) / eta;
+                    assert!((tangent - expected).len() < 1e-5);
+                    assert!((sample.weight() - transmittance).abs().max_component() < 1e-5);
+                }
+            }
+            let expected = fresnel_dielectric(wo.z, eta);
+            assert!((reflected as f32 / SAMPLES as f32 - expected).abs() < 0.005);
+        }
+
+        // Past the critical angle light inside is always reflected
+        let rec = hit(&glass, false);
+        let grazing = Vec3::new(0.8, 0.0, 0.6);
+        for _ in 0..100 {
+            let sample = glass.sample(&rec, &grazing, &mut rng).unwrap();
+            assert!(sample.wi.z > 0.0);
+        }
+    }
+
+    #[test]
+    fn thin_glass_passes_light_straight_through() {
+        // Bouncing between the two faces adds up to 2R / (1 + R) of the light reflected
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(33);
+        let pane: Arc<dyn Material> = Arc::new(Dialectric::thin(1.5, 0.0));
+        let rec = hit(&pane, true);
+        // Near grazing, where a single face already reflects a good share
+        let wo = Vec3::new(0.95, 0.0, 0.3).into_unit_vector();
+        let mut reflected = 0;
+        for _ in 0..SAMPLES {
+            let sample = pane.sample(&rec, &wo, &mut rng).unwrap();
+            assert!((sample.weight().x - 1.0).abs() < 1e-5);
+            if sample.wi.z > 0.0 {
+                reflected += 1;
+            } else {
+                assert!((sample.wi + wo).len() < 1e-5);
+            }
+        }
+        let r = fresnel_dielectric(wo.z, 1.5);
+        assert!((reflected as f32 / SAMPLES as f32 - 2.0 * r / (1.0 + r)).abs() < 0.005);
+    }
+
+    #[test]
+    fn rough_glass_loses_little_energy() {
+        // Only light scattered more than once between microfacets is lost, which is next to
+        // none when the surface is nearly smooth, where the share reflected follows Fresnel
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(34);
+        for (roughness, lowest) in [(0.1, 0.995), (0.3, 0.98), (0.6, 0.8)] {
+            let glass: Arc<dyn Material> = Arc::new(Dialectric::rough(1.5, roughness));
+            for front_face in [true, false] {
+                let rec = hit(&glass, front_face);
+                let mut total = 0.0;
+                let mut reflected = 0.0;
+                for _ in 0..SAMPLES {
+                    if let Some(sample) = glass.sample(&rec, &wo(), &mut rng) {
+                        total += sample.weight().x / SAMPLES as f32;
+                        if sample.wi.z > 0.0 {
+                            reflected += sample.weight().x / SAMPLES as f32;
+                        }
+                    }
+                }
+                assert!(total > lowest && total < 1.005, "{} {}", roughness, total);
+                if roughness == 0.1 {
+                    let eta = if front_face { 1.5 } else { 1.0 / 1.5 };
+                    assert!((reflected - fresnel_dielectric(wo().z, eta)).abs() < 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn diffuse_sampling_is_cosine_weighted() {
+        // Under a cosine-weighted distribution the cosine averages 2/3 and its square 1/2, and
+        // every sample carries exactly the albedo
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(35);
+        let albedo = Color::new(0.8, 0.6, 0.4);
+        let lambertian: Arc<dyn Material> = Arc::new(Lambertian::new(albedo));
+        for front_face in [true, false] {
+            let rec = hit(&lambertian, front_face);
+            let (mut cos, mut cos2) = (0.0, 0.0);
+            for _ in 0..SAMPLES {
+                let sample = lambertian.sample(&rec, &wo(), &mut rng).unwrap();
+                assert!((sample.wi.len() - 1.0).abs() < 1e-4 && sample.wi.z > 0.0);
+                assert!((sample.weight() - albedo).abs().max_component() < 1e-4);
+                cos += sample.wi.z / SAMPLES as f32;
+                cos2 += sample.wi.z * sample.wi.z / SAMPLES as f32;
+            }
+            assert!((cos - 2.0 / 3.0).abs() < 0.005);
+            assert!((cos2 - 0.5).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn oren_nayar_reflects_a_share_of_lambertian() {
+        let albedo = Color::new(0.8, 0.6, 0.4);
+        let lambertian: Arc<dyn Material> = Arc::new(Lambertian::new(albedo));
+        let smooth: Arc<dyn Material> = Arc::new(OrenNayar::new(albedo, 0.0));
+        let rec = hit(&lambertian, true);
+        let mut rng = StdRng::seed_from_u64(36);
+        for _ in 0..1000 {
+            let wo = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+            let wi = sampling::uniform_sphere(rng.gen::<f32>(), rng.gen::<f32>());
+            let difference = smooth.eval(&rec, &wo, &wi) - lambertian.eval(&rec, &wo, &wi);
+            assert!(difference.abs().max_component() < 1e-6);
+        }
+
+        // Seen straight on, the rough surface reflects A = 1 - σ² / (2 (σ² + 0.33)) of what a
+        // Lambertian one would, whichever way the light arrives
+        let sigma = 30.0f32;
+        let sigma2 = sigma.to_radians().powi(2);
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let rough: Arc<dyn Material> = Arc::new(OrenNayar::new(albedo, sigma));
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        for _ in 0..1000 {
+            let sample = rough.sample(&rec, &wo, &mut rng).unwrap();
+            assert!((sample.weight() - a * albedo).abs().max_component() < 1e-5);
+        }
+        // Seen from the side it is brighter towards the viewer than away
+        let wo = Vec3::new(0.8, 0.0, 0.6);
+        let towards = rough.eval(&rec, &wo, &Vec3::new(0.6, 0.0, 0.8));
+        let away = rough.eval(&rec, &wo, &Vec3::new(-0.6, 0.0, 0.8));
+        assert!(towards.x > away.x && away.x >= a * albedo.x / PI - 1e-6);
+    }
+
+    #[test]
+    fn henyey_greenstein_has_mean_cosine_g() {
+        // The phase function integrates to one over the sphere, and the cosine between the
+        // direction light was travelling and the one it's scattered into averages g
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(42);
+        let albedo = Color::new(1.0, 1.0, 1.0);
+        for g in [-0.6, 0.0, 0.3, 0.8] {
+            let phase: Arc<dyn Material> = Arc::new(HenyeyGreenstein::new(albedo, g));
+            let rec = hit(&phase, true);
+            let mean_cos = (0..SAMPLES)
+                .map(|_| -wo().dot(&phase.sample(&rec, &wo(), &mut rng).unwrap().wi))
+                .sum::<f32>()
+                / SAMPLES as f32;
+            assert!((mean_cos - g).abs() < 0.01, "g = {}: {}", g, mean_cos);
+
+            let steps = 400;
+            let d_omega = (2.0 / steps as f32) * (2.0 * PI / steps as f32);
+            let mut total = 0.0;
+            for i in 0..steps {
+                let z = -1.0 + (i as f32 + 0.5) * 2.0 / steps as f32;
+                let r = (1.0 - z * z).sqrt();
+                for j in 0..steps {
+                    let phi = (j as f32 + 0.5) * 2.0 * PI / steps as f32;
+                    let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                    total += phase.pdf(&rec, &wo(), &wi) * d_omega;
+                }
+            }
+            assert!((total - 1.0).abs() < 0.01, "g = {}: {}", g, total);
+        }
+    }
 }
@@ -0,0 +1,660 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Isotropic, Material};
+use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
+use crate::vec::{Color, Point3};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use rand::{Rng, RngCore};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A volume of uniform density filling a closed boundary shape, such as smoke or fog. Rays are
+/// scattered at random depths inside with an exponential distribution, so thicker regions are
+/// more opaque. The boundary's own material is ignored.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f32,
+    phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hittable>, density: f32, albedo: Color) -> ConstantMedium {
+        ConstantMedium::textured(boundary, density, Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(
+        boundary: Box<dyn Hittable>,
+        density: f32,
+        albedo: Arc<dyn Texture>,
+    ) -> ConstantMedium {
+        ConstantMedium::with_phase(boundary, density, Arc::new(Isotropic::textured(albedo)))
+    }
+
+    /// Scatters with `phase`, which should be a volumetric material.
+    pub fn with_phase(
+        boundary: Box<dyn Hittable>,
+        density: f32,
+        phase: Arc<dyn Material>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase,
+        }
+    }
+}
+
+impl ConstantMedium {
+    /// The parametric interval of the ray inside the boundary, clipped to `[t_min, t_max]`.
+    fn interval(
+        &self,
+        r: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut dyn RngCore,
+    ) -> Option<(f32, f32)> {
+        // Hitting the inside of the boundary first means the ray starts in the volume
+        let first = self.boundary.hit(r, t_min, f32::MAX, rng)?;
+        let (t_enter, t_exit) = if first.front_face {
+            let exit = self.boundary.hit(r, first.t + 1e-4, f32::MAX, rng)?;
+            (first.t, exit.t)
+        } else {
+            (t_min, first.t)
+        };
+        let t_exit = t_exit.min(t_max);
+        if t_enter < t_exit {
+            Some((t_enter, t_exit))
+        } else {
+            None
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let (t_enter, t_exit) = self.interval(r, t_min, t_max, rng)?;
+
+        let ray_length = r.direction.len();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = -(1.0 - rng.gen::<f32>()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // Volumes have no surface, so face the normal back along the ray
+        Some(HitRecord::new(
+            r,
+            t,
+            r.point_at_parameter(t),
+            -r.direction.unit_vector(),
+            (0.0, 0.0),
+            self.phase.clone(),
+        ))
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Color {
+        match self.interval(r, t_min, t_max, rng) {
+            Some((t_enter, t_exit)) => {
+                let transmittance = (-self.density * (t_exit - t_enter) * r.direction.len()).exp();
+                Color::new(transmittance, transmittance, transmittance)
+            }
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// A dense 3D grid of density samples, such as one exported from a fluid simulation.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    /// Densities with x varying fastest, then y, then z.
+    data: Vec<f32>,
+    max: f32,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> VoxelGrid {
+        assert!(nx > 0 && ny > 0 && nz > 0, "voxel grid must not be empty");
+        assert_eq!(
+            data.len(),
+            nx * ny * nz,
+            "voxel count doesn't match dimensions"
+        );
+        let max = data.iter().fold(0.0f32, |max, &d| max.max(d));
+        VoxelGrid {
+            nx,
+            ny,
+            nz,
+            data,
+            max,
+        }
+    }
+
+    /// Fills a grid by evaluating `density` at the centre of each voxel, given in `[0, 1]` grid
+    /// coordinates.
+    pub fn from_fn<F: Fn(&Point3) -> f32>(
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        density: F,
+    ) -> VoxelGrid {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    data.push(density(&Point3::new(
+                        (x as f32 + 0.5) / nx as f32,
+                        (y as f32 + 0.5) / ny as f32,
+                        (z as f32 + 0.5) / nz as f32,
+                    )));
+                }
+            }
+        }
+        VoxelGrid::new(nx, ny, nz, data)
+    }
+
+    /// Reads a raw grid file: the x, y and z resolution as little-endian `u32`s, followed by
+    /// that many little-endian `f32` densities with x varying fastest.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<VoxelGrid> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let nx = reader.read_u32::<LittleEndian>()? as usize;
+        let ny = reader.read_u32::<LittleEndian>()? as usize;
+        let nz = reader.read_u32::<LittleEndian>()? as usize;
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(nz))
+            .filter(|&n| n > 0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad grid dimensions"))?;
+        let mut data = vec![0.0; count];
+        reader.read_f32_into::<LittleEndian>(&mut data)?;
+        if reader.read(&mut [0u8])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailing data after grid",
+            ));
+        }
+        Ok(VoxelGrid::new(nx, ny, nz, data))
+    }
+
+    /// The largest density anywhere in the grid.
+    pub fn max_density(&self) -> f32 {
+        self.max
+    }
+
+    /// Trilinearly interpolated density at `p` in `[0, 1]` grid coordinates, treating voxel
+    /// values as samples at voxel centres. Outside the grid the edge values carry on.
+    pub fn density(&self, p: &Point3) -> f32 {
+        let axis = |coord: f32, n: usize| {
+            let x = (coord * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = (x as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), x - i as f32)
+        };
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+        let at = |x: usize, y: usize, z: usize| self.data[(z * self.ny + y) * self.nx + x];
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        lerp(
+            lerp(
+                lerp(at(x0, y0, z0), at(x1, y0, z0), fx),
+                lerp(at(x0, y1, z0), at(x1, y1, z0), fx),
+                fy,
+            ),
+            lerp(
+                lerp(at(x0, y0, z1), at(x1, y0, z1), fx),
+                lerp(at(x0, y1, z1), at(x1, y1, z1), fx),
+                fy,
+            ),
+            fz,
+        )
+    }
+}
+
+/// A volume whose density varies through space, looked up from a voxel grid stretched over
+/// `bounds`. Scattering distances are sampled by delta tracking and shadow rays are attenuated
+/// by ratio tracking, both against the grid's maximum density, so the estimates are unbiased
+/// however the density varies.
+pub struct GridMedium {
+    grid: VoxelGrid,
+    bounds: Aabb,
+    density_scale: f32,
+    phase: Arc<dyn Material>,
+}
+
+impl GridMedium {
+    /// Scales the grid's values by `density_scale`, and scatters with `phase`, which should be
+    /// a volumetric material.
+    pub fn new(
+        grid: VoxelGrid,
+        bounds: Aabb,
+        density_scale: f32,
+        phase: Arc<dyn Material>,
+    ) -> GridMedium {
+        GridMedium {
+            grid,
+            bounds,
+            density_scale,
+            phase,
+        }
+    }
+
+    fn density(&self, p: &Point3) -> f32 {
+        let extent = self.bounds.max - self.bounds.min;
+        let local = *p - self.bounds.min;
+        self.density_scale
+            * self.grid.density(&Point3::new(
+                local.x / extent.x,
+                local.y / extent.y,
+                local.z / extent.z,
+            ))
+    }
+
+    /// Steps along the ray through the bounds by exponentially distributed distances against the
+    /// majorant, calling `collide` with each tentative collision's parameter and the fraction of
+    /// the majorant that's real there, along with `rng` for deciding what happens. Stops when
+    /// `collide` returns false or the ray leaves.
+    fn track<F: FnMut(f32, f32, &mut dyn RngCore) -> bool>(
+        &self,
+        r: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut dyn RngCore,
+        mut collide: F,
+    ) {
+        let majorant = self.density_scale * self.grid.max_density();
+        if majorant <= 0.0 {
+            return;
+        }
+        let (mut t, t_exit) = match self.bounds.hit(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return,
+        };
+        let ray_length = r.direction.len();
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() / (majorant * ray_length);
+            if t >= t_exit {
+                return;
+            }
+            let real = self.density(&r.point_at_parameter(t)) / majorant;
+            if !collide(t, real, rng) {
+                return;
+            }
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        // Delta tracking: each tentative collision is real with probability density / majorant,
+        // otherwise it's a null collision and the ray carries on
+        let mut scatter = None;
+        self.track(r, t_min, t_max, rng, |t, real, rng| {
+            if rng.gen::<f32>() < real {
+                scatter = Some(t);
+                false
+            } else {
+                true
+            }
+        });
+
+        let t = scatter?;
+        Some(HitRecord::new(
+            r,
+            t,
+            r.point_at_parameter(t),
+            -r.direction.unit_vector(),
+            (0.0, 0.0),
+            self.phase.clone(),
+        ))
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Color {
+        // Ratio tracking: weight by the chance of each collision being null rather than picking
+        // one, which gives a smooth estimate instead of all or nothing
+        let mut transmittance = 1.0;
+        self.track(r, t_min, t_max, rng, |_, real, _| {
+            transmittance *= 1.0 - real.min(1.0);
+            transmittance > 0.0
+        });
+        Color::new(transmittance, transmittance, transmittance)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+/// A medium filling all of space around the scene, for fog and haze over outdoor scenes that
+/// can't be wrapped in a boundary. Its density falls off exponentially with height above
+/// `base_height`, or stays uniform when `falloff` is zero. Every ray segment the integrator
+/// traces passes through it, including those inside other objects.
+pub struct Fog {
+    density: f32,
+    base_height: f32,
+    falloff: f32,
+    phase: Arc<dyn Material>,
+}
+
+impl Fog {
+    /// Fog with `density` at `base_height`, thinning by a factor of e every `1 / falloff`
+    /// units above it.
+    pub fn height(density: f32, base_height: f32, falloff: f32, albedo: Color) -> Fog {
+        Fog {
+            density,
+            base_height,
+            falloff,
+            phase: Arc::new(Isotropic::new(albedo)),
+        }
+    }
+
+    /// Scatters with `phase`, which should be a volumetric material.
+    pub fn with_phase(mut self, phase: Arc<dyn Material>) -> Fog {
+        self.phase = phase;
+        self
+    }
+
+    /// Coefficients of the optical depth along the ray from `t_min`, which is
+    /// `a * (1 - exp(-b t)) / b` a parametric distance `t` further on.
+    fn optical_depth_coefficients(&self, r: &Ray, t_min: f32) -> (f32, f32) {
+        let origin = r.point_at_parameter(t_min);
+        let a = self.density
+            * (-self.falloff * (origin.y - self.base_height)).exp()
+            * r.direction.len();
+        (a, self.falloff * r.direction.y)
+    }
+
+    /// The fraction of light that gets through the fog along the ray between `t_min` and
+    /// `t_max`.
+    pub fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let (a, b) = self.optical_depth_coefficients(r, t_min);
+        let t = t_max - t_min;
+        let optical_depth = if b.abs() < 1e-6 {
+            a * t
+        } else {
+            -a * (-b * t).exp_m1() / b
+        };
+        (-optical_depth).exp()
+    }
+
+    /// Samples where along the ray between `t_min` and `t_max` it scatters off the fog,
+    /// returning `None` if it gets through.
+    pub fn sample(
+        &self,
+        r: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord> {
+        // Inverts the optical depth in closed form
+        let (a, b) = self.optical_depth_coefficients(r, t_min);
+        if a <= 0.0 {
+            return None;
+        }
+        let optical_depth = -(1.0 - rng.gen::<f32>()).ln();
+        let distance = if b.abs() < 1e-6 {
+            optical_depth / a
+        } else {
+            // Rising rays can leave the fog before building up enough optical depth
+            let x = 1.0 - optical_depth * b / a;
+            if x <= 0.0 {
+                return None;
+            }
+            -x.ln() / b
+        };
+        let t = t_min + distance;
+        if t >= t_max {
+            return None;
+        }
+
+        Some(HitRecord::new(
+            r,
+            t,
+            r.point_at_parameter(t),
+            -r.direction.unit_vector(),
+            (0.0, 0.0),
+            self.phase.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Sphere;
+    use crate::material::Lambertian;
+    use crate::vec::Vec3;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn ball(radius: f32, density: f32) -> ConstantMedium {
+        let boundary = Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            radius,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        ConstantMedium::new(Box::new(boundary), density, Color::new(0.8, 0.8, 0.8))
+    }
+
+    #[test]
+    fn constant_media_scatter_at_exponential_depths() {
+        // Along a path of length L through density σ a ray scatters with probability
+        // 1 - exp(-σL), at a mean depth of 1/σ - L exp(-σL) / (1 - exp(-σL))
+        const SAMPLES: usize = 100_000;
+        let mut rng = StdRng::seed_from_u64(39);
+        let density = 0.7;
+        let medium = ball(1.5, density);
+        // From outside straight through the middle, from inside, and with a ray whose
+        // direction isn't a unit vector
+        let rays = [
+            (
+                Ray::new(Point3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0), 0.0),
+                2.5,
+                3.0,
+            ),
+            (
+                Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0),
+                0.0,
+                2.0f32.sqrt(),
+            ),
+            (
+                Ray::new(Point3::new(0.0, -4.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 0.0),
+                2.5,
+                3.0,
+            ),
+        ];
+        for (r, start, length) in rays {
+            let (mut scattered, mut depth) = (0, 0.0);
+            for _ in 0..SAMPLES {
+                if let Some(hit) = medium.hit(&r, 0.0, f32::MAX, &mut rng) {
+                    scattered += 1;
+                    assert!(hit.material.is_volumetric());
+                    depth += (hit.p - r.origin).len() - start;
+                }
+            }
+            let transmittance = (-density * length).exp();
+            let escaped = 1.0 - scattered as f32 / SAMPLES as f32;
+            assert!((escaped - transmittance).abs() < 0.005);
+            let mean_depth = 1.0 / density - length * transmittance / (1.0 - transmittance);
+            assert!((depth / scattered as f32 - mean_depth).abs() < 0.01);
+            let exact = medium.transmittance(&r, 0.0, f32::MAX, &mut rng);
+            assert!((exact.x - transmittance).abs() < 1e-4);
+        }
+
+        // Stopping short of the far side only counts the part of the path before it
+        let r = &rays[0].0;
+        let partial = medium.transmittance(r, 0.0, 3.5, &mut rng);
+        assert!((partial.x - (-density * 1.0f32).exp()).abs() < 1e-4);
+    }
+
+    /// A box twice as long in x as it is wide, whose density rises along x and y, and a ray
+    /// slanting through it.
+    fn ramp() -> (GridMedium, Ray) {
+        let grid = VoxelGrid::from_fn(16, 8, 8, |p| 0.5 + 2.0 * p.x + p.y);
+        let bounds = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+        let phase = Arc::new(Isotropic::new(Color::new(0.8, 0.8, 0.8)));
+        let r = Ray::new(Point3::new(-1.0, 0.2, 0.3), Vec3::new(1.0, 0.2, 0.1), 0.0);
+        (GridMedium::new(grid, bounds, 0.8, phase), r)
+    }
+
+    /// The optical depth along `r` from where it enters the medium up to `t`, by the midpoint
+    /// rule.
+    fn optical_depth(medium: &GridMedium, r: &Ray, t: f32) -> f32 {
+        const STEPS: usize = 10_000;
+        let (t_enter, t_exit) = medium.bounds.hit(r, 0.0, f32::MAX).unwrap();
+        let t = t.min(t_exit);
+        let dt = (t - t_enter) / STEPS as f32;
+        (0..STEPS)
+            .map(|i| medium.density(&r.point_at_parameter(t_enter + (i as f32 + 0.5) * dt)))
+            .sum::<f32>()
+            * dt
+            * r.direction.len()
+    }
+
+    #[test]
+    fn voxel_grids_interpolate_between_voxel_centres() {
+        let grid = VoxelGrid::from_fn(5, 4, 3, |p| p.x + 2.0 * p.y - p.z);
+        assert_eq!(grid.max_density(), 0.9 + 2.0 * 0.875 - 1.0 / 6.0);
+        for (x, y, z) in [(0.1, 0.125, 0.5), (0.37, 0.6, 0.3), (0.7, 0.5, 0.62)] {
+            // Linear within the voxel centres, and constant beyond them
+            let expected = x + 2.0 * y - z;
+            assert!((grid.density(&Point3::new(x, y, z)) - expected).abs() < 1e-5);
+        }
+        let outside = grid.density(&Point3::new(-1.0, 2.0, 0.5));
+        assert!((outside - (0.1 + 2.0 * 0.875 - 0.5)).abs() < 1e-5);
+
+        // Between the centres of a curved grid it blends the two nearest voxels
+        let grid = VoxelGrid::from_fn(5, 1, 1, |p| p.x * p.x);
+        for (x, expected) in [(0.3, 0.09), (0.2, 0.05), (0.45, 0.21), (0.85, 0.73)] {
+            let density = grid.density(&Point3::new(x, 0.5, 0.5));
+            assert!((density - expected).abs() < 1e-5, "at {}", x);
+        }
+    }
+
+    #[test]
+    fn ratio_tracking_estimates_transmittance() {
+        const SAMPLES: usize = 20_000;
+        let mut rng = StdRng::seed_from_u64(40);
+        let (medium, r) = ramp();
+        for t_max in [1.8, f32::MAX] {
+            let expected = (-optical_depth(&medium, &r, t_max)).exp();
+            let estimate = (0..SAMPLES)
+                .map(|_| medium.transmittance(&r, 0.0, t_max, &mut rng).x)
+                .sum::<f32>()
+                / SAMPLES as f32;
+            assert!(
+                (estimate - expected).abs() < 0.005,
+                "{} {}",
+                estimate,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn delta_tracking_scatters_by_optical_depth() {
+        // The chance of scattering before t is 1 - exp(-τ(t)), however the density varies
+        const SAMPLES: usize = 50_000;
+        let mut rng = StdRng::seed_from_u64(41);
+        let (medium, r) = ramp();
+        let checkpoints = [1.3, 1.6, 2.0, 2.4, f32::MAX];
+        let mut counts = [0; 5];
+        for _ in 0..SAMPLES {
+            if let Some(hit) = medium.hit(&r, 0.0, f32::MAX, &mut rng) {
+                for (count, t) in counts.iter_mut().zip(checkpoints) {
+                    if hit.t < t {
+                        *count += 1;
+                    }
+                }
+            }
+        }
+        for (count, t) in counts.iter().zip(checkpoints) {
+            let expected = 1.0 - (-optical_depth(&medium, &r, t)).exp();
+            assert!(
+                (*count as f32 / SAMPLES as f32 - expected).abs() < 0.01,
+                "before {}",
+                t
+            );
+        }
+    }
+
+    /// The fog's optical depth along `r` from `t_min` to `t_max`, by the midpoint rule.
+    fn fog_depth(fog: &Fog, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        const STEPS: usize = 10_000;
+        let dt = (t_max - t_min) / STEPS as f32;
+        (0..STEPS)
+            .map(|i| {
+                let p = r.point_at_parameter(t_min + (i as f32 + 0.5) * dt);
+                fog.density * (-fog.falloff * (p.y - fog.base_height)).exp()
+            })
+            .sum::<f32>()
+            * dt
+            * r.direction.len()
+    }
+
+    /// Rays falling, rising and level through the fog, not all with unit directions.
+    fn fog_rays() -> [Ray; 3] {
+        [
+            Ray::new(Point3::new(0.0, 3.0, 0.0), Vec3::new(0.6, -0.3, 0.2), 0.0),
+            Ray::new(Point3::new(1.0, -0.5, 2.0), Vec3::new(-0.4, 0.8, 0.0), 0.0),
+            Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 2.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn height_fog_transmittance_integrates_the_density() {
+        for falloff in [0.0, 0.7] {
+            let fog = Fog::height(0.3, 0.5, falloff, Color::new(1.0, 1.0, 1.0));
+            for r in fog_rays() {
+                for (t_min, t_max) in [(0.0, 1.0), (0.5, 6.0)] {
+                    let expected = (-fog_depth(&fog, &r, t_min, t_max)).exp();
+                    let transmittance = fog.transmittance(&r, t_min, t_max);
+                    assert!((transmittance - expected).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn height_fog_scatters_by_optical_depth() {
+        const SAMPLES: usize = 50_000;
+        let mut rng = StdRng::seed_from_u64(42);
+        let fog = Fog::height(0.3, 0.5, 0.7, Color::new(1.0, 1.0, 1.0));
+        for r in fog_rays() {
+            let checkpoints = [1.0, 2.5, 6.0];
+            let mut counts = [0; 3];
+            for _ in 0..SAMPLES {
+                if let Some(hit) = fog.sample(&r, 0.5, 6.0, &mut rng) {
+                    assert!(hit.t > 0.5 && hit.t < 6.0);
+                    for (count, t) in counts.iter_mut().zip(checkpoints) {
+                        if hit.t < t {
+                            *count += 1;
+                        }
+                    }
+                }
+            }
+            for (count, t) in counts.iter().zip(checkpoints) {
+                let expected = 1.0 - fog.transmittance(&r, 0.5, t);
+                assert!((*count as f32 / SAMPLES as f32 - expected).abs() < 0.01);
+            }
+        }
+
+        // A ray climbing out of the fog gets through with the transmittance of the whole way up
+        let r = &fog_rays()[1];
+        let escaped = (0..SAMPLES)
+            .filter(|_| fog.sample(r, 0.0, f32::MAX, &mut rng).is_none())
+            .count();
+        let expected = (-fog_depth(&fog, r, 0.0, 60.0)).exp();
+        assert!((escaped as f32 / SAMPLES as f32 - expected).abs() < 0.01);
+    }
+}
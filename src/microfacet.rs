@@ -0,0 +1,376 @@
+use crate::vec::{Color, Vec3};
+
+/// Alphas are kept at least this large, since sampling and evaluating the distribution becomes
+/// numerically unstable below it. Distributions this smooth along both axes are treated as a
+/// perfect mirror.
+const MIN_ALPHA: f32 = 1e-3;
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, with Smith height-correlated
+/// masking-shadowing.
+///
+/// Directions are in the local shading frame, with the macrosurface normal along +z and the
+/// `alpha_x` roughness along the x axis.
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f32, alpha_y: f32) -> Ggx {
+        Ggx {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    /// Maps perceptual roughness in `[0, 1]` to alpha by squaring, which makes the change in
+    /// highlight size look roughly linear.
+    pub fn from_roughness(roughness_x: f32, roughness_y: f32) -> Ggx {
+        Ggx::new(roughness_x * roughness_x, roughness_y * roughness_y)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) <= MIN_ALPHA
+    }
+
+    /// Density of microfacet normals `m`, normalised over the projected area of the surface.
+    pub fn d(&self, m: &Vec3) -> f32 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (m.x / self.alpha_x).powi(2) + (m.y / self.alpha_y).powi(2) + m.z * m.z;
+        1.0 / (std::f32::consts::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f32 {
+        if w.z == 0.0 {
+            return f32::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        0.5 * (-1.0 + (1.0 + a2 / (w.z * w.z)).sqrt())
+    }
+
+    /// Fraction of microfacets facing `w` that are visible from it.
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both directions.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density with which `sample_visible` picks the microfacet normal `m` when seen from `wo`.
+    pub fn pdf_visible(&self, wo: &Vec3, m: &Vec3) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `wo`, using
+    /// Heitz's method of sampling the projected hemisphere of the stretched configuration.
+    /// `wo` must be above the surface.
+    pub fn sample_visible(&self, wo: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretch to the configuration where alpha is 1 and the distribution is a hemisphere
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).into_unit_vector();
+        let len_sq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_sq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+
+        // Sample a disk, squashing the half that is hidden by the tilt of the hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).into_unit_vector()
+    }
+}
+
+/// A complex index of refraction per colour channel, describing how much light a conductor
+/// reflects at each angle.
+#[derive(Debug, Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+/// Measured values sampled at roughly 650nm, 550nm and 450nm for the red, green and blue
+/// channels.
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: Color {
+            x: 0.143,
+            y: 0.374,
+            z: 1.442,
+        },
+        k: Color {
+            x: 3.983,
+            y: 2.385,
+            z: 1.603,
+        },
+    };
+    pub const SILVER: ComplexIor = ComplexIor {
+        eta: Color {
+            x: 0.155,
+            y: 0.117,
+            z: 0.138,
+        },
+        k: Color {
+            x: 4.828,
+            y: 3.122,
+            z: 2.147,
+        },
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: Color {
+            x: 0.200,
+            y: 0.924,
+            z: 1.102,
+        },
+        k: Color {
+            x: 3.912,
+            y: 2.452,
+            z: 2.142,
+        },
+    };
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: Color {
+            x: 1.657,
+            y: 0.880,
+            z: 0.521,
+        },
+        k: Color {
+            x: 9.224,
+            y: 6.270,
+            z: 4.837,
+        },
+    };
+    pub const IRON: ComplexIor = ComplexIor {
+        eta: Color {
+            x: 2.870,
+            y: 2.920,
+            z: 2.580,
+        },
+        k: Color {
+            x: 3.080,
+            y: 2.930,
+            z: 2.820,
+        },
+    };
+}
+
+/// Exact Fresnel reflectance of a conductor for light arriving at `cos_theta` to the normal.
+pub fn fresnel_conductor(cos_theta: f32, ior: &ComplexIor) -> Color {
+    Color::new(
+        fresnel_conductor_channel(cos_theta, ior.eta.x, ior.k.x),
+        fresnel_conductor_channel(cos_theta, ior.eta.y, ior.k.y),
+        fresnel_conductor_channel(cos_theta, ior.eta.z, ior.k.z),
+    )
+}
+
+fn fresnel_conductor_channel(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Exact Fresnel reflectance of unpolarised light at a boundary between dielectrics, where `eta`
+/// is the ratio of the index on the far side of the normal to the near side. A negative
+/// `cos_theta` means the light arrives from the far side. Returns 1 on total internal reflection.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_theta < 0.0 {
+        (-cos_theta.max(-1.0), 1.0 / eta)
+    } else {
+        (cos_theta.min(1.0), eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    fn distributions() -> [Ggx; 3] {
+        [Ggx::new(0.5, 0.5), Ggx::new(0.3, 0.3), Ggx::new(0.6, 0.2)]
+    }
+
+    fn direction(theta: f32, phi: f32) -> Vec3 {
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    /// Midpoint rule over the upper hemisphere.
+    fn integrate_hemisphere<F: Fn(&Vec3) -> f32>(f: F) -> f32 {
+        const THETA_STEPS: usize = 1000;
+        const PHI_STEPS: usize = 200;
+        let d_theta = 0.5 * PI / THETA_STEPS as f32;
+        let d_phi = 2.0 * PI / PHI_STEPS as f32;
+        let mut sum = 0.0f64;
+        for i in 0..THETA_STEPS {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..PHI_STEPS {
+                let phi = (j as f32 + 0.5) * d_phi;
+                sum += (f(&direction(theta, phi)) * theta.sin() * d_theta * d_phi) as f64;
+            }
+        }
+        sum as f32
+    }
+
+    #[test]
+    fn projected_normals_cover_the_surface() {
+        for ggx in distributions() {
+            let area = integrate_hemisphere(|m| ggx.d(m) * m.z);
+            assert!(
+                (area - 1.0).abs() < 1e-2,
+                "{:?} integrates to {}",
+                ggx,
+                area
+            );
+        }
+    }
+
+    #[test]
+    fn visible_normals_pdf_is_normalised() {
+        for ggx in distributions() {
+            for theta in [0.0, 0.6, 1.2, 1.5] {
+                let wo = direction(theta, 0.7);
+                let total = integrate_hemisphere(|m| ggx.pdf_visible(&wo, m));
+                assert!(
+                    (total - 1.0).abs() < 1e-2,
+                    "{:?} at {}: {}",
+                    ggx,
+                    theta,
+                    total
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn masking_is_a_fraction() {
+        for ggx in distributions() {
+            assert!((ggx.g1(&Vec3::new(0.0, 0.0, 1.0)) - 1.0).abs() < 1e-6);
+            let mut previous = 1.0;
+            for i in 1..=10 {
+                let w = direction(0.15 * i as f32, 1.0);
+                let g1 = ggx.g1(&w);
+                assert!(g1 > 0.0 && g1 <= previous);
+                previous = g1;
+                assert!(ggx.g2(&w, &w) <= g1);
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_normals_follow_pdf() {
+        // Bin sampled normals by polar angle and compare against the integral of the pdf
+        const BINS: usize = 8;
+        const SAMPLES: usize = 200_000;
+        let mut rng = StdRng::seed_from_u64(11);
+        for ggx in distributions() {
+            let wo = direction(0.9, 2.0);
+            let mut counts = [0usize; BINS];
+            for _ in 0..SAMPLES {
+                let m = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+                assert!((m.len() - 1.0).abs() < 1e-4);
+                let bin = (m.z.clamp(0.0, 1.0).acos() / (0.5 * PI) * BINS as f32) as usize;
+                counts[bin.min(BINS - 1)] += 1;
+            }
+            for (bin, &count) in counts.iter().enumerate() {
+                let low = bin as f32 / BINS as f32 * 0.5 * PI;
+                let high = (bin + 1) as f32 / BINS as f32 * 0.5 * PI;
+                let expected = integrate_hemisphere(|m| {
+                    let theta = m.z.acos();
+                    if theta >= low && theta < high {
+                        ggx.pdf_visible(&wo, m)
+                    } else {
+                        0.0
+                    }
+                });
+                let observed = count as f32 / SAMPLES as f32;
+                assert!(
+                    (observed - expected).abs() < 5e-3,
+                    "{:?} bin {}: {} sampled, {} expected",
+                    ggx,
+                    bin,
+                    observed,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // Head on, a dielectric reflects ((eta - 1) / (eta + 1))^2 from either side
+        let r0 = (0.5f32 / 2.5).powi(2);
+        assert!((fresnel_dielectric(1.0, 1.5) - r0).abs() < 1e-6);
+        assert!((fresnel_dielectric(-1.0, 1.5) - r0).abs() < 1e-6);
+        // Past the critical angle inside the denser medium everything reflects
+        assert_eq!(fresnel_dielectric(-0.3, 1.5), 1.0);
+        assert!((fresnel_dielectric(1e-4, 1.5) - 1.0).abs() < 1e-2);
+
+        // A conductor with no absorption is a dielectric
+        for i in 1..=10 {
+            let cos_theta = 0.1 * i as f32;
+            let conductor = fresnel_conductor_channel(cos_theta, 1.5, 0.0);
+            assert!((conductor - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-4);
+        }
+        let gold = fresnel_conductor(1.0, &ComplexIor::GOLD);
+        assert!(gold.x > gold.z && gold.x < 1.0);
+    }
+
+    #[test]
+    fn one_smooth_axis_stays_finite() {
+        let ggx = Ggx::from_roughness(0.0, 0.6);
+        assert!(!ggx.is_smooth());
+        assert!(Ggx::from_roughness(0.0, 0.0).is_smooth());
+
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..10_000 {
+            let wo = direction(rng.gen_range(0.0..1.5), rng.gen_range(0.0..2.0 * PI));
+            let m = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+            let pdf = ggx.pdf_visible(&wo, &m);
+            assert!(m.x.is_finite() && m.y.is_finite() && m.z.is_finite());
+            assert!(pdf.is_finite() && pdf >= 0.0, "pdf {} for {:?}", pdf, m);
+            assert!(ggx.d(&m).is_finite());
+            assert!(ggx.g2(&wo, &m).is_finite());
+        }
+    }
+}
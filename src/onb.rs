@@ -0,0 +1,73 @@
+use crate::vec::Vec3;
+
+/// An orthonormal basis, used as a local shading frame with `w` along the surface normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let (u, v) = w.orthonormal_basis();
+        Onb { u, v, w }
+    }
+
+    /// A basis around `n` with `u` as close as possible to `tangent`, falling back to an
+    /// arbitrary orientation if the tangent is zero or parallel to the normal.
+    pub fn from_wu(n: &Vec3, tangent: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.square_len() < 1e-12 {
+            return Onb::from_w(&w);
+        }
+        let u = u.unit_vector();
+        let v = w.cross(&u);
+        Onb { u, v, w }
+    }
+
+    pub fn to_local(self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+
+    pub fn to_world(self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling;
+
+    #[test]
+    fn bases_are_orthonormal_and_right_handed() {
+        let mut normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 1e-7),
+        ];
+        for i in 0..40 {
+            for j in 0..40 {
+                normals.push(sampling::uniform_sphere(
+                    (i as f32 + 0.5) / 40.0,
+                    (j as f32 + 0.5) / 40.0,
+                ));
+            }
+        }
+        let a = Vec3::new(0.3, -1.2, 2.0);
+        for n in normals {
+            let onb = Onb::from_w(&n);
+            for (x, y) in [(onb.u, onb.v), (onb.v, onb.w), (onb.w, onb.u)] {
+                assert!(x.dot(&y).abs() < 1e-5, "{:?}", n);
+                assert!((x.len() - 1.0).abs() < 1e-5, "{:?}", n);
+            }
+            assert!((onb.u.cross(&onb.v) - onb.w).len() < 1e-5, "{:?}", n);
+            assert!((onb.to_world(&onb.to_local(&a)) - a).len() < 1e-5);
+            assert!((onb.to_local(&n).z - 1.0).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,688 @@
+//! Photon mapping after Jensen, "Realistic Image Synthesis Using Photon Mapping": light is
+//! traced out from the emitters first and left as photons on the surfaces it reaches, and
+//! camera rays then estimate the light around them from the density of nearby photons. Sharp
+//! caustics through glass come out cleanly this way, where paths from the camera rarely find
+//! the light behind them.
+//!
+//! Lights at infinity such as the sun shoot their photons in from a disk as wide as the scene's
+//! bounding sphere, so scenes on a huge ground get few of them where it matters. The sky behind
+//! the scene isn't a light and leaves no photons. Volumes hold no photons and are only lit
+//! directly.
+
+use crate::bdpt::shading_correction;
+use crate::hittable::HitRecord;
+use crate::integrator::{self, Integrator, Splats};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampling;
+use crate::scene::Scene;
+use crate::vec::{Color, Point3, Vec3};
+
+use rand::{Rng, RngCore};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+
+/// Light left on a surface by a path from an emitter.
+#[derive(Debug, Copy, Clone)]
+pub struct Photon {
+    p: Point3,
+    /// The true normal of the surface, facing the side the photon arrived on.
+    normal: Vec3,
+    /// Unit direction back towards where the photon came from.
+    wi: Vec3,
+    power: Color,
+}
+
+/// Photons in a balanced kd-tree, for finding those near a point. The tree is kept implicitly
+/// in the order of the photons: the middle photon of each range splits the rest of it in two.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// The axis the photon at each index splits its range along.
+    axes: Vec<usize>,
+}
+
+/// A photon found near a point, ordered by how far away it is.
+struct Neighbour {
+    distance_squared: f32,
+    index: usize,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Neighbour) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Neighbour) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Neighbour) -> Ordering {
+        self.distance_squared.total_cmp(&other.distance_squared)
+    }
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+        Self::build(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    /// Splits the photons at the median along the axis they're most spread along.
+    fn build(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.len() <= 1 {
+            return;
+        }
+        let (min, max) = photons
+            .iter()
+            .fold((photons[0].p, photons[0].p), |(min, max), photon| {
+                (min.min(&photon.p), max.max(&photon.p))
+            });
+        let extent = max - min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        let middle = photons.len() / 2;
+        photons.select_nth_unstable_by(middle, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+        axes[middle] = axis;
+        let (below, above) = photons.split_at_mut(middle);
+        let (below_axes, above_axes) = axes.split_at_mut(middle);
+        Self::build(below, below_axes);
+        Self::build(&mut above[1..], &mut above_axes[1..]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    /// The photons within `radius` of `p`.
+    pub fn within(&self, p: &Point3, radius: f32) -> Vec<&Photon> {
+        let mut found = Vec::new();
+        self.visit(
+            0,
+            self.photons.len(),
+            p,
+            radius * radius,
+            &mut |index, _| {
+                found.push(&self.photons[index]);
+                radius * radius
+            },
+        );
+        found
+    }
+
+    /// Up to `count` of the photons nearest to `p` and within `max_radius` of it, with the
+    /// squared radius of the disk they were gathered from: out to the furthest of them if
+    /// `count` were found, or `max_radius` otherwise.
+    pub fn nearest(&self, p: &Point3, count: usize, max_radius: f32) -> (Vec<&Photon>, f32) {
+        let max_radius_squared = max_radius * max_radius;
+        let mut heap = BinaryHeap::with_capacity(count + 1);
+        self.visit(
+            0,
+            self.photons.len(),
+            p,
+            max_radius_squared,
+            &mut |index, distance_squared| {
+                heap.push(Neighbour {
+                    distance_squared,
+                    index,
+                });
+                if heap.len() > count {
+                    heap.pop();
+                }
+                // Once enough are found, only closer photons are of interest
+                match heap.peek() {
+                    Some(furthest) if heap.len() == count => furthest.distance_squared,
+                    _ => max_radius_squared,
+                }
+            },
+        );
+        let radius_squared = match heap.peek() {
+            Some(furthest) if heap.len() == count => furthest.distance_squared,
+            _ => max_radius_squared,
+        };
+        let found = heap
+            .into_iter()
+            .map(|neighbour| &self.photons[neighbour.index])
+            .collect();
+        (found, radius_squared)
+    }
+
+    /// Calls `found` with the index and squared distance of each photon in `start..end` within
+    /// the squared radius of `p` it last returned.
+    fn visit<F: FnMut(usize, f32) -> f32>(
+        &self,
+        start: usize,
+        end: usize,
+        p: &Point3,
+        mut radius_squared: f32,
+        found: &mut F,
+    ) -> f32 {
+        if start >= end {
+            return radius_squared;
+        }
+        let middle = start + (end - start) / 2;
+        let photon = &self.photons[middle];
+        let distance_squared = (photon.p - *p).square_len();
+        if distance_squared <= radius_squared {
+            radius_squared = found(middle, distance_squared);
+        }
+
+        let axis = self.axes[middle];
+        let offset = p[axis] - photon.p[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        radius_squared = self.visit(near.0, near.1, p, radius_squared, found);
+        if offset * offset <= radius_squared {
+            radius_squared = self.visit(far.0, far.1, p, radius_squared, found);
+        }
+        radius_squared
+    }
+}
+
+/// Which of the photons traced from the lights are kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Keep {
+    /// Every photon after the first bounce, for indirect light of all kinds.
+    Indirect,
+    /// Photons that came only through mirrors and glass, which make caustics.
+    Caustic,
+}
+
+/// How many photons are traced with each random number generator.
+const PHOTONS_PER_BATCH: usize = 4096;
+
+/// Traces `count` photons out from the scene's lights, `max_depth` bounces at most, sharing
+/// the lights' power between them. The same `seed` and `stream` give the same photons.
+fn trace_photons(
+    scene: &Scene,
+    count: usize,
+    max_depth: u32,
+    keep: Keep,
+    seed: u64,
+    stream: u32,
+) -> Vec<Photon> {
+    if scene.lights.is_empty() {
+        return Vec::new();
+    }
+    (0..count.div_ceil(PHOTONS_PER_BATCH))
+        .into_par_iter()
+        .flat_map_iter(|batch| {
+            // Each batch has a generator of its own, so the photons don't depend on how the
+            // batches are shared between threads
+            let mut rng = sampling::seeded_rng(seed, &[stream, batch as u32]);
+            let mut photons = Vec::new();
+            let start = batch * PHOTONS_PER_BATCH;
+            for _ in start..count.min(start + PHOTONS_PER_BATCH) {
+                trace_photon(scene, count, max_depth, keep, &mut rng, &mut photons);
+            }
+            photons
+        })
+        .collect()
+}
+
+fn trace_photon(
+    scene: &Scene,
+    count: usize,
+    max_depth: u32,
+    keep: Keep,
+    rng: &mut dyn RngCore,
+    photons: &mut Vec<Photon>,
+) {
+    let pmf = 1.0 / scene.lights.len() as f32;
+    let light = &scene.lights[rng.gen_range(0..scene.lights.len())];
+    let emission = match light.sample_emission(rng) {
+        Some(emission) if emission.pdf_position > 0.0 && emission.pdf_direction > 0.0 => emission,
+        _ => return,
+    };
+    let cos_theta = emission
+        .normal
+        .map_or(1.0, |n| n.dot(&emission.direction).abs());
+    let mut power = emission.radiance * cos_theta
+        / (pmf * emission.pdf_position * emission.pdf_direction * count as f32);
+    let mut r = Ray::new(
+        emission.origin,
+        emission.direction,
+        scene.camera.shutter_time(rng),
+    );
+
+    let mut specular_only = true;
+    for depth in 0..max_depth {
+        let hit = match integrator::intersect(scene, &r, rng) {
+            Some(hit) => hit,
+            None => break,
+        };
+        let frame = hit.shading_frame();
+        let wo = -r.direction.unit_vector();
+        let volumetric = hit.material.is_volumetric();
+        let stored = match keep {
+            Keep::Indirect => depth > 0,
+            Keep::Caustic => depth > 0 && specular_only,
+        };
+        if stored && !volumetric {
+            photons.push(Photon {
+                p: hit.p,
+                normal: hit.normal,
+                wi: wo,
+                power,
+            });
+        }
+
+        let sample = match hit.material.sample(&hit, &frame.to_local(&wo), rng) {
+            Some(sample) => sample,
+            None => break,
+        };
+        if keep == Keep::Caustic && !sample.delta {
+            break;
+        }
+        specular_only &= sample.delta;
+        let wi = frame.to_world(&sample.wi);
+        let weight = if volumetric {
+            sample.f / sample.pdf
+        } else {
+            sample.weight() * shading_correction(&hit, &frame, &wo, &wi)
+        };
+
+        // Russian roulette by how much each bounce keeps, so that photons' power stays about
+        // the same and the density of photons follows the light
+        let survival = weight.max_component().min(1.0);
+        if survival <= 0.0 || rng.gen::<f32>() >= survival {
+            break;
+        }
+        power *= weight / survival;
+        r = Ray::new(hit.p, wi, r.time);
+    }
+}
+
+/// Light scattered towards `wo` at `hit` by `photons` found within a disk of the given squared
+/// radius around it.
+fn estimate(
+    hit: &HitRecord,
+    frame: &Onb,
+    wo: &Vec3,
+    photons: &[&Photon],
+    radius_squared: f32,
+) -> Color {
+    let mut radiance = Color::new(0.0, 0.0, 0.0);
+    if radius_squared <= 0.0 {
+        return radiance;
+    }
+    for photon in photons {
+        // Photons on the other side of thin surfaces, or around sharp corners, light something
+        // else
+        if photon.normal.dot(&hit.normal) > 0.5 {
+            radiance += photon.power * hit.material.eval(hit, wo, &frame.to_local(&photon.wi));
+        }
+    }
+    radiance / (PI * radius_squared)
+}
+
+/// Follows `r` through mirrors and glass, adding up the light seen along the way, and lets
+/// `shade` work out the light leaving each point it reaches by other kinds of scattering.
+fn follow_specular<F>(
+    scene: &Scene,
+    mut r: Ray,
+    max_depth: u32,
+    rng: &mut dyn RngCore,
+    shade: F,
+) -> Color
+where
+    F: Fn(&HitRecord, &Onb, &Vec3, &mut dyn RngCore) -> Color,
+{
+    let mut radiance = Color::new(0.0, 0.0, 0.0);
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    for _ in 0..=max_depth {
+        let hit = match integrator::intersect(scene, &r, rng) {
+            Some(hit) => hit,
+            None => {
+                radiance += throughput * integrator::background_light(scene, &r);
+                break;
+            }
+        };
+        radiance += throughput * hit.material.emitted(&hit);
+
+        let frame = hit.shading_frame();
+        let wo = frame.to_local(&-r.direction.unit_vector());
+        radiance += throughput * shade(&hit, &frame, &wo, rng);
+
+        match hit.material.sample(&hit, &wo, rng) {
+            Some(sample) if sample.delta => {
+                throughput *= sample.weight();
+                r = Ray::new(hit.p, frame.to_world(&sample.wi), r.time);
+            }
+            _ => break,
+        }
+    }
+    radiance
+}
+
+/// How many photons radiance is estimated from.
+const NEAREST_PHOTONS: usize = 50;
+/// How far away photons for the caustic and indirect estimates can be.
+const CAUSTIC_RADIUS: f32 = 0.2;
+const GLOBAL_RADIUS: f32 = 1.0;
+
+/// Two-pass photon mapping. Light is sampled directly, caustics come from a dense map of their
+/// own, and other indirect light is gathered by tracing rays off each point and looking up the
+/// photons where they land, which hides the blotchiness of the photons' density.
+pub struct PhotonMapper {
+    photons: usize,
+    caustic_photons: usize,
+    gather_rays: u32,
+    max_depth: u32,
+    global: PhotonMap,
+    caustics: PhotonMap,
+}
+
+impl PhotonMapper {
+    /// Traces `photons` photons for indirect light and `caustic_photons` more to look for
+    /// caustics with, both `max_depth` bounces at most, and gathers with `gather_rays` rays
+    /// for each camera ray.
+    pub fn new(
+        photons: usize,
+        caustic_photons: usize,
+        gather_rays: u32,
+        max_depth: u32,
+    ) -> PhotonMapper {
+        PhotonMapper {
+            photons,
+            caustic_photons,
+            gather_rays,
+            max_depth,
+            global: PhotonMap::new(Vec::new()),
+            caustics: PhotonMap::new(Vec::new()),
+        }
+    }
+
+    /// Indirect light scattered towards `wo` at `hit`, from the photons where gather rays land.
+    fn final_gather(
+        &self,
+        scene: &Scene,
+        hit: &HitRecord,
+        frame: &Onb,
+        wo: &Vec3,
+        time: f32,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..self.gather_rays {
+            // Delta lobes are followed by the camera ray itself
+            let sample = match hit.material.sample(hit, wo, rng) {
+                Some(sample) if !sample.delta => sample,
+                _ => continue,
+            };
+            let weight = if hit.material.is_volumetric() {
+                sample.f / sample.pdf
+            } else {
+                sample.weight()
+            };
+            let r = Ray::new(hit.p, frame.to_world(&sample.wi), time);
+            radiance += weight * self.gathered(scene, r, rng);
+        }
+        radiance / self.gather_rays.max(1) as f32
+    }
+
+    /// The light arriving back along a gather ray, less what reaches its start straight from
+    /// the lights or as a caustic, which are found in other ways.
+    fn gathered(&self, scene: &Scene, r: Ray, rng: &mut dyn RngCore) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut r = r;
+        for depth in 0..=self.max_depth {
+            let hit = match integrator::intersect(scene, &r, rng) {
+                Some(hit) => hit,
+                None => {
+                    // The sky leaves no photons, so its caustics are found here
+                    if depth > 0 {
+                        radiance += throughput * scene.background(&r);
+                    }
+                    break;
+                }
+            };
+
+            let frame = hit.shading_frame();
+            let wo = frame.to_local(&-r.direction.unit_vector());
+            radiance +=
+                throughput * integrator::direct_lighting(scene, &hit, &frame, &wo, r.time, rng);
+            if !hit.material.is_volumetric() {
+                let (photons, radius_squared) =
+                    self.global.nearest(&hit.p, NEAREST_PHOTONS, GLOBAL_RADIUS);
+                radiance += throughput * estimate(&hit, &frame, &wo, &photons, radius_squared);
+            }
+
+            match hit.material.sample(&hit, &wo, rng) {
+                Some(sample) if sample.delta => {
+                    throughput *= sample.weight();
+                    r = Ray::new(hit.p, frame.to_world(&sample.wi), r.time);
+                }
+                _ => break,
+            }
+        }
+        radiance
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let time = r.time;
+        follow_specular(scene, r, self.max_depth, rng, |hit, frame, wo, rng| {
+            let mut radiance = integrator::direct_lighting(scene, hit, frame, wo, time, rng);
+            if !hit.material.is_volumetric() {
+                let (photons, radius_squared) =
+                    self.caustics
+                        .nearest(&hit.p, NEAREST_PHOTONS, CAUSTIC_RADIUS);
+                radiance += estimate(hit, frame, wo, &photons, radius_squared);
+            }
+            radiance + self.final_gather(scene, hit, frame, wo, time, rng)
+        })
+    }
+
+    fn begin_pass(&mut self, scene: &Scene, _pass: u32, seed: u64) {
+        if self.global.len() == 0 && self.caustics.len() == 0 {
+            let global =
+                trace_photons(scene, self.photons, self.max_depth, Keep::Indirect, seed, 0);
+            let caustics = trace_photons(
+                scene,
+                self.caustic_photons,
+                self.max_depth,
+                Keep::Caustic,
+                seed,
+                1,
+            );
+            self.global = PhotonMap::new(global);
+            self.caustics = PhotonMap::new(caustics);
+        }
+    }
+}
+
+/// Stochastic progressive photon mapping in the probabilistic form of Knaus and Zwicker,
+/// "Progressive Photon Mapping: A Probabilistic Approach". Each pass over the image traces a
+/// fresh set of photons and estimates light from those within a radius that shrinks from pass
+/// to pass, slowly enough that the noise still averages away, so the image converges to the
+/// right answer rather than staying blurred.
+pub struct ProgressivePhotonMapper {
+    passes: u32,
+    photons_per_pass: usize,
+    max_depth: u32,
+    radius: f32,
+    map: PhotonMap,
+}
+
+/// How quickly the radius shrinks, between 0 and 1: lower trades more noise for less blur.
+const ALPHA: f32 = 2.0 / 3.0;
+
+impl ProgressivePhotonMapper {
+    /// Makes `passes` passes, tracing `photons_per_pass` photons of up to `max_depth` bounces
+    /// for each, and looks for photons within `initial_radius` on the first.
+    pub fn new(
+        passes: u32,
+        photons_per_pass: usize,
+        initial_radius: f32,
+        max_depth: u32,
+    ) -> ProgressivePhotonMapper {
+        ProgressivePhotonMapper {
+            passes,
+            photons_per_pass,
+            max_depth,
+            radius: initial_radius,
+            map: PhotonMap::new(Vec::new()),
+        }
+    }
+}
+
+impl Integrator for ProgressivePhotonMapper {
+    fn radiance(&self, r: Ray, scene: &Scene, rng: &mut dyn RngCore, _splats: &Splats) -> Color {
+        let time = r.time;
+        follow_specular(scene, r, self.max_depth, rng, |hit, frame, wo, rng| {
+            let direct = integrator::direct_lighting(scene, hit, frame, wo, time, rng);
+            if hit.material.is_volumetric() {
+                return direct;
+            }
+            let photons = self.map.within(&hit.p, self.radius);
+            direct + estimate(hit, frame, wo, &photons, self.radius * self.radius)
+        })
+    }
+
+    fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    fn begin_pass(&mut self, scene: &Scene, pass: u32, seed: u64) {
+        if pass > 0 {
+            self.radius *= ((pass as f32 + ALPHA) / (pass as f32 + 1.0)).sqrt();
+        }
+        let photons = trace_photons(
+            scene,
+            self.photons_per_pass,
+            self.max_depth,
+            Keep::Indirect,
+            seed,
+            pass,
+        );
+        self.map = PhotonMap::new(photons);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Photons scattered through a box, some bunched up and some on top of each other, each
+    /// tagged with its index in the power's red channel.
+    fn photons(rng: &mut StdRng) -> Vec<Photon> {
+        let mut photons = Vec::new();
+        for i in 0..2000 {
+            let p = match i % 4 {
+                0 => Point3::new(0.5, 0.5, 0.5),
+                1 => Point3::new(
+                    rng.gen_range(0.0..0.1),
+                    rng.gen_range(0.0..1.0),
+                    rng.gen_range(0.0..0.1),
+                ),
+                _ => Point3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ),
+            };
+            photons.push(Photon {
+                p,
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                wi: Vec3::new(0.0, 1.0, 0.0),
+                power: Color::new(i as f32, 0.0, 0.0),
+            });
+        }
+        photons
+    }
+
+    fn indices(photons: &[&Photon]) -> Vec<usize> {
+        let mut indices: Vec<usize> = photons
+            .iter()
+            .map(|photon| photon.power.x as usize)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn within_finds_the_same_photons_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let photons = photons(&mut rng);
+        let map = PhotonMap::new(photons.clone());
+        assert_eq!(map.len(), photons.len());
+        for _ in 0..200 {
+            let p = Point3::new(
+                rng.gen_range(-1.2..1.2),
+                rng.gen_range(-1.2..1.2),
+                rng.gen_range(-1.2..1.2),
+            );
+            let radius = rng.gen_range(0.0..0.6);
+            let expected: Vec<&Photon> = photons
+                .iter()
+                .filter(|photon| (photon.p - p).square_len() <= radius * radius)
+                .collect();
+            assert_eq!(indices(&map.within(&p, radius)), indices(&expected));
+        }
+    }
+
+    #[test]
+    fn nearest_finds_the_same_photons_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let photons = photons(&mut rng);
+        let map = PhotonMap::new(photons.clone());
+        for _ in 0..200 {
+            let p = Point3::new(
+                rng.gen_range(-1.2..1.2),
+                rng.gen_range(-1.2..1.2),
+                rng.gen_range(-1.2..1.2),
+            );
+            let count = rng.gen_range(1..60);
+            let max_radius = rng.gen_range(0.05..0.8);
+            let (found, radius_squared) = map.nearest(&p, count, max_radius);
+
+            let mut distances: Vec<f32> = photons
+                .iter()
+                .map(|photon| (photon.p - p).square_len())
+                .filter(|&d| d <= max_radius * max_radius)
+                .collect();
+            distances.sort_by(f32::total_cmp);
+            distances.truncate(count);
+            // Ties at the edge can go either way, so compare distances rather than photons
+            let mut found_distances: Vec<f32> = found
+                .iter()
+                .map(|photon| (photon.p - p).square_len())
+                .collect();
+            found_distances.sort_by(f32::total_cmp);
+            assert_eq!(found_distances, distances);
+
+            let expected_radius_squared = if distances.len() == count {
+                distances[count - 1]
+            } else {
+                max_radius * max_radius
+            };
+            assert_eq!(radius_squared, expected_radius_squared);
+        }
+        assert!(PhotonMap::new(Vec::new())
+            .nearest(&Point3::new(0.0, 0.0, 0.0), 10, 1.0)
+            .0
+            .is_empty());
+    }
+}
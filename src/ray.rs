@@ -4,11 +4,28 @@ use crate::vec::{Point3, Vec3};
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    pub time: f32,
+    pub differentials: Option<RayDifferentials>,
+}
+
+/// Offset rays through the neighbouring pixels in x and y, used to estimate how much of a
+/// surface a pixel covers.
+#[derive(Debug, Copy, Clone)]
+pub struct RayDifferentials {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+    pub fn new(origin: Point3, direction: Vec3, time: f32) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            differentials: None,
+        }
     }
 
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
@@ -0,0 +1,293 @@
+use crate::vec::Vec3;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Maps the unit square onto the unit disk with Shirley's concentric mapping, which keeps
+/// neighbouring samples close together and the density uniform.
+pub fn concentric_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let a = 2.0 * u1 - 1.0;
+    let b = 2.0 * u2 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// A direction on the hemisphere around +z with density proportional to its cosine, by
+/// projecting a uniform point on the disk up onto the hemisphere (Malley's method).
+pub fn cosine_hemisphere(u1: f32, u2: f32) -> Vec3 {
+    let (x, y) = concentric_disk(u1, u2);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vec3::new(x, y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) / PI
+}
+
+/// A direction distributed uniformly over the unit sphere.
+pub fn uniform_sphere(u1: f32, u2: f32) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+/// A direction distributed uniformly over the cone around +z whose half-angle has cosine
+/// `cos_max`.
+pub fn uniform_cone(u1: f32, u2: f32, cos_max: f32) -> Vec3 {
+    let z = 1.0 - u1 * (1.0 - cos_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+/// Converts a density over the unit square to one over solid angle, for mappings onto the
+/// sphere that run `v` from pole to pole and `u` once around.
+pub fn equirectangular_pdf(uv_pdf: f32, v: f32) -> f32 {
+    let sin_theta = (PI * v).sin();
+    if sin_theta <= 0.0 {
+        0.0
+    } else {
+        uv_pdf / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Weights a sample from one strategy with density `pdf` against another strategy that could
+/// have found it with density `other_pdf`, by Veach's power heuristic with an exponent of 2.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// A random number generator for one of the independent streams of numbers a render draws
+/// from, such as one pixel's in one pass, so that the same `seed` gives the same image however
+/// the work is shared between threads. Streams are told apart by up to five numbers.
+pub fn seeded_rng(seed: u64, stream: &[u32]) -> StdRng {
+    assert!(
+        stream.len() <= 5,
+        "streams are told apart by at most five numbers"
+    );
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..12].copy_from_slice(&(stream.len() as u32).to_le_bytes());
+    for (bytes, n) in key[12..].chunks_exact_mut(4).zip(stream) {
+        bytes.copy_from_slice(&n.to_le_bytes());
+    }
+    StdRng::from_seed(key)
+}
+
+/// A piecewise-constant distribution over `[0, 1)` with density proportional to `func`.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    /// Samples uniformly if `func` is zero everywhere.
+    pub fn new(func: Vec<f32>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f.abs() / n as f32);
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// The average of `func`, which is its integral over `[0, 1)`.
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Returns the sampled point, its density and the index of the piece it fell in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.piece_pdf(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.func.len();
+        self.piece_pdf(((x * n as f32) as usize).min(n - 1))
+    }
+
+    fn piece_pdf(&self, offset: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[offset].abs() / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise-constant distribution over the unit square, given as rows of values along `u`
+/// stacked along `v`. Samples `v` from the rows' totals and then `u` within the chosen row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Returns the sampled `(u, v)` and its density.
+    pub fn sample(&self, u0: f32, u1: f32) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.rows[row].sample(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let n = self.rows.len();
+        let row = ((v * n as f32) as usize).min(n - 1);
+        self.rows[row].pdf(u) * self.marginal.pdf(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evenly spread numbers in `[0, 1)`, so the histograms below come out nearly exact.
+    fn stratified(n: usize) -> impl Iterator<Item = f32> {
+        (0..n).map(move |i| (i as f32 + 0.5) / n as f32)
+    }
+
+    #[test]
+    fn distribution_1d_samples_in_proportion() {
+        let func = vec![1.0, 0.0, 3.0, 4.0];
+        let distribution = Distribution1D::new(func.clone());
+        assert!((distribution.integral() - 2.0).abs() < 1e-6);
+
+        const SAMPLES: usize = 100_000;
+        let mut counts = [0usize; 4];
+        let mut previous = 0.0;
+        for u in stratified(SAMPLES) {
+            let (x, pdf, offset) = distribution.sample(u);
+            assert!((0.0..1.0).contains(&x) && x >= previous);
+            previous = x;
+            assert_eq!(offset, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[offset] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        for (i, (count, f)) in counts.iter().zip(&func).enumerate() {
+            let expected = f / 8.0;
+            assert!((*count as f32 / SAMPLES as f32 - expected).abs() < 1e-3);
+            // The density over each piece's quarter of the line makes up its share
+            let x = (i as f32 + 0.5) / 4.0;
+            assert!((distribution.pdf(x) * 0.25 - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn distribution_1d_falls_back_to_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 5]);
+        for u in stratified(100) {
+            let (x, pdf, _) = distribution.sample(u);
+            assert!((x - u).abs() < 1e-5);
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn distribution_2d_samples_in_proportion() {
+        let (width, height) = (4, 3);
+        let func = [
+            1.0, 2.0, 0.0, 1.0, //
+            0.0, 0.0, 0.0, 0.0, //
+            5.0, 1.0, 2.0, 4.0,
+        ];
+        let total: f32 = func.iter().sum();
+        let distribution = Distribution2D::new(&func, width, height);
+
+        const STEPS: usize = 400;
+        let mut counts = vec![0usize; width * height];
+        for u1 in stratified(STEPS) {
+            for u0 in stratified(STEPS) {
+                let ((u, v), pdf) = distribution.sample(u0, u1);
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                assert!((pdf - distribution.pdf(u, v)).abs() < 1e-4 * pdf);
+                let cell = (v * height as f32) as usize * width + (u * width as f32) as usize;
+                counts[cell] += 1;
+            }
+        }
+        let cell_area = 1.0 / (width * height) as f32;
+        for (cell, f) in func.iter().enumerate() {
+            let observed = counts[cell] as f32 / (STEPS * STEPS) as f32;
+            assert!((observed - f / total).abs() < 1e-3, "cell {}", cell);
+            // The density is constant over each cell and integrates to its share
+            let (u, v) = (
+                ((cell % width) as f32 + 0.5) / width as f32,
+                ((cell / width) as f32 + 0.5) / height as f32,
+            );
+            assert!((distribution.pdf(u, v) * cell_area - f / total).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn directions_stay_in_their_domains() {
+        for u0 in stratified(50) {
+            for u1 in stratified(50) {
+                let (x, y) = concentric_disk(u0, u1);
+                assert!(x * x + y * y <= 1.0 + 1e-6);
+                let w = cosine_hemisphere(u0, u1);
+                assert!((w.len() - 1.0).abs() < 1e-4 && w.z >= 0.0);
+                let w = uniform_sphere(u0, u1);
+                assert!((w.len() - 1.0).abs() < 1e-4);
+                let w = uniform_cone(u0, u1, 0.8);
+                assert!((w.len() - 1.0).abs() < 1e-4 && w.z >= 0.8 - 1e-6);
+            }
+        }
+    }
+}
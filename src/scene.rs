@@ -0,0 +1,103 @@
+use crate::camera::Camera;
+use crate::hittable::{Hittable, HittableList};
+use crate::light::Light;
+use crate::light_tree::LightTree;
+use crate::medium::Fog;
+use crate::ray::Ray;
+use crate::vec::{Color, Vec3};
+
+/// Everything needed to render an image: the objects, the lights shining on them and the
+/// camera looking at them.
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Vec<Box<dyn Light>>,
+    pub camera: Camera,
+    light_tree: LightTree,
+    fog: Option<Fog>,
+    background: Option<SkyGradient>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, camera: Camera) -> Scene {
+        Scene {
+            world,
+            lights: Vec::new(),
+            camera,
+            light_tree: LightTree::new(&[]),
+            fog: None,
+            background: Some(SkyGradient::default()),
+        }
+    }
+
+    /// Adds the lights, which are told how big the world is so that lights at infinity know
+    /// where to shine in from. A sky or environment map among them replaces the background.
+    pub fn with_lights(mut self, mut lights: Vec<Box<dyn Light>>) -> Scene {
+        if lights.iter().any(|light| light.is_environment()) {
+            self.background = None;
+        }
+        if let Some(bounds) = self.world.bounding_box() {
+            for light in lights.iter_mut() {
+                light.preprocess(&bounds);
+            }
+        }
+        self.light_tree = LightTree::new(&lights);
+        self.lights = lights;
+        self
+    }
+
+    /// Picks which of `lights` to sample at a point.
+    pub fn light_tree(&self) -> &LightTree {
+        &self.light_tree
+    }
+
+    /// Fills the scene with fog that every ray passes through.
+    pub fn with_fog(mut self, fog: Fog) -> Scene {
+        self.fog = Some(fog);
+        self
+    }
+
+    pub fn fog(&self) -> Option<&Fog> {
+        self.fog.as_ref()
+    }
+
+    /// Sets what rays that escape the scene see besides any lights at infinity, or leaves it
+    /// black with `None` so that the lights stand out.
+    pub fn with_background(mut self, background: Option<SkyGradient>) -> Scene {
+        self.background = background;
+        self
+    }
+
+    /// The light from the background along a ray that escapes the scene.
+    pub fn background(&self, r: &Ray) -> Color {
+        match &self.background {
+            Some(gradient) => gradient.eval(&r.direction.unit_vector()),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// A sky that fades from `horizon` straight out to `zenith` overhead, for scenes without a
+/// physical sky or an environment map.
+#[derive(Debug, Copy, Clone)]
+pub struct SkyGradient {
+    horizon: Color,
+    zenith: Color,
+}
+
+impl SkyGradient {
+    pub fn new(horizon: Color, zenith: Color) -> SkyGradient {
+        SkyGradient { horizon, zenith }
+    }
+
+    fn eval(&self, direction: &Vec3) -> Color {
+        let t = 0.5 * (direction.y + 1.0);
+        (1.0 - t) * self.horizon + t * self.zenith
+    }
+}
+
+/// White at the horizon to pale blue overhead.
+impl Default for SkyGradient {
+    fn default() -> SkyGradient {
+        SkyGradient::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
@@ -0,0 +1,1761 @@
+//! The scenes that can be rendered, each picked by name on the command line.
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::hdr::HdrImage;
+use crate::hittable::{
+    AlphaMode, Cutout, Hittable, HittableList, Instance, MovingSphere, Quad, Sphere,
+};
+use crate::light::{
+    DirectionalLight, EnvironmentLight, Light, PointLight, QuadLight, QuadSampling, SphereLight,
+    SpotLight, TriangleLight,
+};
+use crate::material::{
+    BumpMapped, Conductor, Dialectric, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal,
+    NormalMapped, OneSided, OrenNayar, Principled,
+};
+use crate::medium::{ConstantMedium, Fog, GridMedium, VoxelGrid};
+use crate::microfacet::ComplexIor;
+use crate::rand::rngs::StdRng;
+use crate::rand::{Rng, SeedableRng};
+use crate::scene::{Scene, SkyGradient};
+use crate::sdf::{self, SdfObject};
+use crate::sky::PhysicalSky;
+use crate::texture::{
+    Checker, Feature, Filter, Fractal, Gradient, Granite, ImageTexture, Marble, NoiseMode,
+    NoiseTexture, Perlin, Simplex, SolidColor, Texture, UvChecker, UvTransform, Wood, Worley, Wrap,
+};
+use crate::transform::{self, Transform};
+use crate::vec::{Color, Point3, Vec3};
+use std::sync::Arc;
+
+/// Builds a scene for an image of the given aspect ratio, laying out anything random from the
+/// seed, from a file if it takes one.
+type Constructor = fn(f32, u64, Option<String>) -> Scene;
+
+/// Every scene, by the name it's picked with.
+const SCENES: &[(&str, Constructor)] = &[
+    ("random", |aspect_ratio, seed, _| {
+        random_scene(aspect_ratio, seed)
+    }),
+    ("sdf", |aspect_ratio, _, _| sdf_scene(aspect_ratio)),
+    ("motion", |aspect_ratio, seed, _| {
+        motion_scene(aspect_ratio, seed)
+    }),
+    ("textures", |aspect_ratio, _, _| texture_scene(aspect_ratio)),
+    ("image", |aspect_ratio, _, _| image_scene(aspect_ratio)),
+    ("noise", |aspect_ratio, seed, _| {
+        noise_scene(aspect_ratio, seed)
+    }),
+    ("metals", |aspect_ratio, _, _| metal_scene(aspect_ratio)),
+    ("glass", |aspect_ratio, _, _| glass_scene(aspect_ratio)),
+    ("principled", |aspect_ratio, _, _| {
+        principled_scene(aspect_ratio)
+    }),
+    ("diffuse", |aspect_ratio, _, _| diffuse_scene(aspect_ratio)),
+    ("bumps", |aspect_ratio, seed, _| {
+        bump_scene(aspect_ratio, seed)
+    }),
+    ("cutout", |aspect_ratio, _, _| cutout_scene(aspect_ratio)),
+    ("smoke", |aspect_ratio, _, _| smoke_scene(aspect_ratio)),
+    ("volume", volume_scene),
+    ("fog", |aspect_ratio, _, _| fog_scene(aspect_ratio)),
+    ("lights", |aspect_ratio, _, _| light_scene(aspect_ratio)),
+    ("area", |aspect_ratio, _, _| area_light_scene(aspect_ratio)),
+    ("environment", |aspect_ratio, _, file| {
+        environment_scene(aspect_ratio, file)
+    }),
+    ("daylight", |aspect_ratio, _, _| {
+        daylight_scene(aspect_ratio)
+    }),
+    ("stage", |aspect_ratio, _, _| stage_scene(aspect_ratio)),
+    ("caustics", |aspect_ratio, _, _| caustic_scene(aspect_ratio)),
+];
+
+/// The scene called `name`, or `None` if there isn't one.
+pub fn build(name: &str, aspect_ratio: f32, seed: u64, file: Option<String>) -> Option<Scene> {
+    SCENES
+        .iter()
+        .find(|(scene, _)| *scene == name)
+        .map(|(_, constructor)| constructor(aspect_ratio, seed, file))
+}
+
+/// The names of all the scenes, for listing in messages.
+pub fn names() -> String {
+    SCENES
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn random_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Dialectric::new(1.5)),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(-4.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(4.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5))),
+        )),
+    ];
+    let cmp = Vec3::new(4.0, 0.2, 0.0);
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen::<f32>();
+            let center = Point3::new(
+                a as f32 + 0.9f32 * rng.gen::<f32>(),
+                0.2,
+                b as f32 + 0.9f32 * rng.gen::<f32>(),
+            );
+            if (center - cmp).len() > 0.9 {
+                if choose_mat < 0.8 {
+                    list.push(Box::new(Sphere::new(
+                        center,
+                        0.2,
+                        Arc::new(Lambertian::new(Color::new(
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                        ))),
+                    )));
+                } else if choose_mat < 0.95 {
+                    list.push(Box::new(Sphere::new(
+                        center,
+                        0.2,
+                        Arc::new(Metal::new(Color::new(
+                            0.5 * (1.0 + rng.gen::<f32>()),
+                            0.5 * (1.0 + rng.gen::<f32>()),
+                            0.5 * (1.0 + rng.gen::<f32>()),
+                        ))),
+                    )));
+                } else {
+                    list.push(Box::new(Sphere::new(
+                        center,
+                        0.2,
+                        Arc::new(Dialectric::new(1.5)),
+                    )))
+                }
+            }
+        }
+    }
+
+    let camera = Camera::new(
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        aspect_ratio,
+        0.1,
+        10.0,
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn sdf_scene(aspect_ratio: f32) -> Scene {
+    let blob = sdf::SmoothUnion::new(
+        Box::new(sdf::Torus::new(0.8, 0.25)),
+        Box::new(sdf::Translate::new(
+            Box::new(sdf::Sphere::new(0.5)),
+            Vec3::new(0.0, 0.4, 0.0),
+        )),
+        0.3,
+    );
+    let twisted = sdf::Twist::new(
+        Box::new(sdf::Cuboid::rounded(Vec3::new(0.4, 1.0, 0.4), 0.05)),
+        1.2,
+    );
+    let hollow = sdf::Subtraction::new(
+        Box::new(sdf::Cuboid::rounded(Vec3::new(0.6, 0.6, 0.6), 0.1)),
+        Box::new(sdf::Sphere::new(0.75)),
+    );
+    let pillars = sdf::Repeat::new(
+        Box::new(sdf::Capsule::new(
+            Point3::new(0.0, -0.3, 0.0),
+            Point3::new(0.0, 0.3, 0.0),
+            0.15,
+        )),
+        Vec3::new(0.8, 0.0, 0.0),
+        Vec3::new(3.0, 0.0, 0.0),
+    );
+    // A back row: a column sliced off at an angle, a puck with a groove round its rim, a lens
+    // where two spheres overlap, and a plate studded with an endless grid of beads cut to size
+    let column = sdf::Intersection::new(
+        Box::new(sdf::Cylinder::new(0.4, 0.9)),
+        Box::new(sdf::Plane::new(Vec3::new(0.4, 1.0, 0.0), 0.55)),
+    );
+    let puck = sdf::SmoothSubtraction::new(
+        Box::new(sdf::Cylinder::new(0.5, 0.3)),
+        Box::new(sdf::Torus::new(0.5, 0.1)),
+        0.05,
+    );
+    let lens = sdf::SmoothIntersection::new(
+        Box::new(sdf::Translate::new(
+            Box::new(sdf::Sphere::new(0.8)),
+            Vec3::new(0.0, 0.5, 0.0),
+        )),
+        Box::new(sdf::Translate::new(
+            Box::new(sdf::Sphere::new(0.8)),
+            Vec3::new(0.0, -0.5, 0.0),
+        )),
+        0.05,
+    );
+    let plate = sdf::Union::new(
+        Box::new(sdf::Cuboid::new(Vec3::new(0.8, 0.05, 0.5))),
+        Box::new(sdf::Intersection::new(
+            Box::new(sdf::Repeat::infinite(
+                Box::new(sdf::Sphere::new(0.1)),
+                Vec3::new(0.3, 0.0, 0.3),
+            )),
+            Box::new(sdf::Cuboid::new(Vec3::new(0.75, 0.1, 0.45))),
+        )),
+    );
+    let place = |shape: Box<dyn sdf::Sdf>, offset: Vec3, material: Arc<dyn Material>| {
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(shape, offset)),
+            1e-4,
+            256,
+            100.0,
+            material,
+        )) as Box<dyn Hittable>
+    };
+    let back_row = vec![
+        place(
+            Box::new(column),
+            Vec3::new(-3.0, 0.9, -2.5),
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.7))),
+        ),
+        place(
+            Box::new(puck),
+            Vec3::new(-1.0, 0.3, -2.5),
+            Arc::new(Metal::new(Color::new(0.9, 0.6, 0.3))),
+        ),
+        place(
+            Box::new(lens),
+            Vec3::new(1.0, 0.3, -2.5),
+            Arc::new(Dialectric::new(1.5)),
+        ),
+        place(
+            Box::new(plate),
+            Vec3::new(3.0, 0.05, -2.5),
+            Arc::new(Lambertian::new(Color::new(0.3, 0.7, 0.4))),
+        ),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )),
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(blob),
+                Vec3::new(-2.2, 0.25, 0.0),
+            )),
+            1e-4,
+            256,
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))),
+        )),
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(twisted),
+                Vec3::new(0.0, 1.0, 0.0),
+            )),
+            1e-4,
+            256,
+            100.0,
+            Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9))),
+        )),
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(hollow),
+                Vec3::new(2.2, 0.6, 0.0),
+            )),
+            1e-4,
+            256,
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))),
+        )),
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(pillars),
+                Vec3::new(0.0, 0.45, 2.0),
+            )),
+            1e-4,
+            256,
+            100.0,
+            Arc::new(Dialectric::new(1.5)),
+        )),
+    ];
+    list.extend(back_row);
+
+    let lookfrom = Point3::new(0.0, 4.5, 10.0);
+    let lookat = Point3::new(0.0, 0.5, -0.5);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn motion_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    ))];
+    for a in -4..4 {
+        for b in -2..2 {
+            let center = Point3::new(
+                a as f32 + 0.9 * rng.gen::<f32>(),
+                0.2,
+                b as f32 + 0.9 * rng.gen::<f32>(),
+            );
+            list.push(Box::new(MovingSphere::new(
+                center,
+                center + Vec3::new(0.0, 0.5 * rng.gen::<f32>(), 0.0),
+                0.0,
+                1.0,
+                0.2,
+                Arc::new(Lambertian::new(Color::new(
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                    rng.gen::<f32>() * rng.gen::<f32>(),
+                ))),
+            )));
+        }
+    }
+
+    let ring: Arc<dyn Hittable> = Arc::new(SdfObject::new(
+        Box::new(sdf::Torus::new(0.8, 0.2)),
+        1e-4,
+        256,
+        100.0,
+        Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2))),
+    ));
+    let upright = Transform::new(
+        Vec3::new(0.0, 1.2, -3.0),
+        transform::Rotation::new(Vec3::new(1.0, 0.0, 0.0), 90.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    );
+    let spun = Transform {
+        rotation: transform::Rotation::new(Vec3::new(1.0, 0.0, 0.0), 90.0)
+            .then(&transform::Rotation::new(Vec3::new(0.0, 1.0, 0.0), 60.0)),
+        ..upright
+    };
+    list.push(Box::new(Instance::moving(
+        ring.clone(),
+        upright,
+        spun,
+        0.0,
+        1.0,
+    )));
+    list.push(Box::new(Instance::new(
+        ring.clone(),
+        Transform::new(
+            Vec3::new(2.5, 0.3, -2.0),
+            transform::Rotation::identity(),
+            Vec3::new(1.0, 1.5, 0.6),
+        ),
+    )));
+    list.push(Box::new(Instance::moving(
+        ring,
+        Transform::translate(Vec3::new(-2.5, 0.2, -2.0)),
+        Transform::translate(Vec3::new(-1.5, 0.2, -2.0)),
+        0.0,
+        1.0,
+    )));
+
+    let lookfrom = Point3::new(0.0, 3.0, 9.0);
+    let lookat = Point3::new(0.0, 0.5, -1.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        1.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn texture_scene(aspect_ratio: f32) -> Scene {
+    let white = Arc::new(SolidColor::new(Color::new(0.9, 0.9, 0.9)));
+    let green = Arc::new(SolidColor::new(Color::new(0.2, 0.3, 0.1)));
+    let red = Arc::new(SolidColor::new(Color::new(0.7, 0.1, 0.1)));
+    let blue = Arc::new(SolidColor::new(Color::new(0.1, 0.2, 0.7)));
+
+    // A small colour wheel so the image mapping and its orientation are easy to check
+    let (width, height) = (64, 32);
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let hue = x as f32 / width as f32 * 2.0 * std::f32::consts::PI;
+            let brightness = 1.0 - y as f32 / height as f32;
+            pixels.push(Color::new(
+                brightness * (0.5 + 0.5 * hue.cos()),
+                brightness * (0.5 + 0.5 * (hue - 2.1).cos()),
+                brightness * (0.5 + 0.5 * (hue + 2.1).cos()),
+            ));
+        }
+    }
+
+    let list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::textured(Arc::new(Checker::new(
+                white.clone(),
+                green,
+                0.5,
+            )))),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(-2.2, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::textured(Arc::new(UvChecker::new(
+                white.clone(),
+                red.clone(),
+                16.0,
+                8.0,
+            )))),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Metal::textured(
+                Arc::new(Gradient::new(
+                    red,
+                    blue,
+                    Point3::new(0.0, 0.0, 0.0),
+                    Vec3::new(0.0, 2.0, 0.0),
+                )),
+                Arc::new(SolidColor::gray(0.0)),
+            )),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(2.2, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::textured(Arc::new(ImageTexture::new(
+                width,
+                height,
+                pixels,
+                Filter::Bilinear,
+                Wrap::Repeat,
+            )))),
+        )),
+    ];
+
+    let lookfrom = Point3::new(0.0, 2.0, 9.0);
+    let lookat = Point3::new(0.0, 0.8, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn image_scene(aspect_ratio: f32) -> Scene {
+    let photo = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test/bmptestsuite-0.9/valid/24bpp-320x240.bmp"
+    );
+    let texture = |filter, wrap| {
+        Arc::new(ImageTexture::open(photo, filter, wrap).expect("Unable to load texture"))
+    };
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    ))];
+    let spheres: [(Arc<dyn Texture>, f32); 5] = [
+        (texture(Filter::Nearest, Wrap::Repeat), -4.4),
+        (texture(Filter::Bilinear, Wrap::Repeat), -2.2),
+        (
+            Arc::new(UvTransform::new(
+                texture(Filter::Bicubic, Wrap::Mirror),
+                (4.0, 2.0),
+                (0.0, 0.0),
+            )),
+            0.0,
+        ),
+        (
+            Arc::new(UvTransform::new(
+                texture(Filter::Trilinear, Wrap::Repeat),
+                (16.0, 8.0),
+                (0.0, 0.0),
+            )),
+            2.2,
+        ),
+        (
+            // A decal on the front of the sphere, with the clamped border colour elsewhere
+            Arc::new(UvTransform::new(
+                texture(Filter::Ewa, Wrap::Clamp),
+                (4.0, 2.0),
+                (-0.5, -0.5),
+            )),
+            4.4,
+        ),
+    ];
+    for (albedo, x) in spheres {
+        list.push(Box::new(Sphere::new(
+            Point3::new(x, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::textured(albedo)),
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 2.0, 12.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn noise_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    // Procedural textures are seeded so that every render with the same seed matches
+    let mut rng = StdRng::seed_from_u64(seed);
+    let perlin = Arc::new(Perlin::new(&mut rng));
+    let simplex = Arc::new(Simplex::new(&mut rng));
+    let solid = |r, g, b| Arc::new(SolidColor::new(Color::new(r, g, b)));
+
+    let textures: [Arc<dyn Texture>; 5] = [
+        Arc::new(Marble::new(
+            perlin.clone(),
+            4.0,
+            Color::new(0.9, 0.9, 0.85),
+            Color::new(0.2, 0.2, 0.25),
+        )),
+        Arc::new(Wood::new(
+            perlin.clone(),
+            0.15,
+            Color::new(0.75, 0.55, 0.3),
+            Color::new(0.4, 0.22, 0.1),
+        )),
+        Arc::new(Granite::new(
+            &mut rng,
+            6.0,
+            [
+                Color::new(0.1, 0.1, 0.1),
+                Color::new(0.6, 0.55, 0.55),
+                Color::new(0.8, 0.6, 0.55),
+            ],
+        )),
+        Arc::new(NoiseTexture::new(
+            simplex,
+            Fractal::new(6),
+            NoiseMode::Fbm,
+            3.0,
+            solid(0.1, 0.2, 0.5),
+            solid(0.9, 0.9, 1.0),
+        )),
+        Arc::new(NoiseTexture::new(
+            Arc::new(Worley::new(&mut rng, Feature::F1)),
+            Fractal::new(1),
+            NoiseMode::Fbm,
+            4.0,
+            solid(0.9, 0.7, 0.2),
+            solid(0.3, 0.1, 0.05),
+        )),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(NoiseTexture::new(
+            perlin,
+            Fractal::new(5),
+            NoiseMode::Turbulence,
+            2.0,
+            solid(0.3, 0.35, 0.2),
+            solid(0.6, 0.6, 0.5),
+        )))),
+    ))];
+    for (i, albedo) in textures.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f32, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::textured(albedo)),
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 2.0, 12.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn metal_scene(aspect_ratio: f32) -> Scene {
+    let materials: [Arc<dyn Material>; 6] = [
+        Arc::new(Metal::rough(Color::new(0.8, 0.8, 0.8), 0.3)),
+        Arc::new(Conductor::new(ComplexIor::GOLD, 0.0)),
+        Arc::new(Conductor::new(ComplexIor::COPPER, 0.25)),
+        // Brushed along the lines of latitude
+        Arc::new(Conductor::anisotropic(
+            ComplexIor::ALUMINIUM,
+            Arc::new(SolidColor::gray(0.05)),
+            Arc::new(SolidColor::gray(0.5)),
+        )),
+        Arc::new(Conductor::new(ComplexIor::SILVER, 0.1)),
+        Arc::new(Conductor::new(ComplexIor::IRON, 0.4)),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-5.5 + 2.2 * i as f32, 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn glass_scene(aspect_ratio: f32) -> Scene {
+    let materials: [Arc<dyn Material>; 5] = [
+        Arc::new(Dialectric::new(1.5)),
+        Arc::new(Dialectric::rough(1.5, 0.3)),
+        Arc::new(Dialectric::new(1.5).tinted(Color::new(0.2, 0.7, 0.5), 1.0)),
+        Arc::new(Dialectric::rough(1.33, 0.15).tinted(Color::new(0.9, 0.5, 0.1), 1.0)),
+        // A soap bubble
+        Arc::new(Dialectric::thin(1.33, 0.0)),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f32, 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    // A frosted window pane with a ball behind it
+    list.push(Box::new(SdfObject::new(
+        Box::new(sdf::Translate::new(
+            Box::new(sdf::Cuboid::new(Vec3::new(2.0, 1.2, 0.01))),
+            Vec3::new(0.0, 1.2, -3.0),
+        )),
+        1e-4,
+        256,
+        100.0,
+        Arc::new(Dialectric::thin(1.5, 0.2)),
+    )));
+    list.push(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, -5.0),
+        1.0,
+        Arc::new(Lambertian::new(Color::new(0.8, 0.2, 0.2))),
+    )));
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn principled_scene(aspect_ratio: f32) -> Scene {
+    let gold = Color::new(1.0, 0.78, 0.34);
+    let stripes = Arc::new(UvChecker::new(
+        Arc::new(SolidColor::gray(0.0)),
+        Arc::new(SolidColor::gray(1.0)),
+        8.0,
+        1.0,
+    ));
+    let back: [Arc<dyn Material>; 5] = [
+        Arc::new(Principled::new(gold).metallic(1.0).roughness(0.1)),
+        Arc::new(Principled::new(gold).metallic(1.0).roughness(0.4)),
+        Arc::new(Principled::new(gold).metallic(0.5).roughness(0.3)),
+        Arc::new(
+            Principled::new(Color::new(0.9, 0.9, 0.9))
+                .metallic(1.0)
+                .roughness(0.5)
+                .anisotropic(0.9),
+        ),
+        Arc::new(
+            Principled::new(Color::new(0.2, 0.4, 0.8))
+                .metallic_map(stripes.clone())
+                .roughness(0.2),
+        ),
+    ];
+    let front: [Arc<dyn Material>; 5] = [
+        Arc::new(
+            Principled::new(Color::new(0.8, 0.1, 0.1))
+                .roughness(0.6)
+                .clearcoat(1.0, 0.05),
+        ),
+        // Velvet
+        Arc::new(
+            Principled::new(Color::new(0.3, 0.05, 0.3))
+                .roughness(1.0)
+                .specular(0.1)
+                .sheen(1.0, 0.5),
+        ),
+        Arc::new(
+            Principled::new(Color::new(1.0, 1.0, 1.0))
+                .roughness(0.05)
+                .transmission(1.0),
+        ),
+        Arc::new(
+            Principled::new(Color::new(0.7, 0.9, 1.0))
+                .roughness(0.3)
+                .transmission(1.0),
+        ),
+        Arc::new(
+            Principled::new(Color::new(0.9, 0.6, 0.2))
+                .roughness_map(stripes)
+                .specular(1.0),
+        ),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    for (z, row) in [(-2.5, back), (0.0, front)] {
+        for (i, material) in row.into_iter().enumerate() {
+            list.push(Box::new(Sphere::new(
+                Point3::new(-4.4 + 2.2 * i as f32, 1.0, z),
+                1.0,
+                material,
+            )));
+        }
+    }
+
+    let lookfrom = Point3::new(0.0, 9.0, 13.0);
+    let lookat = Point3::new(0.0, 0.8, -1.2);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn diffuse_scene(aspect_ratio: f32) -> Scene {
+    let clay = Color::new(0.75, 0.45, 0.3);
+    let materials: [Arc<dyn Material>; 4] = [
+        Arc::new(Lambertian::new(clay)),
+        Arc::new(OrenNayar::new(clay, 20.0)),
+        Arc::new(OrenNayar::new(clay, 40.0)),
+        Arc::new(OrenNayar::new(clay, 60.0)),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(OrenNayar::new(Color::new(0.5, 0.5, 0.5), 30.0)),
+    ))];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-3.3 + 2.2 * i as f32, 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn bump_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let solid = |c| Arc::new(SolidColor::gray(c));
+
+    let materials: [Arc<dyn Material>; 4] = [
+        // A golf ball
+        Arc::new(NormalMapped::new(
+            Arc::new(Principled::new(Color::new(0.9, 0.9, 0.9)).clearcoat(1.0, 0.1)),
+            Arc::new(dimple_normal_map(24, 12, 16)),
+            1.0,
+        )),
+        // Hammered metal
+        Arc::new(BumpMapped::new(
+            Arc::new(Metal::rough(Color::new(0.9, 0.7, 0.5), 0.15)),
+            Arc::new(NoiseTexture::new(
+                Arc::new(Worley::new(&mut rng, Feature::F1)),
+                Fractal::new(1),
+                NoiseMode::Fbm,
+                6.0,
+                solid(0.0),
+                solid(1.0),
+            )),
+            0.03,
+        )),
+        // Plaster
+        Arc::new(BumpMapped::new(
+            Arc::new(Lambertian::new(Color::new(0.85, 0.8, 0.7))),
+            Arc::new(NoiseTexture::new(
+                Arc::new(Perlin::new(&mut rng)),
+                Fractal::new(5),
+                NoiseMode::Turbulence,
+                6.0,
+                solid(0.0),
+                solid(1.0),
+            )),
+            0.02,
+        )),
+        // Rippled glass
+        Arc::new(BumpMapped::new(
+            Arc::new(Dialectric::new(1.5)),
+            Arc::new(NoiseTexture::new(
+                Arc::new(Simplex::new(&mut rng)),
+                Fractal::new(3),
+                NoiseMode::Fbm,
+                3.0,
+                solid(0.0),
+                solid(1.0),
+            )),
+            0.01,
+        )),
+    ];
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-3.3 + 2.2 * i as f32, 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+/// A tangent-space normal map of a grid of round dimples, each `cell` pixels across.
+fn dimple_normal_map(columns: u32, rows: u32, cell: u32) -> ImageTexture {
+    const RADIUS: f32 = 0.42;
+    const SLOPE: f32 = 2.5;
+
+    let (width, height) = (columns * cell, rows * cell);
+    let pixels = (0..width * height)
+        .map(|i| {
+            // Offset from the centre of the cell in cell widths, with y pointing up the image
+            let dx = ((i % width) % cell) as f32 / cell as f32 + 0.5 / cell as f32 - 0.5;
+            let dy = 0.5 - ((i / width) % cell) as f32 / cell as f32 - 0.5 / cell as f32;
+            let n = if dx * dx + dy * dy < RADIUS * RADIUS {
+                // The walls of the dimple face its centre
+                Vec3::new(-SLOPE * dx, -SLOPE * dy, 1.0).into_unit_vector()
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            0.5 * n + Vec3::new(0.5, 0.5, 0.5)
+        })
+        .collect();
+    ImageTexture::new(width, height, pixels, Filter::Bilinear, Wrap::Repeat)
+}
+
+fn cutout_scene(aspect_ratio: f32) -> Scene {
+    let transparent = || Arc::new(SolidColor::gray(0.0));
+    let opaque = || Arc::new(SolidColor::gray(1.0));
+
+    let list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::textured(Arc::new(Checker::new(
+                Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+                Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+                1.0,
+            )))),
+        )),
+        // A cage, showing its inside through the holes
+        Box::new(Cutout::new(
+            Box::new(Sphere::new(
+                Point3::new(-2.2, 1.0, 0.0),
+                1.0,
+                Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.2))),
+            )),
+            Arc::new(UvChecker::new(opaque(), transparent(), 16.0, 8.0)),
+            AlphaMode::Threshold(0.5),
+        )),
+        // Half transparent
+        Box::new(Cutout::new(
+            Box::new(Sphere::new(
+                Point3::new(0.0, 1.0, 0.0),
+                1.0,
+                Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))),
+            )),
+            Arc::new(SolidColor::gray(0.5)),
+            AlphaMode::Stochastic,
+        )),
+        // Fading out towards the top
+        Box::new(Cutout::new(
+            Box::new(Sphere::new(
+                Point3::new(2.2, 1.0, 0.0),
+                1.0,
+                Arc::new(Metal::rough(Color::new(0.8, 0.8, 0.8), 0.2)),
+            )),
+            Arc::new(Gradient::new(
+                opaque(),
+                transparent(),
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+            )),
+            AlphaMode::Stochastic,
+        )),
+        // A one-sided bowl, whose inside can't be seen
+        Box::new(Cutout::new(
+            Box::new(Sphere::new(
+                Point3::new(0.0, 0.6, 2.5),
+                0.6,
+                Arc::new(OneSided::new(Arc::new(Lambertian::new(Color::new(
+                    0.9, 0.8, 0.2,
+                ))))),
+            )),
+            Arc::new(Gradient::new(
+                opaque(),
+                transparent(),
+                Point3::new(0.0, 0.6, 0.0),
+                Vec3::new(0.0, 0.2, 0.0),
+            )),
+            AlphaMode::Threshold(0.5),
+        )),
+        // A fence
+        Box::new(Cutout::new(
+            Box::new(SdfObject::new(
+                Box::new(sdf::Translate::new(
+                    Box::new(sdf::Cuboid::new(Vec3::new(4.0, 1.2, 0.02))),
+                    Vec3::new(0.0, 1.2, -3.15),
+                )),
+                1e-4,
+                256,
+                100.0,
+                Arc::new(Lambertian::new(Color::new(0.4, 0.3, 0.2))),
+            )),
+            Arc::new(Checker::new(opaque(), transparent(), 0.3)),
+            AlphaMode::Threshold(0.5),
+        )),
+    ];
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn smoke_scene(aspect_ratio: f32) -> Scene {
+    // Boundaries only give volumes their shape, so their material is never seen
+    let boundary = || Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+    let cuboid = |center: Vec3, half_extents: Vec3| {
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(sdf::Cuboid::new(half_extents)),
+                center,
+            )),
+            1e-4,
+            256,
+            100.0,
+            boundary(),
+        ))
+    };
+
+    let list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::textured(Arc::new(Checker::new(
+                Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+                Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+                1.0,
+            )))),
+        )),
+        Box::new(ConstantMedium::new(
+            Box::new(Sphere::new(Point3::new(-3.3, 1.0, 0.0), 1.0, boundary())),
+            1.5,
+            Color::new(0.9, 0.9, 0.9),
+        )),
+        Box::new(ConstantMedium::new(
+            cuboid(Vec3::new(-1.1, 0.8, 0.0), Vec3::new(0.8, 0.8, 0.8)),
+            2.0,
+            Color::new(0.2, 0.2, 0.2),
+        )),
+        // A waxy blob: a dense volume inside a glass skin
+        Box::new(Sphere::new(
+            Point3::new(1.1, 1.0, 0.0),
+            1.0,
+            Arc::new(Dialectric::new(1.5)),
+        )),
+        Box::new(ConstantMedium::new(
+            Box::new(Sphere::new(Point3::new(1.1, 1.0, 0.0), 0.99, boundary())),
+            8.0,
+            Color::new(0.9, 0.6, 0.5),
+        )),
+        Box::new(ConstantMedium::textured(
+            Box::new(Sphere::new(Point3::new(3.3, 1.0, 0.0), 1.0, boundary())),
+            3.0,
+            Arc::new(Gradient::new(
+                Arc::new(SolidColor::new(Color::new(0.9, 0.3, 0.1))),
+                Arc::new(SolidColor::new(Color::new(0.9, 0.9, 0.2))),
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+            )),
+        )),
+        // Mist lying over the ground
+        Box::new(ConstantMedium::with_phase(
+            cuboid(Vec3::new(0.0, 0.2, 0.0), Vec3::new(6.0, 0.2, 4.0)),
+            0.4,
+            Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0))),
+        )),
+    ];
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+/// Renders a density grid file given on the command line, or a procedural cloud without one.
+fn volume_scene(aspect_ratio: f32, seed: u64, grid_path: Option<String>) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let perlin = Perlin::new(&mut rng);
+    let fractal = Fractal::new(5);
+    // A ball of billowing noise that thins out towards its edge
+    let cloud = |offset: Vec3| {
+        VoxelGrid::from_fn(64, 48, 48, |p| {
+            let centered = (*p - Vec3::new(0.5, 0.5, 0.5)) * 2.0;
+            let falloff = 1.0 - centered.len();
+            let billow = fractal.fbm(&perlin, &(*p * 4.0 + offset));
+            (falloff + 0.6 * billow).max(0.0)
+        })
+    };
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    match grid_path {
+        Some(path) => {
+            let grid = VoxelGrid::open(&path).expect("Unable to load density grid");
+            list.push(Box::new(GridMedium::new(
+                grid,
+                Aabb::centered(Point3::new(0.0, 2.0, 0.0), Vec3::new(2.0, 2.0, 2.0)),
+                4.0,
+                Arc::new(HenyeyGreenstein::new(Color::new(0.9, 0.9, 0.9), 0.5)),
+            )));
+        }
+        None => {
+            // Forward scattering like water droplets
+            list.push(Box::new(GridMedium::new(
+                cloud(Vec3::new(0.0, 0.0, 0.0)),
+                Aabb::centered(Point3::new(-1.8, 1.5, 0.0), Vec3::new(2.0, 1.5, 1.5)),
+                6.0,
+                Arc::new(HenyeyGreenstein::new(Color::new(0.95, 0.95, 0.95), 0.6)),
+            )));
+            // Back scattering like dust
+            list.push(Box::new(GridMedium::new(
+                cloud(Vec3::new(7.3, 1.9, 4.1)),
+                Aabb::centered(Point3::new(2.2, 1.2, 0.0), Vec3::new(1.6, 1.2, 1.2)),
+                6.0,
+                Arc::new(HenyeyGreenstein::new(Color::new(0.9, 0.6, 0.4), -0.3)),
+            )));
+        }
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+}
+
+fn fog_scene(aspect_ratio: f32) -> Scene {
+    let wood = Arc::new(Lambertian::new(Color::new(0.4, 0.3, 0.2)));
+    let slats = |center: Vec3, half_extents: Vec3, period: Vec3, limit: Vec3| {
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(sdf::Repeat::new(
+                    Box::new(sdf::Cuboid::new(half_extents)),
+                    period,
+                    limit,
+                )),
+                center,
+            )),
+            1e-4,
+            256,
+            100.0,
+            wood.clone(),
+        ))
+    };
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::textured(Arc::new(Checker::new(
+                Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+                Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+                1.0,
+            )))),
+        )),
+        // A slatted pergola whose gaps let shafts of skylight into the fog beneath
+        slats(
+            Vec3::new(0.0, 4.0, 0.0),
+            Vec3::new(0.15, 0.1, 3.0),
+            Vec3::new(0.6, 0.0, 0.0),
+            Vec3::new(7.0, 0.0, 0.0),
+        ),
+    ];
+    for (x, z) in [(-4.2, -2.8), (4.2, -2.8), (-4.2, 2.8), (4.2, 2.8)] {
+        list.push(slats(
+            Vec3::new(x, 2.0, z),
+            Vec3::new(0.15, 2.0, 0.15),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        ));
+    }
+    let spheres: [Arc<dyn Material>; 3] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.2, 0.1))),
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8))),
+        Arc::new(Dialectric::new(1.5)),
+    ];
+    for (i, material) in spheres.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.2 * (i as f32 - 1.0), 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+    // Receding rows of spheres that fade into the haze
+    for i in 0..5 {
+        list.push(Box::new(Sphere::new(
+            Point3::new(-6.0 + 3.0 * i as f32, 1.0, -12.0 - 6.0 * i as f32),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))),
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    // Sunlight streaming through the slats lights up shafts in the fog
+    let sun = DirectionalLight::new(Vec3::new(0.4, 1.0, -0.6), Color::new(3.0, 2.7, 2.2))
+        .angular_diameter(0.5);
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(vec![Box::new(sun)])
+        .with_fog(
+            Fog::height(0.08, 0.0, 0.4, Color::new(0.9, 0.9, 0.9)).with_phase(Arc::new(
+                HenyeyGreenstein::new(Color::new(0.9, 0.9, 0.9), 0.4),
+            )),
+        )
+        .with_background(Some(SkyGradient::new(
+            Color::new(0.3, 0.3, 0.3),
+            Color::new(0.15, 0.21, 0.3),
+        )))
+}
+
+fn light_scene(aspect_ratio: f32) -> Scene {
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    let materials: [Arc<dyn Material>; 5] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.2))),
+        Arc::new(OrenNayar::new(Color::new(0.8, 0.8, 0.8), 40.0)),
+        Arc::new(Principled::new(Color::new(0.2, 0.4, 0.8)).roughness(0.3)),
+        Arc::new(Metal::rough(Color::new(0.9, 0.7, 0.4), 0.25)),
+        Arc::new(Dialectric::new(1.5)),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.2 * (i as f32 - 2.0), 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lights: Vec<Box<dyn Light>> = vec![
+        // A low, warm sun casting long soft-edged shadows
+        Box::new(
+            DirectionalLight::new(Vec3::new(-1.0, 0.6, 0.4), Color::new(2.5, 2.0, 1.5))
+                .angular_diameter(2.0),
+        ),
+        // A cool fill from the right
+        Box::new(PointLight::new(
+            Point3::new(6.0, 3.0, 4.0),
+            Color::new(15.0, 20.0, 30.0),
+        )),
+        // A spot picking out the middle sphere
+        Box::new(SpotLight::new(
+            Point3::new(0.0, 7.0, 3.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Color::new(150.0, 150.0, 120.0),
+            20.0,
+            12.0,
+        )),
+    ];
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(lights)
+        .with_background(Some(SkyGradient::new(
+            Color::new(0.05, 0.05, 0.05),
+            Color::new(0.025, 0.035, 0.05),
+        )))
+}
+
+/// A product shot under a softbox, with a fill panel, a glowing ball and a triangular rim light.
+fn area_light_scene(aspect_ratio: f32) -> Scene {
+    let softbox = QuadLight::new(
+        Point3::new(-4.0, 6.0, -1.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 3.0),
+        Color::new(8.0, 8.0, 8.0),
+    );
+    let fill = QuadLight::new(
+        Point3::new(-7.0, 0.5, 4.0),
+        Vec3::new(0.0, 0.0, -2.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        Color::new(1.5, 1.8, 2.5),
+    )
+    .sampling(QuadSampling::Area);
+    let ball = SphereLight::new(
+        Point3::new(4.0, 3.5, -2.0),
+        0.5,
+        Color::new(40.0, 30.0, 20.0),
+    );
+    let rim = TriangleLight::new(
+        Point3::new(2.0, 0.5, -4.0),
+        Point3::new(6.0, 0.5, -4.0),
+        Point3::new(4.0, 4.0, -4.0),
+        Color::new(4.0, 4.0, 4.0),
+    );
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6))),
+        )),
+        softbox.object(),
+        fill.object(),
+        ball.object(),
+        rim.object(),
+    ];
+    let materials: [Arc<dyn Material>; 4] = [
+        Arc::new(Principled::new(Color::new(0.8, 0.1, 0.1)).roughness(0.2)),
+        Arc::new(Metal::rough(Color::new(0.9, 0.9, 0.9), 0.1)),
+        Arc::new(Principled::new(Color::new(0.1, 0.3, 0.8)).clearcoat(1.0, 0.05)),
+        Arc::new(Dialectric::new(1.5)),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.2 * (i as f32 - 1.5), 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+    let lights: Vec<Box<dyn Light>> = vec![
+        Box::new(softbox),
+        Box::new(fill),
+        Box::new(ball),
+        Box::new(rim),
+    ];
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(lights)
+        .with_background(None)
+}
+
+/// Lights the scene with an HDR environment map given on the command line, or without one, a
+/// procedural sky with a small, very bright sun.
+fn environment_scene(aspect_ratio: f32, map_path: Option<String>) -> Scene {
+    let image = match map_path {
+        Some(path) => HdrImage::open(&path).expect("Unable to load environment map"),
+        None => {
+            let sun = Vec3::new(-0.5, 0.6, -0.7).unit_vector();
+            let (width, height) = (1024, 512);
+            HdrImage::from_fn(width, height, |x, y| {
+                let theta = std::f32::consts::PI * (y as f32 + 0.5) / height as f32;
+                let phi = 2.0 * std::f32::consts::PI * ((x as f32 + 0.5) / width as f32 - 0.5);
+                let direction = Vec3::new(
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                    -theta.sin() * phi.cos(),
+                );
+                if direction.dot(&sun) > 0.9997 {
+                    Color::new(2500.0, 2300.0, 2000.0)
+                } else if direction.y > 0.0 {
+                    let t = direction.y.sqrt();
+                    (1.0 - t) * Color::new(0.6, 0.6, 0.7) + t * Color::new(0.15, 0.3, 0.6)
+                } else {
+                    Color::new(0.3, 0.25, 0.2)
+                }
+            })
+        }
+    };
+
+    let mut list: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::textured(Arc::new(Checker::new(
+            Arc::new(SolidColor::new(Color::new(0.8, 0.8, 0.8))),
+            Arc::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))),
+            1.0,
+        )))),
+    ))];
+    let materials: [Arc<dyn Material>; 5] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.2))),
+        Arc::new(Principled::new(Color::new(0.2, 0.4, 0.8)).roughness(0.3)),
+        Arc::new(Metal::rough(Color::new(0.9, 0.7, 0.4), 0.2)),
+        Arc::new(Dialectric::new(1.5)),
+        Arc::new(OrenNayar::new(Color::new(0.8, 0.8, 0.8), 40.0)),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.2 * (i as f32 - 2.0), 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(vec![Box::new(EnvironmentLight::new(image, 1.0))])
+}
+
+/// Late afternoon light on a few blocks, from the analytic sky and sun.
+fn daylight_scene(aspect_ratio: f32) -> Scene {
+    let concrete = Arc::new(Lambertian::new(Color::new(0.7, 0.68, 0.65)));
+    let block = |center: Vec3, half_extents: Vec3| {
+        Box::new(SdfObject::new(
+            Box::new(sdf::Translate::new(
+                Box::new(sdf::Cuboid::new(half_extents)),
+                center,
+            )),
+            1e-4,
+            256,
+            100.0,
+            concrete.clone(),
+        ))
+    };
+
+    let list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.35, 0.35, 0.33))),
+        )),
+        block(Vec3::new(-4.5, 2.5, -4.0), Vec3::new(1.5, 2.5, 1.5)),
+        block(Vec3::new(-1.0, 1.5, -6.0), Vec3::new(1.5, 1.5, 1.0)),
+        block(Vec3::new(4.0, 3.5, -7.0), Vec3::new(1.2, 3.5, 1.2)),
+        Box::new(Sphere::new(
+            Point3::new(0.5, 1.0, 0.0),
+            1.0,
+            Arc::new(Dialectric::new(1.5)),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(3.0, 1.0, -1.0),
+            1.0,
+            Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9))),
+        )),
+        Box::new(Sphere::new(
+            Point3::new(-2.0, 0.7, 1.0),
+            0.7,
+            Arc::new(Lambertian::new(Color::new(0.7, 0.2, 0.1))),
+        )),
+    ];
+
+    let sky = PhysicalSky::new(Vec3::new(-0.8, 0.45, -0.6), 3.0, Color::new(0.3, 0.3, 0.3))
+        .intensity(0.04);
+    let lights: Vec<Box<dyn Light>> = vec![Box::new(sky.sun()), Box::new(sky)];
+
+    let lookfrom = Point3::new(0.0, 2.0, 14.0);
+    let lookat = Point3::new(0.0, 2.5, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera).with_lights(lights)
+}
+
+/// A dark stage in front of a wall of hundreds of coloured LEDs, with a row of footlights and
+/// two washes overhead, to show lights being picked by how much they add to each point.
+fn stage_scene(aspect_ratio: f32) -> Scene {
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Principled::new(Color::new(0.05, 0.05, 0.05)).roughness(0.3)),
+        )),
+        Box::new(Quad::new(
+            Point3::new(-12.0, 0.0, -6.0),
+            Vec3::new(24.0, 0.0, 0.0),
+            Vec3::new(0.0, 10.0, 0.0),
+            Arc::new(Lambertian::new(Color::new(0.1, 0.1, 0.1))),
+        )),
+    ];
+    let mut lights: Vec<Box<dyn Light>> = Vec::new();
+
+    // Hues sweep across the wall and fade from top to bottom
+    let hue = |h: f32| {
+        let channel = |offset: f32| {
+            let x = ((h + offset).rem_euclid(1.0) * 6.0 - 3.0).abs() - 1.0;
+            x.clamp(0.0, 1.0)
+        };
+        Color::new(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+    };
+    let (columns, rows) = (32, 10);
+    for i in 0..columns {
+        for j in 0..rows {
+            let u = i as f32 / (columns - 1) as f32;
+            let v = j as f32 / (rows - 1) as f32;
+            let led = SphereLight::new(
+                Point3::new(-9.0 + 18.0 * u, 1.5 + 5.0 * v, -5.8),
+                0.08,
+                (20.0 + 40.0 * v) * hue(0.8 * u + 0.2 * v),
+            );
+            list.push(led.object());
+            lights.push(Box::new(led));
+        }
+    }
+
+    for i in 0..12 {
+        let x = -5.5 + i as f32;
+        let footlight = TriangleLight::new(
+            Point3::new(x + 0.3, 0.0, 4.0),
+            Point3::new(x - 0.3, 0.0, 4.0),
+            Point3::new(x, 0.3, 4.1),
+            Color::new(6.0, 5.0, 3.5),
+        );
+        list.push(footlight.object());
+        lights.push(Box::new(footlight));
+    }
+    for x in [-4.0, 2.0] {
+        let wash = QuadLight::new(
+            Point3::new(x, 8.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Color::new(3.0, 3.0, 4.0),
+        );
+        list.push(wash.object());
+        lights.push(Box::new(wash));
+    }
+
+    let materials: [Arc<dyn Material>; 3] = [
+        Arc::new(Metal::rough(Color::new(0.9, 0.9, 0.9), 0.05)),
+        Arc::new(Principled::new(Color::new(0.8, 0.8, 0.8)).roughness(0.4)),
+        Arc::new(Dialectric::new(1.5)),
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.5 * (i as f32 - 1.0), 1.0, 0.0),
+            1.0,
+            material,
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 2.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(lights)
+        .with_background(None)
+}
+
+fn caustic_scene(aspect_ratio: f32) -> Scene {
+    // A small, bright bulb focuses sharp caustics through the glass onto the floor, which
+    // paths from the camera alone almost never find
+    let bulb = SphereLight::new(
+        Point3::new(-2.0, 7.0, -3.0),
+        0.25,
+        Color::new(800.0, 760.0, 680.0),
+    );
+    let mut list: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7))),
+        )),
+        bulb.object(),
+    ];
+    for (i, ior) in [1.33, 1.5, 2.4].into_iter().enumerate() {
+        list.push(Box::new(Sphere::new(
+            Point3::new(2.5 * (i as f32 - 1.0), 1.0, 0.0),
+            1.0,
+            Arc::new(Dialectric::new(ior)),
+        )));
+    }
+
+    let lookfrom = Point3::new(0.0, 3.0, 14.0);
+    let lookat = Point3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        aspect_ratio,
+        0.0,
+        (lookfrom - lookat).len(),
+        0.0,
+        0.0,
+    );
+
+    Scene::new(HittableList::new(list), camera)
+        .with_lights(vec![Box::new(bulb)])
+        .with_background(None)
+}
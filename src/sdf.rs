@@ -0,0 +1,702 @@
+use crate::aabb::Aabb;
+use crate::hittable::{sphere_uv, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec::{Point3, Vec3};
+use rand::RngCore;
+use std::sync::Arc;
+
+/// A signed distance field: negative inside the surface, positive outside.
+///
+/// `distance` must never overestimate the true distance to the surface, otherwise sphere tracing
+/// can step through it.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: &Point3) -> f32;
+
+    /// A box enclosing the zero level set, or `None` if the field is unbounded.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// Renders an `Sdf` by sphere tracing along the ray.
+pub struct SdfObject {
+    sdf: Box<dyn Sdf>,
+    epsilon: f32,
+    max_steps: u32,
+    max_distance: f32,
+    material: Arc<dyn Material>,
+}
+
+impl SdfObject {
+    pub fn new(
+        sdf: Box<dyn Sdf>,
+        epsilon: f32,
+        max_steps: u32,
+        max_distance: f32,
+        material: Arc<dyn Material>,
+    ) -> SdfObject {
+        SdfObject {
+            sdf,
+            epsilon,
+            max_steps,
+            max_distance,
+            material,
+        }
+    }
+
+    /// Estimates the surface normal from the gradient of the field using the tetrahedron
+    /// technique, which needs four evaluations instead of six for central differences.
+    fn normal(&self, p: &Point3) -> Vec3 {
+        let h = self.epsilon;
+        let k0 = Vec3::new(1.0, -1.0, -1.0);
+        let k1 = Vec3::new(-1.0, -1.0, 1.0);
+        let k2 = Vec3::new(-1.0, 1.0, -1.0);
+        let k3 = Vec3::new(1.0, 1.0, 1.0);
+        let n = k0 * self.sdf.distance(&(*p + h * k0))
+            + k1 * self.sdf.distance(&(*p + h * k1))
+            + k2 * self.sdf.distance(&(*p + h * k2))
+            + k3 * self.sdf.distance(&(*p + h * k3));
+        n.into_unit_vector()
+    }
+}
+
+impl Hittable for SdfObject {
+    // Shapes here draw no random numbers, so `rng` is only handed on to the retry past a culled
+    // face
+    #[allow(clippy::only_used_in_recursion)]
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let dir_len = r.direction.len();
+        let t_max = t_max.min(self.max_distance / dir_len);
+        let (t_start, t_end) = match self.sdf.bounds() {
+            Some(bounds) => bounds.hit(r, t_min, t_max)?,
+            None => (t_min, t_max),
+        };
+
+        // March on whichever side of the surface the ray starts so that rays travelling through
+        // the inside of a shape (e.g. refracted rays) find the exit point
+        let sign = if self.sdf.distance(&r.point_at_parameter(t_start)) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let mut t = t_start;
+        for step in 0..self.max_steps {
+            let p = r.point_at_parameter(t);
+            let d = sign * self.sdf.distance(&p);
+            // A ray leaving a surface starts within epsilon of it, so always take the first step
+            if d < self.epsilon && step > 0 {
+                // There's no natural parameterisation of an arbitrary field, so map the normal
+                // onto the sphere like a cube map
+                let normal = self.normal(&p);
+                let rec =
+                    HitRecord::new(r, t, p, normal, sphere_uv(&normal), self.material.clone());
+                if rec.is_culled() {
+                    // Start again from just past the surface
+                    return self.hit(r, t + 2.0 * self.epsilon / dir_len, t_max, rng);
+                }
+                return Some(rec);
+            }
+            t += d.max(self.epsilon) / dir_len;
+            if t > t_end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.sdf.bounds()
+    }
+}
+
+pub struct Sphere {
+    radius: f32,
+}
+
+impl Sphere {
+    pub fn new(radius: f32) -> Sphere {
+        Sphere { radius }
+    }
+}
+
+impl Sdf for Sphere {
+    fn distance(&self, p: &Point3) -> f32 {
+        p.len() - self.radius
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::centered(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(r, r, r),
+        ))
+    }
+}
+
+pub struct Cuboid {
+    half_extents: Vec3,
+    rounding: f32,
+}
+
+impl Cuboid {
+    pub fn new(half_extents: Vec3) -> Cuboid {
+        Cuboid::rounded(half_extents, 0.0)
+    }
+
+    /// A box whose edges are rounded off with the given radius. The overall size still matches
+    /// `half_extents`.
+    pub fn rounded(half_extents: Vec3, rounding: f32) -> Cuboid {
+        Cuboid {
+            half_extents,
+            rounding,
+        }
+    }
+}
+
+impl Sdf for Cuboid {
+    fn distance(&self, p: &Point3) -> f32 {
+        let r = self.rounding;
+        let q = p.abs() - self.half_extents + Vec3::new(r, r, r);
+        q.max(&Vec3::new(0.0, 0.0, 0.0)).len() + q.max_component().min(0.0) - r
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::centered(
+            Point3::new(0.0, 0.0, 0.0),
+            self.half_extents,
+        ))
+    }
+}
+
+/// A torus lying in the xz plane.
+pub struct Torus {
+    major_radius: f32,
+    minor_radius: f32,
+}
+
+impl Torus {
+    pub fn new(major_radius: f32, minor_radius: f32) -> Torus {
+        Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: &Point3) -> f32 {
+        let qx = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        (qx * qx + p.y * p.y).sqrt() - self.minor_radius
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(Aabb::centered(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(outer, self.minor_radius, outer),
+        ))
+    }
+}
+
+pub struct Capsule {
+    a: Point3,
+    b: Point3,
+    radius: f32,
+}
+
+impl Capsule {
+    pub fn new(a: Point3, b: Point3, radius: f32) -> Capsule {
+        Capsule { a, b, radius }
+    }
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: &Point3) -> f32 {
+        let pa = *p - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+        (pa - h * ba).len() - self.radius
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.a.min(&self.b), self.a.max(&self.b)).expand(self.radius))
+    }
+}
+
+/// A capped cylinder around the y axis.
+pub struct Cylinder {
+    radius: f32,
+    half_height: f32,
+}
+
+impl Cylinder {
+    pub fn new(radius: f32, half_height: f32) -> Cylinder {
+        Cylinder {
+            radius,
+            half_height,
+        }
+    }
+}
+
+impl Sdf for Cylinder {
+    fn distance(&self, p: &Point3) -> f32 {
+        let dx = (p.x * p.x + p.z * p.z).sqrt() - self.radius;
+        let dy = p.y.abs() - self.half_height;
+        let outside = (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt();
+        dx.max(dy).min(0.0) + outside
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::centered(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(self.radius, self.half_height, self.radius),
+        ))
+    }
+}
+
+/// The half-space below the plane `dot(p, normal) = offset`.
+pub struct Plane {
+    normal: Vec3,
+    offset: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vec3, offset: f32) -> Plane {
+        Plane {
+            normal: normal.unit_vector(),
+            offset,
+        }
+    }
+}
+
+impl Sdf for Plane {
+    fn distance(&self, p: &Point3) -> f32 {
+        p.dot(&self.normal) - self.offset
+    }
+}
+
+pub struct Translate {
+    inner: Box<dyn Sdf>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new(inner: Box<dyn Sdf>, offset: Vec3) -> Translate {
+        Translate { inner, offset }
+    }
+}
+
+impl Sdf for Translate {
+    fn distance(&self, p: &Point3) -> f32 {
+        self.inner.distance(&(*p - self.offset))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.inner.bounds().map(|b| b.translate(&self.offset))
+    }
+}
+
+pub struct Union {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl Union {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Union {
+        Union { a, b }
+    }
+}
+
+impl Sdf for Union {
+    fn distance(&self, p: &Point3) -> f32 {
+        self.a.distance(p).min(self.b.distance(p))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.a.bounds()?.union(&self.b.bounds()?))
+    }
+}
+
+pub struct Intersection {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl Intersection {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Intersection {
+        Intersection { a, b }
+    }
+}
+
+impl Sdf for Intersection {
+    fn distance(&self, p: &Point3) -> f32 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        intersect_bounds(self.a.bounds(), self.b.bounds())
+    }
+}
+
+/// Carves `b` out of `a`.
+pub struct Subtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl Subtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Subtraction {
+        Subtraction { a, b }
+    }
+}
+
+impl Sdf for Subtraction {
+    fn distance(&self, p: &Point3) -> f32 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.a.bounds()
+    }
+}
+
+/// Union of two fields blended over a region of width `k` using a polynomial smooth minimum.
+pub struct SmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f32,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f32) -> SmoothUnion {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Point3) -> f32 {
+        smooth_min(self.a.distance(p), self.b.distance(p), self.k)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.a.bounds()?.union(&self.b.bounds()?).expand(self.k))
+    }
+}
+
+pub struct SmoothIntersection {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f32,
+}
+
+impl SmoothIntersection {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f32) -> SmoothIntersection {
+        SmoothIntersection { a, b, k }
+    }
+}
+
+impl Sdf for SmoothIntersection {
+    fn distance(&self, p: &Point3) -> f32 {
+        -smooth_min(-self.a.distance(p), -self.b.distance(p), self.k)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        intersect_bounds(self.a.bounds(), self.b.bounds())
+    }
+}
+
+pub struct SmoothSubtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f32,
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f32) -> SmoothSubtraction {
+        SmoothSubtraction { a, b, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: &Point3) -> f32 {
+        -smooth_min(-self.a.distance(p), self.b.distance(p), self.k)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.a.bounds()
+    }
+}
+
+/// Twists the field around the y axis by `rate` radians per unit of height.
+pub struct Twist {
+    inner: Box<dyn Sdf>,
+    rate: f32,
+}
+
+impl Twist {
+    pub fn new(inner: Box<dyn Sdf>, rate: f32) -> Twist {
+        Twist { inner, rate }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: &Point3) -> f32 {
+        let (sin, cos) = (self.rate * p.y).sin_cos();
+        let q = Point3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+        // Twisting stretches space by up to sqrt(1 + (rate * r)^2) at radius r, so scale the
+        // distance down to keep it a lower bound
+        let r = (p.x * p.x + p.z * p.z).sqrt();
+        self.inner.distance(&q) / (1.0 + (self.rate * r).abs())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.inner.bounds()?;
+        let r = bounds
+            .corners()
+            .iter()
+            .map(|c| (c.x * c.x + c.z * c.z).sqrt())
+            .fold(0.0, f32::max);
+        Some(Aabb::new(
+            Point3::new(-r, bounds.min.y, -r),
+            Point3::new(r, bounds.max.y, r),
+        ))
+    }
+}
+
+/// Repeats the field on a grid with the given cell size. Axes with a period of zero are not
+/// repeated.
+pub struct Repeat {
+    inner: Box<dyn Sdf>,
+    period: Vec3,
+    limit: Vec3,
+}
+
+impl Repeat {
+    /// Repeats the field `limit` times in each direction along each axis, so an axis with a limit
+    /// of 2 holds 5 copies.
+    pub fn new(inner: Box<dyn Sdf>, period: Vec3, limit: Vec3) -> Repeat {
+        Repeat {
+            inner,
+            period,
+            limit,
+        }
+    }
+
+    pub fn infinite(inner: Box<dyn Sdf>, period: Vec3) -> Repeat {
+        Repeat::new(
+            inner,
+            period,
+            Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        )
+    }
+
+    fn repeat_axis(p: f32, period: f32, limit: f32) -> f32 {
+        if period == 0.0 {
+            p
+        } else {
+            p - period * (p / period).round().clamp(-limit, limit)
+        }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: &Point3) -> f32 {
+        let q = Point3::new(
+            Repeat::repeat_axis(p.x, self.period.x, self.limit.x),
+            Repeat::repeat_axis(p.y, self.period.y, self.limit.y),
+            Repeat::repeat_axis(p.z, self.period.z, self.limit.z),
+        );
+        self.inner.distance(&q)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.inner.bounds()?;
+        let mut spread = Vec3::new(0.0, 0.0, 0.0);
+        for (s, (period, limit)) in [
+            (&mut spread.x, (self.period.x, self.limit.x)),
+            (&mut spread.y, (self.period.y, self.limit.y)),
+            (&mut spread.z, (self.period.z, self.limit.z)),
+        ] {
+            if period != 0.0 {
+                if !limit.is_finite() {
+                    return None;
+                }
+                *s = period.abs() * limit;
+            }
+        }
+        Some(Aabb::new(bounds.min - spread, bounds.max + spread))
+    }
+}
+
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+fn intersect_bounds(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b)),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let sphere = Sphere::new(1.0);
+        assert_close(sphere.distance(&Point3::new(3.0, 0.0, 0.0)), 2.0);
+        assert_close(sphere.distance(&Point3::new(0.0, 0.0, 0.0)), -1.0);
+
+        let cuboid = Cuboid::new(Vec3::new(1.0, 2.0, 3.0));
+        assert_close(cuboid.distance(&Point3::new(2.0, 0.0, 0.0)), 1.0);
+        assert_close(cuboid.distance(&Point3::new(0.0, 0.0, 0.0)), -1.0);
+        // Off a corner the distance is to the corner itself
+        assert_close(cuboid.distance(&Point3::new(4.0, 6.0, 3.0)), 5.0);
+
+        let torus = Torus::new(2.0, 0.5);
+        assert_close(torus.distance(&Point3::new(2.0, 0.0, 0.0)), -0.5);
+        assert_close(torus.distance(&Point3::new(0.0, 0.0, 0.0)), 1.5);
+
+        let capsule = Capsule::new(Point3::new(0.0, -1.0, 0.0), Point3::new(0.0, 1.0, 0.0), 0.5);
+        assert_close(capsule.distance(&Point3::new(1.0, 0.0, 0.0)), 0.5);
+        assert_close(capsule.distance(&Point3::new(0.0, 3.0, 0.0)), 1.5);
+
+        let cylinder = Cylinder::new(1.0, 2.0);
+        assert_close(cylinder.distance(&Point3::new(3.0, 0.0, 0.0)), 2.0);
+        assert_close(cylinder.distance(&Point3::new(0.0, 5.0, 0.0)), 3.0);
+        assert_close(cylinder.distance(&Point3::new(0.0, 0.0, 0.0)), -1.0);
+
+        let plane = Plane::new(Vec3::new(0.0, 2.0, 0.0), 1.0);
+        assert_close(plane.distance(&Point3::new(5.0, 3.0, -2.0)), 2.0);
+    }
+
+    #[test]
+    fn combinators() {
+        let a = || Box::new(Sphere::new(1.0));
+        let b = || {
+            Box::new(Translate::new(
+                Box::new(Sphere::new(1.0)),
+                Vec3::new(1.5, 0.0, 0.0),
+            ))
+        };
+        let p = Point3::new(-0.5, 0.0, 0.0);
+        assert_close(Union::new(a(), b()).distance(&p), -0.5);
+        assert_close(Intersection::new(a(), b()).distance(&p), 1.0);
+        assert_close(Subtraction::new(a(), b()).distance(&p), -0.5);
+
+        // Smooth blends never sit above their sharp counterparts, and match them far from the
+        // seam
+        let q = Point3::new(0.75, 0.0, 0.0);
+        assert!(SmoothUnion::new(a(), b(), 0.5).distance(&q) <= Union::new(a(), b()).distance(&q));
+        assert!(
+            SmoothIntersection::new(a(), b(), 0.5).distance(&q)
+                >= Intersection::new(a(), b()).distance(&q)
+        );
+        let far = Point3::new(-3.0, 0.0, 0.0);
+        assert_close(
+            SmoothUnion::new(a(), b(), 0.5).distance(&far),
+            Union::new(a(), b()).distance(&far),
+        );
+        assert_close(
+            SmoothSubtraction::new(a(), b(), 0.5).distance(&far),
+            Subtraction::new(a(), b()).distance(&far),
+        );
+    }
+
+    #[test]
+    fn repetition() {
+        let grid = Repeat::infinite(Box::new(Sphere::new(0.25)), Vec3::new(2.0, 0.0, 0.0));
+        assert_close(grid.distance(&Point3::new(100.0, 0.0, 0.0)), -0.25);
+        assert_close(grid.distance(&Point3::new(101.0, 0.0, 0.0)), 0.75);
+        assert!(grid.bounds().is_none());
+
+        let row = Repeat::new(
+            Box::new(Sphere::new(0.25)),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_close(row.distance(&Point3::new(2.0, 0.0, 0.0)), -0.25);
+        assert_close(row.distance(&Point3::new(6.0, 0.0, 0.0)), 3.75);
+        let bounds = row.bounds().unwrap();
+        assert_close(bounds.min.x, -2.25);
+        assert_close(bounds.max.x, 2.25);
+    }
+
+    #[test]
+    fn twist_never_overestimates() {
+        let cuboid = || Box::new(Cuboid::new(Vec3::new(0.5, 1.0, 0.5)));
+        let twist = Twist::new(cuboid(), 1.0);
+        // Sample points along a line and check the step never jumps past the surface
+        for i in 0..100 {
+            let p = Point3::new(-2.0 + 0.04 * i as f32, 0.7, 0.3);
+            let d = twist.distance(&p);
+            let q = Point3::new(p.x + d.abs(), p.y, p.z);
+            if d > 0.0 {
+                assert!(twist.distance(&q) >= -1e-4, "stepped through at {:?}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_enclose_the_surface() {
+        let shapes: Vec<Box<dyn Sdf>> = vec![
+            Box::new(Sphere::new(1.0)),
+            Box::new(Cuboid::rounded(Vec3::new(1.0, 0.5, 0.25), 0.1)),
+            Box::new(Torus::new(1.0, 0.3)),
+            Box::new(Cylinder::new(0.5, 1.0)),
+            Box::new(Twist::new(
+                Box::new(Cuboid::new(Vec3::new(0.4, 1.0, 0.4))),
+                1.2,
+            )),
+        ];
+        for shape in shapes {
+            let bounds = shape.bounds().unwrap();
+            for corner in bounds.expand(1e-3).corners() {
+                assert!(shape.distance(&corner) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_tracing_finds_the_surface() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let object = SdfObject::new(
+            Box::new(Sphere::new(1.0)),
+            1e-5,
+            256,
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        let hit = object.hit(&r, 0.001, f32::MAX, &mut rng).unwrap();
+        // The direction isn't unit length, so t is in units of it
+        assert!((hit.t - 2.0).abs() < 1e-3);
+        assert!((hit.p.z - 1.0).abs() < 1e-3);
+        assert!(hit.normal.dot(&Vec3::new(0.0, 0.0, 1.0)) > 0.999);
+
+        // Starting inside, the ray finds its way out
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let hit = object.hit(&inside, 0.001, f32::MAX, &mut rng).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-3);
+        assert!(!hit.front_face);
+
+        let miss = Ray::new(Point3::new(0.0, 2.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(object.hit(&miss, 0.001, f32::MAX, &mut rng).is_none());
+    }
+}
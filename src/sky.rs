@@ -0,0 +1,372 @@
+//! An analytic daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for
+//! Daylight".
+
+use crate::aabb::Aabb;
+use crate::light::{self, DirectionalLight, EmissionSample, Light, LightSample};
+use crate::sampling::{self, Distribution2D};
+use crate::vec::{Color, Point3, Vec3};
+
+use rand::{Rng, RngCore};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// The sun's angular diameter in degrees.
+const SUN_ANGULAR_DIAMETER: f32 = 0.53;
+
+/// Illuminance from the sun above the atmosphere in kilolux, before `intensity` scales it.
+const SUN_ILLUMINANCE: f32 = 128.0;
+
+/// Resolution of the table of the sky's brightness over the sphere that directions towards it
+/// are sampled from.
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+/// Coefficients of the Perez distribution of sky luminance over the dome.
+#[derive(Debug, Copy, Clone)]
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    /// The relative value at angle `theta` from the zenith and `gamma` from the sun.
+    fn eval(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta.max(1e-3)).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Clear sky radiance for a given sun position and haziness, surrounding the scene at infinity.
+/// Below the horizon is a uniform ground lit by the sky and sun. The sun itself isn't included;
+/// add the light from `sun` alongside, which also shows its disk. Directions are sampled from
+/// a table of the sky's brightness, so the glow around the sun is found by shadow rays.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f32,
+    ground_albedo: Color,
+    intensity: f32,
+    /// Perez coefficients and zenith values for luminance and the two chromaticities.
+    perez: [Perez; 3],
+    zenith: [f32; 3],
+    ground: Color,
+    distribution: Distribution2D,
+    scene_sphere: Option<(Point3, f32)>,
+}
+
+impl PhysicalSky {
+    /// `sun_direction` points towards the sun, which should be above the horizon. `turbidity`
+    /// measures haze, from about 2 for a very clear sky to 10 for a hazy one.
+    pub fn new(sun_direction: Vec3, turbidity: f32, ground_albedo: Color) -> PhysicalSky {
+        let sun_direction = sun_direction.unit_vector();
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+        let perez = [
+            Perez {
+                a: 0.1787 * t - 1.4630,
+                b: -0.3554 * t + 0.4275,
+                c: -0.0227 * t + 5.3251,
+                d: 0.1206 * t - 2.5771,
+                e: -0.0670 * t + 0.3703,
+            },
+            Perez {
+                a: -0.0193 * t - 0.2592,
+                b: -0.0665 * t + 0.0008,
+                c: -0.0004 * t + 0.2125,
+                d: -0.0641 * t - 0.8989,
+                e: -0.0033 * t + 0.0452,
+            },
+            Perez {
+                a: -0.0167 * t - 0.2608,
+                b: -0.0950 * t + 0.0092,
+                c: -0.0079 * t + 0.2102,
+                d: -0.0441 * t - 1.6537,
+                e: -0.0109 * t + 0.0529,
+            },
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic = |c: [f32; 4]| ((c[0] * theta_s + c[1]) * theta_s + c[2]) * theta_s + c[3];
+        let x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        // The model gives each value relative to its own value at the zenith
+        let mut zenith = [luminance, x, y];
+        for (z, p) in zenith.iter_mut().zip(perez.iter()) {
+            *z /= p.eval(1.0, theta_s);
+        }
+
+        let mut sky = PhysicalSky {
+            sun_direction,
+            turbidity: t,
+            ground_albedo,
+            intensity: 0.1,
+            perez,
+            zenith,
+            ground: Color::new(0.0, 0.0, 0.0),
+            distribution: Distribution2D::new(&[1.0], 1, 1),
+            scene_sphere: None,
+        };
+        sky.update();
+        sky
+    }
+
+    /// Scales the sky and sun from photometric units, so that a clear midday sky comes out
+    /// around 1 with the default of 0.1.
+    pub fn intensity(mut self, intensity: f32) -> PhysicalSky {
+        self.intensity = intensity;
+        self.update();
+        self
+    }
+
+    /// The sun to go with the sky, dimmed and reddened by the air it shines through.
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(self.sun_direction, self.sun_irradiance())
+            .angular_diameter(SUN_ANGULAR_DIAMETER)
+    }
+
+    fn sun_irradiance(&self) -> Color {
+        let cos_theta = self.sun_direction.y;
+        if cos_theta <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        // Kasten's relative optical air mass, and Rayleigh and aerosol optical depths at
+        // wavelengths for red, green and blue
+        let theta_degrees = cos_theta.acos().to_degrees();
+        let air_mass = 1.0 / (cos_theta + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |wavelength: f32| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        };
+        self.intensity
+            * SUN_ILLUMINANCE
+            * Color::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            )
+    }
+
+    /// Radiance of the sky towards a direction above the horizon.
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y;
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * self.perez[i].eval(cos_theta, gamma));
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        // xyY to XYZ to linear sRGB
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        let rgb = Color::new(
+            3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+        );
+        self.intensity * rgb.max(&Color::new(0.0, 0.0, 0.0))
+    }
+
+    /// Recomputes everything that follows from the sky's radiance.
+    fn update(&mut self) {
+        self.update_ground();
+        self.distribution = self.tabulate();
+    }
+
+    /// Lights the ground by integrating the sky over the upper hemisphere, plus the sun.
+    fn update_ground(&mut self) {
+        const STEPS_THETA: usize = 16;
+        const STEPS_PHI: usize = 64;
+        let mut irradiance = Color::new(0.0, 0.0, 0.0);
+        for i in 0..STEPS_THETA {
+            let theta = FRAC_PI_2 * (i as f32 + 0.5) / STEPS_THETA as f32;
+            for j in 0..STEPS_PHI {
+                let phi = 2.0 * PI * (j as f32 + 0.5) / STEPS_PHI as f32;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance += self.sky_radiance(&direction) * (theta.cos() * theta.sin());
+            }
+        }
+        irradiance *= (FRAC_PI_2 / STEPS_THETA as f32) * (2.0 * PI / STEPS_PHI as f32);
+        irradiance += self.sun_irradiance() * self.sun_direction.y.max(0.0);
+        self.ground = self.ground_albedo * irradiance / PI;
+    }
+
+    /// The brightness of the sky and ground over the sphere, weighted by the solid angle of each
+    /// cell, to pick directions in proportion to it.
+    fn tabulate(&self) -> Distribution2D {
+        let mut func = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        for y in 0..TABLE_HEIGHT {
+            let v = (y as f32 + 0.5) / TABLE_HEIGHT as f32;
+            let sin_theta = (PI * v).sin();
+            for x in 0..TABLE_WIDTH {
+                let direction =
+                    PhysicalSky::uv_to_direction((x as f32 + 0.5) / TABLE_WIDTH as f32, v);
+                func.push(self.infinite_radiance(&direction).luminance().max(0.0) * sin_theta);
+            }
+        }
+        Distribution2D::new(&func, TABLE_WIDTH, TABLE_HEIGHT)
+    }
+
+    /// The unit direction at `(u, v)` in the table, with `v` running down from the zenith and
+    /// `u` around from +x.
+    fn uv_to_direction(u: f32, v: f32) -> Vec3 {
+        let (theta, phi) = (PI * v, 2.0 * PI * u);
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn direction_to_uv(direction: &Vec3) -> (f32, f32) {
+        (
+            (direction.z.atan2(direction.x) / (2.0 * PI)).rem_euclid(1.0),
+            direction.y.clamp(-1.0, 1.0).acos() / PI,
+        )
+    }
+}
+
+impl Light for PhysicalSky {
+    fn is_environment(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, _p: &Point3, rng: &mut dyn RngCore) -> Option<LightSample> {
+        let ((u, v), uv_pdf) = self.distribution.sample(rng.gen::<f32>(), rng.gen::<f32>());
+        let pdf = sampling::equirectangular_pdf(uv_pdf, v);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let wi = PhysicalSky::uv_to_direction(u, v);
+        Some(LightSample {
+            wi,
+            distance: f32::MAX,
+            radiance: self.infinite_radiance(&wi),
+            pdf,
+        })
+    }
+
+    fn is_area(&self) -> bool {
+        true
+    }
+
+    fn infinite_radiance(&self, direction: &Vec3) -> Color {
+        if direction.y > 0.0 {
+            self.sky_radiance(direction)
+        } else {
+            self.ground
+        }
+    }
+
+    fn infinite_pdf(&self, direction: &Vec3) -> f32 {
+        let (u, v) = PhysicalSky::direction_to_uv(direction);
+        sampling::equirectangular_pdf(self.distribution.pdf(u, v), v)
+    }
+
+    fn sample_emission(&self, rng: &mut dyn RngCore) -> Option<EmissionSample> {
+        let (center, _) = self.scene_sphere?;
+        let sample = self.sample(&center, rng)?;
+        light::infinite_emission(self.scene_sphere, &sample, rng)
+    }
+
+    fn emission_pdf(&self, _q: &Point3, direction: &Vec3) -> (f32, f32) {
+        light::infinite_emission_pdf(self.scene_sphere, self.infinite_pdf(&-*direction))
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.scene_sphere = Some(scene_bounds.bounding_sphere());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sky() -> PhysicalSky {
+        PhysicalSky::new(Vec3::new(-0.8, 0.45, -0.6), 3.0, Color::new(0.3, 0.3, 0.3))
+    }
+
+    #[test]
+    fn samples_agree_with_infinite_pdf() {
+        let sky = sky();
+        let mut rng = StdRng::seed_from_u64(3);
+        let p = Point3::new(0.0, 0.0, 0.0);
+        for _ in 0..1000 {
+            let sample = sky.sample(&p, &mut rng).unwrap();
+            assert!((sample.wi.len() - 1.0).abs() < 1e-4);
+            let pdf = sky.infinite_pdf(&sample.wi);
+            assert!(
+                (pdf - sample.pdf).abs() < 1e-3 * pdf,
+                "{} != {}",
+                pdf,
+                sample.pdf
+            );
+            let radiance = sky.infinite_radiance(&sample.wi);
+            assert!((sample.radiance - radiance).abs().max_component() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        const STEPS: usize = 512;
+        let sky = sky();
+        let d_omega = (2.0 / STEPS as f32) * (2.0 * PI / STEPS as f32);
+        let mut total = 0.0;
+        for i in 0..STEPS {
+            let y = -1.0 + (i as f32 + 0.5) * 2.0 / STEPS as f32;
+            let r = (1.0 - y * y).sqrt();
+            for j in 0..STEPS {
+                let phi = (j as f32 + 0.5) * 2.0 * PI / STEPS as f32;
+                let direction = Vec3::new(r * phi.cos(), y, r * phi.sin());
+                total += sky.infinite_pdf(&direction) * d_omega;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-2, "{}", total);
+        // The bright sky is favoured over the dim ground
+        assert!(
+            sky.infinite_pdf(&Vec3::new(-0.8, 0.5, -0.6).unit_vector())
+                > sky.infinite_pdf(&Vec3::new(0.0, -1.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn emission_crosses_the_scene() {
+        let mut sky = sky();
+        let mut rng = StdRng::seed_from_u64(5);
+        assert!(sky.sample_emission(&mut rng).is_none());
+
+        let bounds = Aabb::new(Point3::new(-2.0, 0.0, -1.0), Point3::new(4.0, 3.0, 1.0));
+        let (center, radius) = bounds.bounding_sphere();
+        sky.preprocess(&bounds);
+        for _ in 0..1000 {
+            let emission = sky.sample_emission(&mut rng).unwrap();
+            // Rays start on a disk just outside the sphere and head straight through it
+            let to_center = center - emission.origin;
+            let along = to_center.dot(&emission.direction);
+            assert!((along - radius).abs() < 1e-3 * radius);
+            assert!((to_center - along * emission.direction).len() <= radius * (1.0 + 1e-4));
+
+            let (pdf_position, pdf_direction) =
+                sky.emission_pdf(&emission.origin, &emission.direction);
+            assert!((pdf_position - 1.0 / (PI * radius * radius)).abs() < 1e-6);
+            assert!((pdf_position - emission.pdf_position).abs() < 1e-6);
+            assert!((pdf_direction - emission.pdf_direction).abs() < 1e-3 * pdf_direction);
+        }
+    }
+}
@@ -0,0 +1,493 @@
+use super::{Texture, UvDerivatives};
+use crate::bmp;
+use crate::vec::{Color, Point3};
+use std::path::Path;
+
+/// How surface coordinates outside `[0, 1]` map onto the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom interpolation of the surrounding 4x4 texels.
+    Bicubic,
+    /// Bilinear lookups in the two mipmap levels closest to the pixel footprint, blended
+    /// together.
+    Trilinear,
+    /// An elliptically weighted average over the pixel footprint, which stays sharp where
+    /// trilinear filtering blurs surfaces seen at grazing angles.
+    Ewa,
+}
+
+/// Footprints more elongated than this are widened, trading some blur for a bounded number of
+/// texel lookups.
+const MAX_ANISOTROPY: f32 = 8.0;
+
+/// Falloff of the Gaussian used by the EWA filter.
+const EWA_ALPHA: f32 = 2.0;
+
+/// An image mapped over the surface coordinates, with `(0, 0)` at the bottom left.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    levels: Vec<Level>,
+    filter: Filter,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    /// `pixels` is in linear colour, in row-major order starting from the top left.
+    pub fn new(
+        width: u32,
+        height: u32,
+        pixels: Vec<Color>,
+        filter: Filter,
+        wrap: Wrap,
+    ) -> ImageTexture {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        let mut levels = vec![Level {
+            width,
+            height,
+            texels: pixels,
+        }];
+        if filter == Filter::Trilinear || filter == Filter::Ewa {
+            while let Some(next) = levels.last().unwrap().downsample(wrap) {
+                levels.push(next);
+            }
+        }
+        ImageTexture {
+            levels,
+            filter,
+            wrap,
+        }
+    }
+
+    /// Builds a texture from 8-bit sRGB encoded pixels, as stored by most image formats,
+    /// converting them to linear colour.
+    pub fn from_srgb(
+        width: u32,
+        height: u32,
+        pixels: impl IntoIterator<Item = [u8; 3]>,
+        filter: Filter,
+        wrap: Wrap,
+    ) -> ImageTexture {
+        let mut to_linear = [0.0; 256];
+        for (i, value) in to_linear.iter_mut().enumerate() {
+            *value = srgb_to_linear(i as f32 / 255.0);
+        }
+        let pixels = pixels
+            .into_iter()
+            .map(|[r, g, b]| {
+                Color::new(
+                    to_linear[r as usize],
+                    to_linear[g as usize],
+                    to_linear[b as usize],
+                )
+            })
+            .collect();
+        ImageTexture::new(width, height, pixels, filter, wrap)
+    }
+
+    pub fn from_bmp(image: &bmp::Image, filter: Filter, wrap: Wrap) -> ImageTexture {
+        let pixels = image.coordinates().map(|(x, y)| {
+            let pixel = image.get_pixel(x, y);
+            [pixel.r, pixel.g, pixel.b]
+        });
+        ImageTexture::from_srgb(image.get_width(), image.get_height(), pixels, filter, wrap)
+    }
+
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        filter: Filter,
+        wrap: Wrap,
+    ) -> bmp::BmpResult<ImageTexture> {
+        Ok(ImageTexture::from_bmp(&bmp::open(path)?, filter, wrap))
+    }
+
+    fn trilinear(&self, u: f32, v: f32, derivatives: &UvDerivatives) -> Color {
+        let base = &self.levels[0];
+        let width = (derivatives.dudx * base.width as f32)
+            .hypot(derivatives.dvdx * base.height as f32)
+            .max(
+                (derivatives.dudy * base.width as f32).hypot(derivatives.dvdy * base.height as f32),
+            );
+        let lod = width.max(f32::MIN_POSITIVE).log2();
+        self.blend_levels(lod, |level| level.bilinear(u, v, self.wrap))
+    }
+
+    fn ewa(&self, u: f32, v: f32, derivatives: &UvDerivatives) -> Color {
+        let mut major = (derivatives.dudx, derivatives.dvdx);
+        let mut minor = (derivatives.dudy, derivatives.dvdy);
+        let length = |(du, dv): (f32, f32)| du.hypot(dv);
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let major_length = length(major);
+        let mut minor_length = length(minor);
+        if minor_length * MAX_ANISOTROPY < major_length && minor_length > 0.0 {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+        if minor_length == 0.0 {
+            return self.levels[0].bilinear(u, v, self.wrap);
+        }
+
+        // Pick the level where the minor axis covers about one texel so that the number of texels
+        // under the ellipse stays bounded by the anisotropy
+        let base = &self.levels[0];
+        let lod = (minor_length * base.width.max(base.height) as f32).log2();
+        self.blend_levels(lod, |level| level.ewa(u, v, major, minor, self.wrap))
+    }
+
+    fn blend_levels<F: Fn(&Level) -> Color>(&self, lod: f32, lookup: F) -> Color {
+        let last = self.levels.len() - 1;
+        if lod <= 0.0 {
+            lookup(&self.levels[0])
+        } else if lod >= last as f32 {
+            lookup(&self.levels[last])
+        } else {
+            let i = lod.floor() as usize;
+            let t = lod - i as f32;
+            (1.0 - t) * lookup(&self.levels[i]) + t * lookup(&self.levels[i + 1])
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.filtered(u, v, p, &UvDerivatives::default())
+    }
+
+    fn filtered(&self, u: f32, v: f32, _p: &Point3, derivatives: &UvDerivatives) -> Color {
+        match self.filter {
+            Filter::Nearest => self.levels[0].nearest(u, v, self.wrap),
+            Filter::Bilinear => self.levels[0].bilinear(u, v, self.wrap),
+            Filter::Bicubic => self.levels[0].bicubic(u, v, self.wrap),
+            Filter::Trilinear => self.trilinear(u, v, derivatives),
+            Filter::Ewa => self.ewa(u, v, derivatives),
+        }
+    }
+}
+
+/// One level of the mipmap pyramid.
+#[derive(Debug, Clone)]
+struct Level {
+    width: u32,
+    height: u32,
+    texels: Vec<Color>,
+}
+
+impl Level {
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Color {
+        let x = wrap_index(x, self.width as i64, wrap);
+        let y = wrap_index(y, self.height as i64, wrap);
+        self.texels[(y * self.width as i64 + x) as usize]
+    }
+
+    /// Converts surface coordinates to continuous texel coordinates, where texel centres lie on
+    /// integers and y runs down the image.
+    fn texel_coords(&self, u: f32, v: f32) -> (f32, f32) {
+        (
+            u * self.width as f32 - 0.5,
+            (1.0 - v) * self.height as f32 - 0.5,
+        )
+    }
+
+    fn nearest(&self, u: f32, v: f32, wrap: Wrap) -> Color {
+        let (s, t) = self.texel_coords(u, v);
+        self.texel(s.round() as i64, t.round() as i64, wrap)
+    }
+
+    fn bilinear(&self, u: f32, v: f32, wrap: Wrap) -> Color {
+        let (s, t) = self.texel_coords(u, v);
+        let (x, y) = (s.floor(), t.floor());
+        let (ds, dt) = (s - x, t - y);
+        let (x, y) = (x as i64, y as i64);
+        (1.0 - ds) * (1.0 - dt) * self.texel(x, y, wrap)
+            + ds * (1.0 - dt) * self.texel(x + 1, y, wrap)
+            + (1.0 - ds) * dt * self.texel(x, y + 1, wrap)
+            + ds * dt * self.texel(x + 1, y + 1, wrap)
+    }
+
+    fn bicubic(&self, u: f32, v: f32, wrap: Wrap) -> Color {
+        let (s, t) = self.texel_coords(u, v);
+        let (x, y) = (s.floor(), t.floor());
+        let wx = catmull_rom_weights(s - x);
+        let wy = catmull_rom_weights(t - y);
+        let (x, y) = (x as i64, y as i64);
+        let mut c = Color::new(0.0, 0.0, 0.0);
+        for (j, wy) in wy.iter().enumerate() {
+            for (i, wx) in wx.iter().enumerate() {
+                c += wx * wy * self.texel(x + i as i64 - 1, y + j as i64 - 1, wrap);
+            }
+        }
+        // The negative lobes of the filter can overshoot near hard edges
+        c.max(&Color::new(0.0, 0.0, 0.0))
+    }
+
+    /// Gaussian weighted average over the ellipse spanned by the two axes of the footprint.
+    fn ewa(&self, u: f32, v: f32, major: (f32, f32), minor: (f32, f32), wrap: Wrap) -> Color {
+        let (s, t) = self.texel_coords(u, v);
+        let (w, h) = (self.width as f32, self.height as f32);
+        // Texel rows run opposite to v
+        let (ds0, dt0) = (major.0 * w, -major.1 * h);
+        let (ds1, dt1) = (minor.0 * w, -minor.1 * h);
+
+        // Implicit ellipse A s^2 + B s t + C t^2 < 1, widened by a texel so that it never falls
+        // between texel centres
+        let mut a = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut b = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut c = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+        let s0 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let mut total_weight = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f32 - t;
+            for is in s0..=s1 {
+                let ss = is as f32 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-EWA_ALPHA * r2).exp() - (-EWA_ALPHA).exp();
+                    sum += weight * self.texel(is, it, wrap);
+                    total_weight += weight;
+                }
+            }
+        }
+        if total_weight > 0.0 {
+            sum / total_weight
+        } else {
+            self.bilinear(u, v, wrap)
+        }
+    }
+
+    /// Box filters the level down to half its size, or returns `None` once it is a single texel.
+    fn downsample(&self, wrap: Wrap) -> Option<Level> {
+        if self.width == 1 && self.height == 1 {
+            return None;
+        }
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                // Odd sizes fold the last row or column into its neighbour rather than wrapping
+                let x0 = (2 * x).min(self.width as i64 - 1);
+                let x1 = (2 * x + 1).min(self.width as i64 - 1);
+                let y0 = (2 * y).min(self.height as i64 - 1);
+                let y1 = (2 * y + 1).min(self.height as i64 - 1);
+                texels.push(
+                    0.25 * (self.texel(x0, y0, wrap)
+                        + self.texel(x1, y0, wrap)
+                        + self.texel(x0, y1, wrap)
+                        + self.texel(x1, y1, wrap)),
+                );
+            }
+        }
+        Some(Level {
+            width,
+            height,
+            texels,
+        })
+    }
+}
+
+fn wrap_index(i: i64, size: i64, wrap: Wrap) -> i64 {
+    match wrap {
+        Wrap::Repeat => i.rem_euclid(size),
+        Wrap::Clamp => i.clamp(0, size - 1),
+        Wrap::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            if i < size {
+                i
+            } else {
+                2 * size - 1 - i
+            }
+        }
+    }
+}
+
+fn catmull_rom_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image whose texels rise linearly across and down it.
+    fn ramp(width: u32, height: u32, filter: Filter, wrap: Wrap) -> ImageTexture {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Color::new(x as f32, y as f32, 0.0)))
+            .collect();
+        ImageTexture::new(width, height, pixels, filter, wrap)
+    }
+
+    /// One-texel black and white stripes running down the image.
+    fn stripes(width: u32, height: u32, filter: Filter) -> ImageTexture {
+        let pixels = (0..height)
+            .flat_map(|_| (0..width).map(|x| Color::new(1.0, 1.0, 1.0) * (x % 2) as f32))
+            .collect();
+        ImageTexture::new(width, height, pixels, filter, Wrap::Repeat)
+    }
+
+    fn origin() -> Point3 {
+        Point3::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn interpolation_reproduces_linear_ramps() {
+        // Both filters are exact for linear functions, away from the edges where the wrap mode
+        // takes over
+        let (width, height) = (16, 8);
+        for filter in [Filter::Bilinear, Filter::Bicubic] {
+            let texture = ramp(width, height, filter, Wrap::Clamp);
+            for i in 0..=40 {
+                for j in 0..=20 {
+                    let (s, t) = (2.0 + i as f32 * 0.275, 2.0 + j as f32 * 0.15);
+                    let u = (s + 0.5) / width as f32;
+                    let v = 1.0 - (t + 0.5) / height as f32;
+                    let c = texture.value(u, v, &origin());
+                    assert!(
+                        (c.x - s).abs() < 1e-3 && (c.y - t).abs() < 1e-3,
+                        "{:?}",
+                        filter
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_modes_fold_coordinates_back_onto_the_image() {
+        let (width, height) = (5, 3);
+        // The texel's column and row, which the ramp stores in its red and green
+        let lookup = |wrap, u: f32, v: f32| {
+            let c = ramp(width, height, Filter::Nearest, wrap).value(u, v, &origin());
+            (c.x, c.y)
+        };
+        for i in 0..width {
+            for j in 0..height {
+                let u = (i as f32 + 0.5) / width as f32;
+                let v = (j as f32 + 0.5) / height as f32;
+                let c = lookup(Wrap::Repeat, u, v);
+                assert_eq!(lookup(Wrap::Repeat, u + 2.0, v - 1.0), c);
+                assert_eq!(lookup(Wrap::Mirror, -u, 2.0 - v), c);
+                assert_eq!(lookup(Wrap::Mirror, 2.0 + u, v), c);
+                assert_eq!(lookup(Wrap::Clamp, u, v), c);
+            }
+        }
+        let corner = lookup(Wrap::Clamp, 0.99, 0.99);
+        assert_eq!(lookup(Wrap::Clamp, 3.5, 7.0), corner);
+        let corner = lookup(Wrap::Clamp, 0.01, 0.01);
+        assert_eq!(lookup(Wrap::Clamp, -3.5, -7.0), corner);
+    }
+
+    #[test]
+    fn mipmap_levels_keep_the_average() {
+        let texture = ramp(16, 8, Filter::Trilinear, Wrap::Repeat);
+        let mean = |level: &Level| {
+            level
+                .texels
+                .iter()
+                .fold(Color::new(0.0, 0.0, 0.0), |a, b| a + *b)
+                / level.texels.len() as f32
+        };
+        let expected = mean(&texture.levels[0]);
+        let sizes: Vec<_> = texture.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]);
+        for level in &texture.levels {
+            assert!((mean(level) - expected).abs().max_component() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn wide_footprints_average_the_texels_under_them() {
+        for filter in [Filter::Trilinear, Filter::Ewa] {
+            let texture = stripes(64, 64, filter);
+            let p = origin();
+            // A footprint of a tenth of a texel sees the stripe it's on
+            let fine = UvDerivatives {
+                dudx: 0.1 / 64.0,
+                dvdx: 0.0,
+                dudy: 0.0,
+                dvdy: 0.1 / 64.0,
+            };
+            assert!(texture.filtered(1.5 / 64.0, 0.5, &p, &fine).x > 0.99);
+            assert!(texture.filtered(2.5 / 64.0, 0.5, &p, &fine).x < 0.01);
+            // Over many stripes it sees their average, as does one stretched across them and
+            // squeezed along them, which EWA keeps sharp
+            let coarse = UvDerivatives {
+                dudx: 8.0 / 64.0,
+                dvdx: 0.0,
+                dudy: 0.0,
+                dvdy: 8.0 / 64.0,
+            };
+            let stretched = UvDerivatives {
+                dvdy: 1.0 / 64.0,
+                ..coarse
+            };
+            for u in [0.3, 0.51, 0.77] {
+                for derivatives in [coarse, stretched] {
+                    let c = texture.filtered(u, 0.5, &p, &derivatives);
+                    assert!((c.x - 0.5).abs() < 0.05, "{:?} at {}: {:?}", filter, u, c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_is_converted_to_linear() {
+        // Reference values from the sRGB transfer function
+        let texture = ImageTexture::from_srgb(
+            3,
+            1,
+            [[0, 0, 0], [128, 10, 255], [188, 188, 188]],
+            Filter::Nearest,
+            Wrap::Clamp,
+        );
+        let c = texture.value(0.5, 0.5, &origin());
+        assert!(
+            (c - Color::new(0.21586, 0.0030353, 1.0))
+                .abs()
+                .max_component()
+                < 1e-4
+        );
+        let c = texture.value(0.9, 0.5, &origin());
+        assert!((c.x - 0.50289).abs() < 1e-4);
+        assert_eq!(texture.value(0.1, 0.5, &origin()).x, 0.0);
+    }
+}
@@ -0,0 +1,329 @@
+mod image;
+mod noise;
+
+pub use self::image::{Filter, ImageTexture, Wrap};
+pub use self::noise::{
+    Feature, Fractal, Granite, Marble, NoiseMode, NoiseTexture, Perlin, Simplex, Wood, Worley,
+};
+
+use crate::vec::{Color, Point3, Vec3};
+use std::sync::Arc;
+
+/// A colour that varies over a surface, looked up by the surface coordinates `(u, v)` or the
+/// hit point `p`.
+pub trait Texture: core::fmt::Debug + Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+
+    /// Looks up the texture averaged over the footprint of a pixel, described by how fast the
+    /// surface coordinates change across it. Textures that don't need antialiasing can ignore the
+    /// footprint.
+    fn filtered(&self, u: f32, v: f32, p: &Point3, _derivatives: &UvDerivatives) -> Color {
+        self.value(u, v, p)
+    }
+}
+
+/// Partial derivatives of the surface coordinates with respect to the image x and y directions.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct UvDerivatives {
+    pub dudx: f32,
+    pub dvdx: f32,
+    pub dudy: f32,
+    pub dvdy: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+
+    pub fn gray(value: f32) -> SolidColor {
+        SolidColor::new(Color::new(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// A 3D checkerboard of cubes with sides of length `scale`, so it can be applied to any shape
+/// without needing surface coordinates.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> Checker {
+        Checker { even, odd, scale }
+    }
+}
+
+impl Checker {
+    fn select(&self, p: &Point3) -> &dyn Texture {
+        let cell = (p.x / self.scale).floor() as i64
+            + (p.y / self.scale).floor() as i64
+            + (p.z / self.scale).floor() as i64;
+        if cell % 2 == 0 {
+            &*self.even
+        } else {
+            &*self.odd
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.select(p).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Point3, derivatives: &UvDerivatives) -> Color {
+        self.select(p).filtered(u, v, p, derivatives)
+    }
+}
+
+/// A checkerboard in surface coordinates with `columns` squares along `u` and `rows` along `v`.
+#[derive(Debug, Clone)]
+pub struct UvChecker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    columns: f32,
+    rows: f32,
+}
+
+impl UvChecker {
+    pub fn new(
+        even: Arc<dyn Texture>,
+        odd: Arc<dyn Texture>,
+        columns: f32,
+        rows: f32,
+    ) -> UvChecker {
+        UvChecker {
+            even,
+            odd,
+            columns,
+            rows,
+        }
+    }
+}
+
+impl UvChecker {
+    fn select(&self, u: f32, v: f32) -> &dyn Texture {
+        let cell = (u * self.columns).floor() as i64 + (v * self.rows).floor() as i64;
+        if cell % 2 == 0 {
+            &*self.even
+        } else {
+            &*self.odd
+        }
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.select(u, v).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Point3, derivatives: &UvDerivatives) -> Color {
+        self.select(u, v).filtered(u, v, p, derivatives)
+    }
+}
+
+/// Blends linearly from `start` at `origin` to `end` at `origin + direction`, clamping beyond
+/// either end.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    start: Arc<dyn Texture>,
+    end: Arc<dyn Texture>,
+    origin: Point3,
+    direction: Vec3,
+}
+
+impl Gradient {
+    pub fn new(
+        start: Arc<dyn Texture>,
+        end: Arc<dyn Texture>,
+        origin: Point3,
+        direction: Vec3,
+    ) -> Gradient {
+        Gradient {
+            start,
+            end,
+            origin,
+            direction,
+        }
+    }
+}
+
+impl Gradient {
+    fn blend(&self, p: &Point3) -> f32 {
+        ((*p - self.origin).dot(&self.direction) / self.direction.square_len()).clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for Gradient {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        let t = self.blend(p);
+        (1.0 - t) * self.start.value(u, v, p) + t * self.end.value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Point3, derivatives: &UvDerivatives) -> Color {
+        let t = self.blend(p);
+        (1.0 - t) * self.start.filtered(u, v, p, derivatives)
+            + t * self.end.filtered(u, v, p, derivatives)
+    }
+}
+
+/// Scales and offsets the surface coordinates before looking up `inner`, to tile a texture or
+/// place it as a decal.
+#[derive(Debug, Clone)]
+pub struct UvTransform {
+    inner: Arc<dyn Texture>,
+    scale: (f32, f32),
+    offset: (f32, f32),
+}
+
+impl UvTransform {
+    pub fn new(inner: Arc<dyn Texture>, scale: (f32, f32), offset: (f32, f32)) -> UvTransform {
+        UvTransform {
+            inner,
+            scale,
+            offset,
+        }
+    }
+
+    fn transform(&self, u: f32, v: f32) -> (f32, f32) {
+        (
+            u * self.scale.0 + self.offset.0,
+            v * self.scale.1 + self.offset.1,
+        )
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        let (u, v) = self.transform(u, v);
+        self.inner.value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Point3, derivatives: &UvDerivatives) -> Color {
+        let (u, v) = self.transform(u, v);
+        let derivatives = UvDerivatives {
+            dudx: derivatives.dudx * self.scale.0,
+            dvdx: derivatives.dvdx * self.scale.1,
+            dudy: derivatives.dudy * self.scale.0,
+            dvdy: derivatives.dvdy * self.scale.1,
+        };
+        self.inner.filtered(u, v, p, &derivatives)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn black() -> Arc<dyn Texture> {
+        Arc::new(SolidColor::gray(0.0))
+    }
+
+    fn white() -> Arc<dyn Texture> {
+        Arc::new(SolidColor::gray(1.0))
+    }
+
+    #[test]
+    fn checkers_alternate_between_cells() {
+        let mut rng = StdRng::seed_from_u64(28);
+        let solid = Checker::new(black(), white(), 0.5);
+        let uv = UvChecker::new(black(), white(), 4.0, 3.0);
+        let mut odd = 0;
+        const SAMPLES: usize = 20_000;
+        for _ in 0..SAMPLES {
+            let p = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            let cell = [p.x, p.y, p.z]
+                .iter()
+                .map(|c| (c / 0.5).floor() as i64)
+                .sum::<i64>();
+            assert_eq!(solid.value(0.0, 0.0, &p).x, cell.rem_euclid(2) as f32);
+            odd += cell.rem_euclid(2);
+
+            let (u, v) = (rng.gen::<f32>(), rng.gen::<f32>());
+            let cell = (u * 4.0) as i64 + (v * 3.0) as i64;
+            assert_eq!(uv.value(u, v, &p).x, (cell % 2) as f32);
+        }
+        // Half the space lies in odd cells
+        assert!((odd as f32 / SAMPLES as f32 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn gradient_blends_along_its_direction() {
+        let origin = Point3::new(1.0, 2.0, 3.0);
+        let direction = Vec3::new(0.0, 4.0, 0.0);
+        let gradient = Gradient::new(black(), white(), origin, direction);
+        for i in -4..=12 {
+            let t = i as f32 / 8.0;
+            // Moving across the direction leaves the blend alone
+            let p = origin + t * direction + Vec3::new(5.0, 0.0, -2.0);
+            let value = gradient.value(0.0, 0.0, &p);
+            assert!((value.x - t.clamp(0.0, 1.0)).abs() < 1e-6, "at {}", t);
+        }
+    }
+
+    #[test]
+    fn uv_transform_scales_the_lookup_and_its_footprint() {
+        /// Shows the coordinates and footprint it's looked up with.
+        #[derive(Debug)]
+        struct Footprint;
+        impl Texture for Footprint {
+            fn value(&self, u: f32, v: f32, _p: &Point3) -> Color {
+                Color::new(u, v, 0.0)
+            }
+
+            fn filtered(&self, _u: f32, _v: f32, _p: &Point3, uv: &UvDerivatives) -> Color {
+                Color::new(uv.dudx, uv.dvdy, uv.dudy + uv.dvdx)
+            }
+        }
+
+        let p = Point3::new(0.0, 0.0, 0.0);
+        let tiled = UvTransform::new(
+            Arc::new(UvChecker::new(black(), white(), 1.0, 1.0)),
+            (4.0, 3.0),
+            (0.0, 0.0),
+        );
+        let direct = UvChecker::new(black(), white(), 4.0, 3.0);
+        let mut rng = StdRng::seed_from_u64(29);
+        for _ in 0..1000 {
+            let (u, v) = (rng.gen::<f32>(), rng.gen::<f32>());
+            assert_eq!(tiled.value(u, v, &p).x, direct.value(u, v, &p).x);
+        }
+
+        let moved = UvTransform::new(Arc::new(Footprint), (2.0, 0.5), (0.25, -1.0));
+        let value = moved.value(0.5, 0.5, &p);
+        assert!((value.x - 1.25).abs() < 1e-6 && (value.y + 0.75).abs() < 1e-6);
+        let derivatives = UvDerivatives {
+            dudx: 0.1,
+            dvdx: 0.2,
+            dudy: 0.3,
+            dvdy: 0.4,
+        };
+        let footprint = moved.filtered(0.5, 0.5, &p, &derivatives);
+        assert!(
+            (footprint - Color::new(0.2, 0.2, 0.7))
+                .abs()
+                .max_component()
+                < 1e-6
+        );
+    }
+}
@@ -0,0 +1,537 @@
+use super::Texture;
+use crate::vec::{Color, Point3, Vec3};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::sync::Arc;
+
+const TABLE_SIZE: usize = 256;
+
+/// A smooth pseudo-random function of space, returning values in roughly `[-1, 1]`.
+///
+/// Implementations draw their tables from the RNG they are built with, so a seeded RNG gives the
+/// same pattern on every render.
+pub trait Noise: core::fmt::Debug + Send + Sync {
+    fn noise(&self, p: &Point3) -> f32;
+}
+
+/// Gradient noise with a random unit gradient at each lattice point.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm: Permutation,
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Perlin {
+        let gradients = (0..TABLE_SIZE).map(|_| random_unit_vector(rng)).collect();
+        Perlin {
+            gradients,
+            perm: Permutation::new(rng),
+        }
+    }
+}
+
+impl Noise for Perlin {
+    fn noise(&self, p: &Point3) -> f32 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm.hash(i + di, j + dj, k + dk)];
+                    let offset = Vec3::new(u - di as f32, v - dj as f32, w - dk as f32);
+                    let weight = fade_weight(u, di) * fade_weight(v, dj) * fade_weight(w, dk);
+                    accum += weight * gradient.dot(&offset);
+                }
+            }
+        }
+        // The largest possible value is sqrt(3)/2 with unit gradients
+        accum * 2.0 / 3f32.sqrt()
+    }
+}
+
+/// Simplex noise, which interpolates over tetrahedra instead of cubes so it needs four gradient
+/// lookups per sample instead of eight and has no axis-aligned artifacts.
+#[derive(Debug, Clone)]
+pub struct Simplex {
+    perm: Permutation,
+}
+
+impl Simplex {
+    pub fn new<R: Rng>(rng: &mut R) -> Simplex {
+        Simplex {
+            perm: Permutation::new(rng),
+        }
+    }
+}
+
+impl Noise for Simplex {
+    fn noise(&self, p: &Point3) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+        const GRADIENTS: [(f32, f32, f32); 12] = [
+            (1.0, 1.0, 0.0),
+            (-1.0, 1.0, 0.0),
+            (1.0, -1.0, 0.0),
+            (-1.0, -1.0, 0.0),
+            (1.0, 0.0, 1.0),
+            (-1.0, 0.0, 1.0),
+            (1.0, 0.0, -1.0),
+            (-1.0, 0.0, -1.0),
+            (0.0, 1.0, 1.0),
+            (0.0, -1.0, 1.0),
+            (0.0, 1.0, -1.0),
+            (0.0, -1.0, -1.0),
+        ];
+
+        // Skew into the lattice of cubes, each of which is split into six tetrahedra
+        let s = (p.x + p.y + p.z) * F3;
+        let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+        let t = (i + j + k) * G3;
+        let x0 = Vec3::new(p.x - (i - t), p.y - (j - t), p.z - (k - t));
+
+        // Walk from the cube's origin to its far corner along the axes in decreasing order of the
+        // offset, which visits the corners of the tetrahedron containing the point
+        let (o1, o2) = if x0.x >= x0.y {
+            if x0.y >= x0.z {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0.x >= x0.z {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if x0.y < x0.z {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0.x < x0.z {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let corners = [(0, 0, 0), o1, o2, (1, 1, 1)];
+        let mut accum = 0.0;
+        for (n, &(ci, cj, ck)) in corners.iter().enumerate() {
+            let offset =
+                x0 - Vec3::new(ci as f32, cj as f32, ck as f32) + n as f32 * Vec3::new(G3, G3, G3);
+            let falloff = 0.6 - offset.square_len();
+            if falloff > 0.0 {
+                let (gx, gy, gz) = GRADIENTS[self.perm.hash(i + ci, j + cj, k + ck) % 12];
+                accum += falloff.powi(4) * Vec3::new(gx, gy, gz).dot(&offset);
+            }
+        }
+        // Scales the result to roughly [-1, 1]
+        32.0 * accum
+    }
+}
+
+/// Which distances to the nearest feature points make up cellular noise.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Feature {
+    /// Distance to the nearest point, giving rounded cells.
+    F1,
+    /// Difference between the two, which is zero along cell borders and gives a cracked look.
+    F2MinusF1,
+}
+
+/// Worley (cellular) noise, built from the distances to a random point in each unit cell.
+#[derive(Debug, Clone)]
+pub struct Worley {
+    points: Vec<Vec3>,
+    perm: Permutation,
+    feature: Feature,
+}
+
+impl Worley {
+    pub fn new<R: Rng>(rng: &mut R, feature: Feature) -> Worley {
+        let points = (0..TABLE_SIZE)
+            .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()))
+            .collect();
+        Worley {
+            points,
+            perm: Permutation::new(rng),
+            feature,
+        }
+    }
+
+    /// Distances to the nearest and second nearest feature points.
+    fn distances(&self, p: &Point3) -> (f32, f32) {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let cell = Vec3::new(i, j, k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let mut f1 = f32::INFINITY;
+        let mut f2 = f32::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let point = cell
+                        + Vec3::new(di as f32, dj as f32, dk as f32)
+                        + self.points[self.perm.hash(i + di, j + dj, k + dk)];
+                    let d = (point - *p).square_len();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1.sqrt(), f2.sqrt())
+    }
+
+    /// The raw feature distance, which is mostly within `[0, 1]`.
+    pub fn feature(&self, p: &Point3) -> f32 {
+        let (f1, f2) = self.distances(p);
+        match self.feature {
+            Feature::F1 => f1,
+            Feature::F2MinusF1 => f2 - f1,
+        }
+    }
+}
+
+impl Noise for Worley {
+    fn noise(&self, p: &Point3) -> f32 {
+        2.0 * self.feature(p).min(1.0) - 1.0
+    }
+}
+
+/// Sums octaves of noise at increasing frequency and decreasing amplitude.
+#[derive(Debug, Copy, Clone)]
+pub struct Fractal {
+    pub octaves: u32,
+    /// Frequency multiplier between octaves.
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves.
+    pub gain: f32,
+}
+
+impl Fractal {
+    pub fn new(octaves: u32) -> Fractal {
+        Fractal {
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    /// Fractal Brownian motion, normalised back to roughly `[-1, 1]`.
+    pub fn fbm(&self, noise: &dyn Noise, p: &Point3) -> f32 {
+        self.sum(p, |q| noise.noise(q))
+    }
+
+    /// Like `fbm` but summing the absolute value of each octave, which folds the noise into
+    /// sharp creases. The result is in roughly `[0, 1]`.
+    pub fn turbulence(&self, noise: &dyn Noise, p: &Point3) -> f32 {
+        self.sum(p, |q| noise.noise(q).abs())
+    }
+
+    fn sum<F: Fn(&Point3) -> f32>(&self, p: &Point3, octave: F) -> f32 {
+        let mut accum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut q = *p;
+        for _ in 0..self.octaves {
+            accum += amplitude * octave(&q);
+            total += amplitude;
+            amplitude *= self.gain;
+            q *= self.lacunarity;
+        }
+        if total > 0.0 {
+            accum / total
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseMode {
+    Fbm,
+    Turbulence,
+}
+
+/// Blends between two textures by fractal noise.
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    noise: Arc<dyn Noise>,
+    fractal: Fractal,
+    mode: NoiseMode,
+    scale: f32,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        noise: Arc<dyn Noise>,
+        fractal: Fractal,
+        mode: NoiseMode,
+        scale: f32,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> NoiseTexture {
+        NoiseTexture {
+            noise,
+            fractal,
+            mode,
+            scale,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let t = match self.mode {
+            NoiseMode::Fbm => 0.5 + 0.5 * self.fractal.fbm(&*self.noise, &q),
+            NoiseMode::Turbulence => self.fractal.turbulence(&*self.noise, &q),
+        }
+        .clamp(0.0, 1.0);
+        (1.0 - t) * self.low.value(u, v, p) + t * self.high.value(u, v, p)
+    }
+}
+
+/// Bands along the x axis distorted by turbulence.
+#[derive(Debug, Clone)]
+pub struct Marble {
+    noise: Arc<dyn Noise>,
+    scale: f32,
+    base: Color,
+    vein: Color,
+}
+
+impl Marble {
+    pub fn new(noise: Arc<dyn Noise>, scale: f32, base: Color, vein: Color) -> Marble {
+        Marble {
+            noise,
+            scale,
+            base,
+            vein,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let turbulence = Fractal::new(7).turbulence(&*self.noise, &q);
+        let t = 0.5 * (1.0 + (q.x + 10.0 * turbulence).sin());
+        // Sharpen the bands so the veins are thin
+        let t = t.powi(3);
+        (1.0 - t) * self.base + t * self.vein
+    }
+}
+
+/// Concentric growth rings around the y axis, wobbled by noise.
+#[derive(Debug, Clone)]
+pub struct Wood {
+    noise: Arc<dyn Noise>,
+    ring_spacing: f32,
+    light: Color,
+    dark: Color,
+}
+
+impl Wood {
+    pub fn new(noise: Arc<dyn Noise>, ring_spacing: f32, light: Color, dark: Color) -> Wood {
+        Wood {
+            noise,
+            ring_spacing,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let q = *p / self.ring_spacing;
+        let grain = Fractal::new(3).fbm(&*self.noise, &Point3::new(q.x, 0.1 * q.y, q.z));
+        let radius = (q.x * q.x + q.z * q.z).sqrt() + 0.5 * grain;
+        let ring = radius - radius.floor();
+        // Latewood forms a thin dark band at the end of each ring
+        let t = ((ring - 0.7) / 0.3).clamp(0.0, 1.0);
+        (1.0 - t) * self.light + t * self.dark
+    }
+}
+
+/// Crystals of three colours separated by dark grain boundaries.
+#[derive(Debug, Clone)]
+pub struct Granite {
+    cells: Worley,
+    speckle: Arc<dyn Noise>,
+    scale: f32,
+    colors: [Color; 3],
+}
+
+impl Granite {
+    pub fn new<R: Rng>(rng: &mut R, scale: f32, colors: [Color; 3]) -> Granite {
+        Granite {
+            cells: Worley::new(rng, Feature::F2MinusF1),
+            speckle: Arc::new(Simplex::new(rng)),
+            scale,
+            colors,
+        }
+    }
+}
+
+impl Texture for Granite {
+    fn value(&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let speckle = 0.5 + 0.5 * Fractal::new(4).fbm(&*self.speckle, &(3.0 * q));
+        let color = if speckle < 0.4 {
+            self.colors[0]
+        } else if speckle < 0.6 {
+            self.colors[1]
+        } else {
+            self.colors[2]
+        };
+        let border = (self.cells.feature(&q) / 0.1).min(1.0);
+        (0.4 + 0.6 * border) * color
+    }
+}
+
+/// A shuffled table used to hash lattice coordinates.
+#[derive(Debug, Clone)]
+struct Permutation {
+    x: Vec<usize>,
+    y: Vec<usize>,
+    z: Vec<usize>,
+}
+
+impl Permutation {
+    fn new<R: Rng>(rng: &mut R) -> Permutation {
+        let mut shuffled = || {
+            let mut table: Vec<usize> = (0..TABLE_SIZE).collect();
+            table.shuffle(rng);
+            table
+        };
+        Permutation {
+            x: shuffled(),
+            y: shuffled(),
+            z: shuffled(),
+        }
+    }
+
+    fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let mask = TABLE_SIZE as i64 - 1;
+        self.x[(i & mask) as usize] ^ self.y[(j & mask) as usize] ^ self.z[(k & mask) as usize]
+    }
+}
+
+/// Weight of the lattice point at offset `d` (0 or 1) along one axis, smoothed with the quintic
+/// fade curve so that the noise has continuous second derivatives.
+fn fade_weight(t: f32, d: i64) -> f32 {
+    let s = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    if d == 0 {
+        1.0 - s
+    } else {
+        s
+    }
+}
+
+fn random_unit_vector<R: Rng>(rng: &mut R) -> Vec3 {
+    loop {
+        let p = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let len = p.square_len();
+        if len > 1e-6 && len < 1.0 {
+            return p / len.sqrt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Points scattered over a few lattice cells, including negative coordinates.
+    fn points() -> Vec<Point3> {
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..2000)
+            .map(|_| {
+                Point3::new(
+                    rng.gen_range(-20.0..20.0),
+                    rng.gen_range(-20.0..20.0),
+                    rng.gen_range(-20.0..20.0),
+                )
+            })
+            .collect()
+    }
+
+    fn noises(seed: u64) -> Vec<Box<dyn Noise>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        vec![
+            Box::new(Perlin::new(&mut rng)),
+            Box::new(Simplex::new(&mut rng)),
+            Box::new(Worley::new(&mut rng, Feature::F1)),
+            Box::new(Worley::new(&mut rng, Feature::F2MinusF1)),
+        ]
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        for noise in noises(7) {
+            let values: Vec<f32> = points().iter().map(|p| noise.noise(p)).collect();
+            for &value in &values {
+                // Simplex is only scaled to roughly [-1, 1]
+                assert!(value.abs() <= 1.05, "{:?} gave {}", noise, value);
+            }
+            // It also isn't stuck at one value
+            let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            assert!(max - min > 0.5, "{:?} spans only {}..{}", noise, min, max);
+        }
+    }
+
+    #[test]
+    fn lattice_noise_vanishes_at_lattice_points() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let perlin = Perlin::new(&mut rng);
+        for p in points() {
+            let corner = Point3::new(p.x.floor(), p.y.floor(), p.z.floor());
+            assert!(perlin.noise(&corner).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_pattern() {
+        let first = noises(42);
+        let second = noises(42);
+        let other = noises(43);
+        for ((a, b), c) in first.iter().zip(&second).zip(&other) {
+            let mut differs = false;
+            for p in points() {
+                assert_eq!(a.noise(&p), b.noise(&p));
+                differs |= a.noise(&p) != c.noise(&p);
+            }
+            assert!(differs, "{:?} ignores its seed", a);
+        }
+    }
+
+    #[test]
+    fn fractal_sums_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let perlin = Perlin::new(&mut rng);
+        let fractal = Fractal::new(5);
+        for p in points() {
+            assert!(fractal.fbm(&perlin, &p).abs() <= 1.0);
+            let turbulence = fractal.turbulence(&perlin, &p);
+            assert!((0.0..=1.0).contains(&turbulence));
+        }
+        assert_eq!(
+            Fractal::new(0).fbm(&perlin, &Point3::new(0.3, 0.2, 0.1)),
+            0.0
+        );
+    }
+}
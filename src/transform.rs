@@ -0,0 +1,140 @@
+use crate::vec::{Point3, Vec3};
+
+/// A unit quaternion representing a rotation.
+#[derive(Debug, Copy, Clone)]
+pub struct Rotation {
+    w: f32,
+    v: Vec3,
+}
+
+impl Rotation {
+    pub fn identity() -> Rotation {
+        Rotation {
+            w: 1.0,
+            v: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Rotation counter-clockwise around `axis` by `degrees`.
+    pub fn new(axis: Vec3, degrees: f32) -> Rotation {
+        let half_angle = 0.5 * degrees * std::f32::consts::PI / 180.0;
+        Rotation {
+            w: half_angle.cos(),
+            v: half_angle.sin() * axis.unit_vector(),
+        }
+    }
+
+    pub fn inverse(&self) -> Rotation {
+        Rotation {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    /// The rotation equivalent to applying `self` and then `other`.
+    pub fn then(&self, other: &Rotation) -> Rotation {
+        Rotation {
+            w: other.w * self.w - other.v.dot(&self.v),
+            v: other.w * self.v + self.w * other.v + other.v.cross(&self.v),
+        }
+    }
+
+    pub fn rotate(&self, p: &Vec3) -> Vec3 {
+        let t = 2.0 * self.v.cross(p);
+        *p + self.w * t + self.v.cross(&t)
+    }
+
+    /// Spherical linear interpolation, taking the shorter way around.
+    pub fn slerp(&self, other: &Rotation, t: f32) -> Rotation {
+        let mut cos_theta = self.w * other.w + self.v.dot(&other.v);
+        let (mut w1, mut v1) = (other.w, other.v);
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            w1 = -w1;
+            v1 = -v1;
+        }
+        let (s0, s1) = if cos_theta > 0.9995 {
+            // Nearly parallel; fall back to linear interpolation to avoid dividing by sin ~ 0
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+        let w = s0 * self.w + s1 * w1;
+        let v = s0 * self.v + s1 * v1;
+        let len = (w * w + v.square_len()).sqrt();
+        Rotation {
+            w: w / len,
+            v: v / len,
+        }
+    }
+}
+
+/// Scale, then rotate, then translate.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Rotation,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Rotation, scale: Vec3) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Rotation::identity(),
+            Vec3::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    pub fn translate(translation: Vec3) -> Transform {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        self.rotation.rotate(&(*p * self.scale)) + self.translation
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.rotation.rotate(&(*v * self.scale))
+    }
+
+    /// Normals transform by the inverse transpose, so they are divided by the scale rather than
+    /// multiplied. The result is not normalised.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.rotation.rotate(&(*n / self.scale))
+    }
+
+    pub fn inverse_point(&self, p: &Point3) -> Point3 {
+        self.rotation.inverse().rotate(&(*p - self.translation)) / self.scale
+    }
+
+    pub fn inverse_vector(&self, v: &Vec3) -> Vec3 {
+        self.rotation.inverse().rotate(v) / self.scale
+    }
+
+    /// Interpolates each component separately so that rotations sweep along an arc instead of
+    /// shearing the object.
+    pub fn interpolate(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            translation: (1.0 - t) * self.translation + t * other.translation,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: (1.0 - t) * self.scale + t * other.scale,
+        }
+    }
+}